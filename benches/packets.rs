@@ -0,0 +1,94 @@
+//! prime rays of the teapot traced one by one and in packets of 2×2 pixels, the way
+//! `RenderOptions::packets` traces them. run with `cargo bench --bench packets`
+extern crate cgmath;
+extern crate raytracer;
+extern crate wavefront_obj;
+
+use std::time::{Duration, Instant};
+
+use cgmath::{One, Quaternion};
+use wavefront_obj::obj;
+
+use raytracer::objects::{Mesh, ObjectBuilder};
+use raytracer::raycast::Ray;
+use raytracer::scene::{Camera, Culling, Scene, SceneBuilder};
+use raytracer::types::Point;
+
+const ROUNDS: usize = 5;
+
+fn teapot() -> Scene {
+    let object = obj::parse(String::from(include_str!("../teapot.obj")))
+        .unwrap()
+        .objects
+        .into_iter()
+        .find(|o| !o.vertices.is_empty())
+        .unwrap();
+    SceneBuilder::new()
+        .add_object(
+            ObjectBuilder::create_for(Mesh::create(object))
+                .at_position(Point::new(0.0, -1.0, -4.0))
+                .into(),
+        )
+        .finish()
+}
+
+/// the fastest of a few rounds, in nanoseconds per ray
+fn per_ray<F: FnMut() -> usize>(mut round: F) -> f64 {
+    let mut fastest = Duration::from_secs(u64::MAX);
+    let mut rays = 0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        rays = round();
+        fastest = fastest.min(start.elapsed());
+    }
+    fastest.as_secs_f64() * 1e9 / rays as f64
+}
+
+fn main() {
+    let scene = teapot();
+    let camera = Camera {
+        width: 256,
+        height: 256,
+        fov: 90.0,
+        position: Point::new(0.0, 0.0, 0.0),
+        rotation: Quaternion::one(),
+        lens: None,
+    };
+    let culling = Culling::none();
+    let blocks: Vec<Vec<Ray>> = (0..camera.height / 2)
+        .flat_map(|by| (0..camera.width / 2).map(move |bx| (bx * 2, by * 2)))
+        .map(|(x, y)| {
+            [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|&(dx, dy)| {
+                    Ray::create_prime((x + dx) as f64, (y + dy) as f64, &scene, &camera)
+                })
+                .collect()
+        })
+        .collect();
+
+    let scalar = per_ray(|| {
+        let mut hits = 0;
+        for ray in blocks.iter().flatten() {
+            hits += scene.trace_culled(ray, &culling).is_some() as usize;
+        }
+        assert!(hits > 0);
+        blocks.len() * 4
+    });
+    let packets = per_ray(|| {
+        let mut hits = 0;
+        for block in &blocks {
+            hits += scene
+                .trace_packet(block, &culling)
+                .iter()
+                .filter(|hit| hit.is_some())
+                .count();
+        }
+        assert!(hits > 0);
+        blocks.len() * 4
+    });
+
+    println!("scalar:  {:8.1} ns per ray", scalar);
+    println!("packets: {:8.1} ns per ray", packets);
+    println!("packets take {:.2} of the scalar time", packets / scalar);
+}
@@ -0,0 +1,161 @@
+use cgmath::{One, Quaternion};
+use render::RenderOptions;
+use scene::Camera;
+use types::Point;
+
+pub const USAGE: &str = "usage: raytracer [--scene scene.ron | --model model.obj] \
+                         [--out image.png] [--width N] [--height N] [--samples N] \
+                         [--threads N] [rotation]";
+
+/// what the binary renders and where the result goes
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    /// a scene description file rendered instead of the built-in scene, see `scene_file`
+    pub scene: Option<String>,
+    /// an obj model rendered instead of the bundled teapot
+    pub model: Option<String>,
+    pub out: String,
+    pub width: u32,
+    pub height: u32,
+    pub samples: Option<usize>,
+    pub threads: Option<usize>,
+    /// rotation of the model around the y axis, in degrees. only used by the built-in scene
+    pub rotation: f64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            scene: None,
+            model: None,
+            out: String::from("test.png"),
+            width: 1000,
+            height: 1000,
+            samples: None,
+            threads: None,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Config {
+    pub fn camera(&self) -> Camera {
+        Camera {
+            width: self.width,
+            height: self.height,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        }
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        let defaults = RenderOptions::default();
+        RenderOptions {
+            samples: self.samples.unwrap_or(defaults.samples),
+            threads: self.threads,
+            ..defaults
+        }
+    }
+}
+
+fn number<T: ::std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+/// parses the arguments following the program name
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => {
+                config.scene = Some(args.next().ok_or("--scene needs a value")?);
+            }
+            "--model" => {
+                config.model = Some(args.next().ok_or("--model needs a value")?);
+            }
+            "--out" => config.out = args.next().ok_or("--out needs a value")?,
+            "--width" => config.width = number(&arg, args.next())?,
+            "--height" => config.height = number(&arg, args.next())?,
+            "--samples" => config.samples = Some(number(&arg, args.next())?),
+            "--threads" => config.threads = Some(number(&arg, args.next())?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => config.rotation = number("rotation", Some(arg))?,
+        }
+    }
+
+    if config.scene.is_some() && config.model.is_some() {
+        return Err(String::from(
+            "--model only replaces the teapot of the built-in scene",
+        ));
+    }
+    if config.width == 0 || config.height == 0 {
+        return Err(String::from("width and height have to be positive"));
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use cli::{parse_args, Config};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_all_options() {
+        let config = parse_args(args(
+            "--model cube.obj --out cube.png --width 640 --height 480 --samples 1 --threads 2 30",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                scene: None,
+                model: Some(String::from("cube.obj")),
+                out: String::from("cube.png"),
+                width: 640,
+                height: 480,
+                samples: Some(1),
+                threads: Some(2),
+                rotation: 30.0,
+            }
+        );
+        assert_eq!(config.camera().width, 640);
+        assert_eq!(config.render_options().samples, 1);
+        assert_eq!(config.render_options().threads, Some(2));
+    }
+
+    #[test]
+    fn test_parse_scene_file() {
+        let config = parse_args(args("--scene scene.ron --width 320")).unwrap();
+
+        assert_eq!(config.scene, Some(String::from("scene.ron")));
+        assert_eq!(config.model, None);
+        assert_eq!(config.camera().width, 320);
+    }
+
+    #[test]
+    fn test_no_arguments_give_the_defaults() {
+        assert_eq!(parse_args(vec![]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_rejected() {
+        assert!(parse_args(args("--width")).is_err());
+        assert!(parse_args(args("--width wide")).is_err());
+        assert!(parse_args(args("--height 0")).is_err());
+        assert!(parse_args(args("--fast")).is_err());
+        assert!(parse_args(args("--scene")).is_err());
+        assert!(parse_args(args("--scene scene.ron --model cube.obj")).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! conversions between linear light, which all shading works in, and the srgb encoding of 8
+//! bit images and of colors picked on a screen. colors are decoded when they enter the
+//! renderer, from textures or hex codes, and encoded once when a pixel is written out
+use types::Color;
+
+/// the linear value of an srgb encoded one, both in [0, 1]
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// the srgb encoded value of a linear one, both in [0, 1]
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// applies `convert` to the straight color channels of a premultiplied color, alpha is
+/// never encoded
+fn convert_channels<F: Fn(f32) -> f32>(color: Color, convert: F) -> Color {
+    if color.alpha <= 0.0 {
+        return color;
+    }
+    let channel = |value: f32| convert(value / color.alpha) * color.alpha;
+    Color {
+        red: channel(color.red),
+        green: channel(color.green),
+        blue: channel(color.blue),
+        alpha: color.alpha,
+    }
+}
+
+/// the linear color of one whose channels are srgb encoded
+pub fn color_srgb_to_linear(color: Color) -> Color {
+    convert_channels(color, srgb_to_linear)
+}
+
+/// the srgb encoded color of a linear one, channels outside [0, 1] are clamped first
+pub fn color_linear_to_srgb(color: Color) -> Color {
+    convert_channels(color, |c| linear_to_srgb(c.clamp(0.0, 1.0)))
+}
+
+/// the linear color of a `#rrggbb` or `#rrggbbaa` hex code, the `#` is optional
+pub fn parse_hex(hex: &str) -> Result<Color, String> {
+    let digits = hex.trim().trim_start_matches('#');
+    if (digits.len() != 6 && digits.len() != 8) || !digits.is_ascii() {
+        return Err(format!("expected #rrggbb or #rrggbbaa: {}", hex));
+    }
+    let mut channels = [255u8; 4];
+    for (i, channel) in channels.iter_mut().enumerate().take(digits.len() / 2) {
+        *channel = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16)
+            .map_err(|_| format!("invalid hex color: {}", hex))?;
+    }
+    let alpha = channels[3] as f32 / 255.0;
+    let channel = |i: usize| srgb_to_linear(channels[i] as f32 / 255.0) * alpha;
+    Ok(Color::from_rgba(channel(0), channel(1), channel(2), alpha))
+}
+
+#[cfg(test)]
+mod test {
+    use color_space::{
+        color_linear_to_srgb, color_srgb_to_linear, linear_to_srgb, parse_hex, srgb_to_linear,
+    };
+    use types::Color;
+
+    #[test]
+    fn test_linear_to_srgb_and_back_is_near_identity() {
+        for i in 0..=100 {
+            let linear = i as f32 / 100.0;
+            assert_approx_eq!(srgb_to_linear(linear_to_srgb(linear)), linear, 1e-5);
+        }
+        let color = Color::from_rgba(0.02, 0.2, 0.4, 0.5);
+        let back = color_srgb_to_linear(color_linear_to_srgb(color));
+
+        assert_approx_eq!(back.red, color.red, 1e-5);
+        assert_approx_eq!(back.green, color.green, 1e-5);
+        assert_approx_eq!(back.blue, color.blue, 1e-5);
+        assert_approx_eq!(back.alpha, color.alpha);
+        assert_approx_eq!(linear_to_srgb(0.214), 0.5, 1e-3);
+    }
+
+    #[test]
+    fn test_hex_colors_are_decoded_to_linear() {
+        let gray = parse_hex("#bcbcbc").unwrap();
+        let translucent = parse_hex("ff000080").unwrap();
+
+        assert_approx_eq!(gray.red, 0.503, 1e-3);
+        assert_approx_eq!(gray.alpha, 1.0);
+        assert_approx_eq!(translucent.red / translucent.alpha, 1.0);
+        assert_approx_eq!(translucent.alpha, 128.0 / 255.0);
+        assert!(parse_hex("#12345").is_err());
+        assert!(parse_hex("#gggggg").is_err());
+    }
+}
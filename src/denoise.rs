@@ -0,0 +1,220 @@
+use image::{DynamicImage, GenericImage};
+use render::Aovs;
+use types::Color;
+
+/// settings of the edge-avoiding à-trous filter
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseOptions {
+    /// every pass doubles the reach of the 5×5 kernel, three of them cover 25×25 pixels
+    pub passes: u32,
+    /// how far apart two colors may be and still be mixed, halved after every pass
+    pub color_sigma: f32,
+    /// how far apart two encoded normals may be, in the [0, 1] range of the normal aov
+    pub normal_sigma: f32,
+    /// how far apart two depths may be, in the [0, 1] range of the depth aov
+    pub depth_sigma: f32,
+}
+
+impl Default for DenoiseOptions {
+    fn default() -> DenoiseOptions {
+        DenoiseOptions {
+            passes: 3,
+            color_sigma: 0.5,
+            normal_sigma: 0.1,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// the rgb channels of a pixel in [0, 1]
+fn channels(image: &DynamicImage, x: u32, y: u32) -> [f32; 3] {
+    let data = image.get_pixel(x, y).data;
+    [
+        data[0] as f32 / 255.0,
+        data[1] as f32 / 255.0,
+        data[2] as f32 / 255.0,
+    ]
+}
+
+fn distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// smooths the noise of a render, only mixing pixels that show the same surface.
+///
+/// neighbors are weighted by how close their normal, depth and color are to the pixel's own,
+/// so edges between objects and shading discontinuities stay sharp. colors are mixed as
+/// linear light, the srgb encoded image is decoded first. fails if `aovs` weren't recorded
+/// for the same region as `image`.
+pub fn denoise(
+    image: &DynamicImage,
+    aovs: &Aovs,
+    options: &DenoiseOptions,
+) -> Result<DynamicImage, String> {
+    let (width, height) = (image.width(), image.height());
+    for &(name, aov) in &[("depth", &aovs.depth), ("normal", &aovs.normal)] {
+        if aov.dimensions() != (width, height) {
+            return Err(format!(
+                "the {} aov is {}x{}, but the image is {}x{}",
+                name,
+                aov.width(),
+                aov.height(),
+                width,
+                height
+            ));
+        }
+    }
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    let mut normals = Vec::with_capacity((width * height) as usize);
+    let mut depths = Vec::with_capacity((width * height) as usize);
+    let mut colors = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            normals.push(channels(&aovs.normal, x, y));
+            depths.push(channels(&aovs.depth, x, y)[0]);
+            colors.push(Color::from_rgba8(image.get_pixel(x, y)).decode_srgb());
+        }
+    }
+
+    let mut color_sigma = options.color_sigma;
+    for pass in 0..options.passes {
+        let step = 1i64 << pass;
+        let mut filtered = colors.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let center = index(x, y);
+                let own = colors[center];
+                let mut sum = Color::from_rgba(0.0, 0.0, 0.0, 0.0);
+                let mut total = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let sx = x as i64 + (i as i64 - 2) * step;
+                        let sy = y as i64 + (j as i64 - 2) * step;
+                        if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                            continue;
+                        }
+                        let sample = index(sx as u32, sy as u32);
+                        let color = colors[sample];
+
+                        let color_distance = distance_squared(
+                            [own.red, own.green, own.blue],
+                            [color.red, color.green, color.blue],
+                        );
+                        let normal_distance = distance_squared(normals[center], normals[sample]);
+                        let depth_distance = (depths[center] - depths[sample]).abs();
+                        let weight = kx
+                            * ky
+                            * (-color_distance / (color_sigma * color_sigma)).exp()
+                            * (-normal_distance / (options.normal_sigma * options.normal_sigma))
+                                .exp()
+                            * (-depth_distance / options.depth_sigma).exp();
+
+                        sum = sum + color * weight;
+                        total += weight;
+                    }
+                }
+
+                filtered[center] = sum * (1.0 / total);
+            }
+        }
+
+        colors = filtered;
+        color_sigma /= 2.0;
+    }
+
+    let mut output = DynamicImage::new_rgba8(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let color = colors[index(x, y)].clamp().encode_srgb();
+            output.put_pixel(x, y, color.to_rgba8());
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use denoise::{denoise, DenoiseOptions};
+    use image::{DynamicImage, GenericImage, Rgba};
+    use random::Random;
+    use render::Aovs;
+
+    fn variance(image: &DynamicImage, xs: ::std::ops::Range<u32>) -> f32 {
+        let values: Vec<f32> = xs
+            .flat_map(|x| (4..28).map(move |y| (x, y)))
+            .map(|(x, y)| image.get_pixel(x, y).data[0] as f32)
+            .collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn test_noise_is_smoothed_but_edges_stay_sharp() {
+        // a noisy gray wall on the left, a black object closer to the camera on the right
+        let mut random = Random::seeded(3);
+        let mut image = DynamicImage::new_rgba8(32, 32);
+        let mut aovs = Aovs::new(32, 32);
+        for y in 0..32 {
+            for x in 0..32 {
+                let (color, depth, normal) = if x < 16 {
+                    let noise = (random.next_f64() * 100.0) as u8;
+                    (78 + noise, 200, [128, 128, 255])
+                } else {
+                    (0, 50, [255, 128, 128])
+                };
+                image.put_pixel(x, y, Rgba([color, color, color, 255]));
+                aovs.depth.put_pixel(x, y, Rgba([depth, depth, depth, 255]));
+                aovs.normal
+                    .put_pixel(x, y, Rgba([normal[0], normal[1], normal[2], 255]));
+            }
+        }
+
+        let denoised = denoise(&image, &aovs, &DenoiseOptions::default()).unwrap();
+
+        assert!(variance(&denoised, 0..16) < variance(&image, 0..16) / 4.0);
+        for y in 0..32 {
+            assert!(denoised.get_pixel(15, y).data[0] >= 78);
+            assert_eq!(denoised.get_pixel(16, y).data[0], 0);
+        }
+    }
+
+    #[test]
+    fn test_colors_are_mixed_as_linear_light() {
+        // black and white pixels of one surface average to half the light, which is
+        // brighter than the halfway byte once encoded
+        let mut image = DynamicImage::new_rgba8(32, 32);
+        let aovs = Aovs::new(32, 32);
+        for y in 0..32 {
+            for x in 0..32 {
+                let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+                image.put_pixel(x, y, Rgba([value, value, value, 255]));
+            }
+        }
+        let options = DenoiseOptions {
+            color_sigma: 100.0,
+            ..DenoiseOptions::default()
+        };
+
+        let denoised = denoise(&image, &aovs, &options).unwrap();
+
+        let center = denoised.get_pixel(16, 16).data[0];
+        assert!((180..=196).contains(&center), "{}", center);
+    }
+
+    #[test]
+    fn test_aovs_of_another_size_are_rejected() {
+        let image = DynamicImage::new_rgba8(32, 32);
+        let aovs = Aovs::new(16, 32);
+
+        let err = denoise(&image, &aovs, &DenoiseOptions::default())
+            .err()
+            .unwrap();
+
+        assert!(err.contains("16x32"), "{}", err);
+    }
+}
@@ -0,0 +1,192 @@
+use cgmath::InnerSpace;
+use image::{DynamicImage, GenericImage};
+use std::f64::consts::PI;
+use std::sync::Arc;
+use types::{Color, Direction};
+
+/// images surrounding the scene, seen by every ray that misses all objects
+#[derive(Clone)]
+pub enum EnvironmentMap {
+    /// a single image, mapped like `DirectionalLight::from_environment`: u follows the angle
+    /// around the y axis starting at -x, v runs from +y (top row) to -y.
+    Equirectangular(Arc<DynamicImage>),
+    /// the faces of a cube around the scene in the order +x, -x, +y, -y, +z, -z, laid out as
+    /// seen from the inside with +y up on the side faces, -z up on +y and +z up on -y
+    CubeMap(Arc<[DynamicImage; 6]>),
+}
+
+/// the linear color at the fractional pixel position, interpolated between the four nearest
+/// texels.
+/// columns wrap around if `wrap` is set, everything else is clamped to the edge
+fn bilinear(image: &DynamicImage, fx: f64, fy: f64, wrap: bool) -> Color {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let (x0, y0) = (fx.floor(), fy.floor());
+    let (tx, ty) = ((fx - x0) as f32, (fy - y0) as f32);
+
+    let texel = |x: i64, y: i64| {
+        let x = if wrap {
+            x.rem_euclid(width)
+        } else {
+            x.clamp(0, width - 1)
+        };
+        let y = y.clamp(0, height - 1);
+        Color::from_rgba8(image.get_pixel(x as u32, y as u32)).decode_srgb()
+    };
+
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+    let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// the cube face `direction` points at and where it hits it, u and v in [0, 1] from the top
+/// left corner of the face
+pub fn cube_face(direction: &Direction) -> (usize, f64, f64) {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+
+    (face, (sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0)
+}
+
+impl EnvironmentMap {
+    /// an equirectangular environment
+    pub fn new<I: Into<Arc<DynamicImage>>>(image: I) -> EnvironmentMap {
+        EnvironmentMap::Equirectangular(image.into())
+    }
+
+    pub fn cube_map(faces: [DynamicImage; 6]) -> EnvironmentMap {
+        EnvironmentMap::CubeMap(Arc::new(faces))
+    }
+
+    /// the color seen looking in `direction`, interpolated between the four nearest texels.
+    /// equirectangular longitude wraps around the seam at -x, latitude is clamped at the
+    /// poles. cube faces are clamped at their edges.
+    pub fn sample(&self, direction: &Direction) -> Color {
+        match *self {
+            EnvironmentMap::Equirectangular(ref image) => {
+                let (width, height) = (image.width(), image.height());
+                let phi = direction.z.atan2(direction.x);
+                let theta = (direction.y / direction.magnitude())
+                    .clamp(-1.0, 1.0)
+                    .acos();
+
+                let fx = (phi / PI + 1.0) / 2.0 * width as f64 - 0.5;
+                let fy = theta / PI * height as f64 - 0.5;
+                bilinear(image, fx, fy, true)
+            }
+            EnvironmentMap::CubeMap(ref faces) => {
+                let (face, u, v) = cube_face(direction);
+                let image = &faces[face];
+                let fx = u * image.width() as f64 - 0.5;
+                let fy = v * image.height() as f64 - 0.5;
+                bilinear(image, fx, fy, false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use color_space::srgb_to_linear;
+    use environment::{cube_face, EnvironmentMap};
+    use image::{DynamicImage, GenericImage, Rgba};
+    use types::Direction;
+
+    /// red in the first column, blue in the last, so the seam lies between them
+    fn seam_image() -> DynamicImage {
+        let mut image = DynamicImage::new_rgb8(16, 8);
+        for y in 0..8 {
+            image.put_pixel(0, y, Rgba([255, 0, 0, 255]));
+            image.put_pixel(15, y, Rgba([0, 0, 255, 255]));
+        }
+        image
+    }
+
+    #[test]
+    fn test_seam_is_interpolated_across() {
+        let environment = EnvironmentMap::new(seam_image());
+
+        let left = environment.sample(&Direction::new(-1.0, 0.0, -1e-4));
+        let right = environment.sample(&Direction::new(-1.0, 0.0, 1e-4));
+
+        assert_approx_eq!(left.red, right.red, 1e-3);
+        assert_approx_eq!(left.blue, right.blue, 1e-3);
+        assert_approx_eq!(left.red, 0.5, 1e-3);
+    }
+
+    #[test]
+    fn test_cube_map_looks_up_the_face_in_the_direction() {
+        let shades = [40, 80, 120, 160, 200, 240];
+        let mut faces = [
+            DynamicImage::new_rgb8(4, 4),
+            DynamicImage::new_rgb8(4, 4),
+            DynamicImage::new_rgb8(4, 4),
+            DynamicImage::new_rgb8(4, 4),
+            DynamicImage::new_rgb8(4, 4),
+            DynamicImage::new_rgb8(4, 4),
+        ];
+        for (face, shade) in faces.iter_mut().zip(&shades) {
+            for y in 0..4 {
+                for x in 0..4 {
+                    face.put_pixel(x, y, Rgba([*shade, 0, 0, 255]));
+                }
+            }
+        }
+        // the top row of the +x face is marked
+        faces[0].put_pixel(1, 0, Rgba([40, 255, 0, 255]));
+        faces[0].put_pixel(2, 0, Rgba([40, 255, 0, 255]));
+        let environment = EnvironmentMap::cube_map(faces);
+
+        let red = |x, y, z| environment.sample(&Direction::new(x, y, z)).red;
+        let linear = |shade: f32| srgb_to_linear(shade / 255.0);
+
+        assert_approx_eq!(red(1.0, 0.1, -0.2), linear(40.0));
+        assert_approx_eq!(red(-1.0, 0.1, 0.2), linear(80.0));
+        assert_approx_eq!(red(0.1, 1.0, 0.2), linear(120.0));
+        assert_approx_eq!(red(0.1, -1.0, 0.2), linear(160.0));
+        assert_approx_eq!(red(0.1, 0.2, 1.0), linear(200.0));
+        assert_approx_eq!(red(0.1, 0.2, -1.0), linear(240.0));
+        assert_eq!(cube_face(&Direction::new(1.0, 0.0, 0.0)), (0, 0.5, 0.5));
+        assert_approx_eq!(
+            environment.sample(&Direction::new(1.0, 0.99, 0.0)).green,
+            1.0
+        );
+        assert_approx_eq!(
+            environment.sample(&Direction::new(1.0, -0.99, 0.0)).green,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_poles_clamp_to_the_outer_rows() {
+        let mut image = DynamicImage::new_rgb8(4, 4);
+        for x in 0..4 {
+            image.put_pixel(x, 0, Rgba([255, 255, 255, 255]));
+        }
+        let environment = EnvironmentMap::new(image);
+
+        let up = environment.sample(&Direction::new(0.0, 1.0, 0.0));
+        let down = environment.sample(&Direction::new(0.0, -1.0, 0.0));
+
+        assert_approx_eq!(up.green, 1.0);
+        assert_approx_eq!(down.green, 0.0);
+    }
+}
@@ -0,0 +1,22 @@
+#[cfg(test)]
+#[macro_use]
+extern crate assert_approx_eq;
+extern crate cgmath;
+extern crate image;
+extern crate num_cpus;
+extern crate threadpool;
+extern crate wavefront_obj;
+
+pub mod cli;
+pub mod color_space;
+pub mod denoise;
+pub mod environment;
+pub mod light;
+pub mod lut;
+pub mod objects;
+pub mod random;
+pub mod raycast;
+pub mod render;
+pub mod scene;
+pub mod scene_file;
+pub mod types;
@@ -1,33 +1,184 @@
-use types::{Color, Direction};
+use cgmath::InnerSpace;
+use image::{DynamicImage, GenericImage};
+use std::f64::consts::PI;
+use types::{Color, Direction, Point};
 
-#[derive(Debug, Copy, Clone)]
-pub enum Light {
-    Directional(DirectionalLight),
-}
+/// anything that shines on the scene. lights are sampled from the point being shaded,
+/// the same way any `Structure` can be placed in the scene as an object.
+pub trait Light {
+    /// the light arriving at `point`
+    fn sample(&self, point: &Point) -> LightSample;
 
-impl Light {
-    pub fn direction(&self) -> Direction {
-        match *self {
-            Light::Directional(ref s) => s.direction,
-        }
+    fn casts_shadows(&self) -> bool {
+        false
     }
 
-    pub fn intensity(&self) -> f32 {
-        match *self {
-            Light::Directional(ref s) => s.intensity,
-        }
+    /// angular radius of the light in radians as seen from the surface
+    fn softness(&self) -> f64 {
+        0.0
     }
 
-    pub fn color(&self) -> &Color {
-        match *self {
-            Light::Directional(ref s) => &s.color,
-        }
+    fn layers(&self) -> u32 {
+        ALL_LAYERS
+    }
+
+    /// whether the light shines on surfaces linked to `layers`
+    fn lights(&self, layers: u32) -> bool {
+        self.layers() & layers != 0
     }
 }
 
+/// the light one light sends to a point
+#[derive(Debug, Copy, Clone)]
+pub enum LightSample {
+    /// light from `direction`, pointing from the point towards the light, which is `distance`
+    /// away. distant lights are infinitely far away
+    Directed {
+        direction: Direction,
+        distance: f64,
+        radiance: Color,
+    },
+    /// light arriving from everywhere, it reaches every surface whatever it faces
+    Ambient(Color),
+}
+
+/// lights and objects on every layer, lights shine on all objects by default
+pub const ALL_LAYERS: u32 = !0;
+
 #[derive(Debug, Copy, Clone)]
 pub struct DirectionalLight {
     pub direction: Direction,
     pub color: Color,
     pub intensity: f32,
+    /// fill lights usually should not cast any shadows
+    pub casts_shadows: bool,
+    /// angular radius of the light in radians, 0.0 gives hard shadows
+    pub softness: f64,
+    /// the light only shines on objects sharing one of these bits
+    pub layers: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AmbientLight {
+    pub color: Color,
+    pub intensity: f32,
+}
+
+pub fn luminance(color: &Color) -> f32 {
+    0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Point) -> LightSample {
+        LightSample::Directed {
+            direction: (-self.direction).normalize(),
+            distance: f64::INFINITY,
+            radiance: self.color * self.intensity,
+        }
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+
+    fn softness(&self) -> f64 {
+        self.softness
+    }
+
+    fn layers(&self) -> u32 {
+        self.layers
+    }
+}
+
+impl Light for AmbientLight {
+    fn sample(&self, _point: &Point) -> LightSample {
+        LightSample::Ambient(self.color * self.intensity)
+    }
+}
+
+impl DirectionalLight {
+    /// approximates an equirectangular environment image with `count` directional lights.
+    ///
+    /// texels are picked by importance sampling their luminance weighted by the solid angle
+    /// they cover, so bright regions get more lights. every light carries the same share
+    /// of the total energy and the color of the texel it was sampled from. the mapping
+    /// matches the sphere texture coordinates: u follows the angle around the y axis and
+    /// v runs from +y (top row) to -y.
+    pub fn from_environment(image: &DynamicImage, count: usize) -> Vec<DirectionalLight> {
+        let (width, height) = (image.width(), image.height());
+        let texel_angle = (2.0 * PI / width as f64) * (PI / height as f64);
+        let theta = |y: u32| (y as f64 + 0.5) / height as f64 * PI;
+
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        let mut cdf = Vec::with_capacity((width * height) as usize);
+        let mut total = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                let color = Color::from_rgba8(image.get_pixel(x, y)).decode_srgb();
+                total += luminance(&color) as f64 * theta(y).sin() * texel_angle;
+                texels.push((x, y, color));
+                cdf.push(total);
+            }
+        }
+
+        if count == 0 || total <= 0.0 {
+            return Vec::new();
+        }
+
+        (0..count)
+            .map(|k| {
+                let target = (k as f64 + 0.5) / count as f64 * total;
+                let index = cdf
+                    .iter()
+                    .position(|&c| c >= target)
+                    .unwrap_or(cdf.len() - 1);
+                let (x, y, color) = texels[index];
+
+                let phi = ((x as f64 + 0.5) / width as f64 * 2.0 - 1.0) * PI;
+                let theta = theta(y);
+                let towards_texel = Direction::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+
+                DirectionalLight {
+                    direction: -towards_texel,
+                    color: color * (1.0 / luminance(&color)),
+                    intensity: (total / count as f64) as f32,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GenericImage, Rgba};
+    use light::DirectionalLight;
+
+    #[test]
+    fn test_bright_spot_yields_light_from_its_direction() {
+        let mut image = DynamicImage::new_rgb8(64, 32);
+        // u = 0.5, v = 0.5 lies on the horizon towards +x
+        image.put_pixel(32, 16, Rgba([255, 255, 255, 255]));
+
+        let lights = DirectionalLight::from_environment(&image, 4);
+
+        assert_eq!(lights.len(), 4);
+        for light in lights {
+            assert!(light.direction.x < -0.99, "{:?}", light.direction);
+            assert!(light.intensity > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_black_environment_yields_no_lights() {
+        let image = DynamicImage::new_rgb8(8, 4);
+
+        assert!(DirectionalLight::from_environment(&image, 4).is_empty());
+    }
 }
@@ -0,0 +1,221 @@
+use image::{DynamicImage, GenericImage, Rgba};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// a color grading lookup table as stored in .cube files, applied to the finished image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    /// entries along each axis
+    size: usize,
+    three_dimensional: bool,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// `size` rgb entries for a 1d table, `size`³ for a 3d one with red changing fastest
+    table: Vec<[f32; 3]>,
+}
+
+fn floats(values: &[&str], line: &str) -> Result<[f32; 3], String> {
+    if values.len() != 3 {
+        return Err(format!("expected three numbers: {}", line));
+    }
+    let mut parsed = [0.0; 3];
+    for (value, text) in parsed.iter_mut().zip(values) {
+        *value = text
+            .parse()
+            .map_err(|_| format!("invalid number in: {}", line))?;
+    }
+    Ok(parsed)
+}
+
+impl Lut {
+    /// the table that leaves every color as it is, with `size` entries along each axis
+    pub fn identity(size: usize) -> Lut {
+        let size = size.max(2);
+        let step = |i: usize| i as f32 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push([step(r), step(g), step(b)]);
+                }
+            }
+        }
+        Lut {
+            size,
+            three_dimensional: true,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        }
+    }
+
+    /// parses the text of a .cube file with either a `LUT_1D_SIZE` or a `LUT_3D_SIZE`
+    pub fn from_cube(source: &str) -> Result<Lut, String> {
+        let mut size = None;
+        let mut three_dimensional = false;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for line in source.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => {}
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    let entries = words
+                        .get(1)
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .filter(|&n| n >= 2)
+                        .ok_or_else(|| format!("invalid size: {}", line))?;
+                    size = Some(entries);
+                    three_dimensional = words[0] == "LUT_3D_SIZE";
+                }
+                "DOMAIN_MIN" => domain_min = floats(&words[1..], line)?,
+                "DOMAIN_MAX" => domain_max = floats(&words[1..], line)?,
+                _ => table.push(floats(&words, line)?),
+            }
+        }
+
+        let size = size.ok_or("the file has no LUT_1D_SIZE or LUT_3D_SIZE")?;
+        let expected = if three_dimensional {
+            size * size * size
+        } else {
+            size
+        };
+        if table.len() != expected {
+            return Err(format!(
+                "expected {} entries, found {}",
+                expected,
+                table.len()
+            ));
+        }
+        Ok(Lut {
+            size,
+            three_dimensional,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Lut, String> {
+        let mut source = String::new();
+        File::open(path.as_ref())
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.as_ref().display(), err))?;
+        Lut::from_cube(&source)
+    }
+
+    /// the graded color, channels in [0, 1]. colors between the entries are interpolated
+    /// linearly, trilinearly for a 3d table
+    pub fn apply_rgb(&self, rgb: [f32; 3]) -> [f32; 3] {
+        // the position of each channel in entries along its axis
+        let mut position = [0.0; 3];
+        for c in 0..3 {
+            let range = (self.domain_max[c] - self.domain_min[c]).max(1e-6);
+            let t = ((rgb[c] - self.domain_min[c]) / range).clamp(0.0, 1.0);
+            position[c] = t * (self.size - 1) as f32;
+        }
+        let split = |p: f32| {
+            let i = (p.floor() as usize).min(self.size - 2);
+            (i, p - i as f32)
+        };
+
+        if !self.three_dimensional {
+            let mut graded = [0.0; 3];
+            for c in 0..3 {
+                let (i, t) = split(position[c]);
+                graded[c] = self.table[i][c] * (1.0 - t) + self.table[i + 1][c] * t;
+            }
+            return graded;
+        }
+
+        let (r, tr) = split(position[0]);
+        let (g, tg) = split(position[1]);
+        let (b, tb) = split(position[2]);
+        let mut graded = [0.0; 3];
+        for (corner, weight) in [
+            ((0, 0, 0), (1.0 - tr) * (1.0 - tg) * (1.0 - tb)),
+            ((1, 0, 0), tr * (1.0 - tg) * (1.0 - tb)),
+            ((0, 1, 0), (1.0 - tr) * tg * (1.0 - tb)),
+            ((1, 1, 0), tr * tg * (1.0 - tb)),
+            ((0, 0, 1), (1.0 - tr) * (1.0 - tg) * tb),
+            ((1, 0, 1), tr * (1.0 - tg) * tb),
+            ((0, 1, 1), (1.0 - tr) * tg * tb),
+            ((1, 1, 1), tr * tg * tb),
+        ]
+        .iter()
+        {
+            let (dr, dg, db) = *corner;
+            let entry =
+                self.table[(r + dr) + (g + dg) * self.size + (b + db) * self.size * self.size];
+            for c in 0..3 {
+                graded[c] += entry[c] * weight;
+            }
+        }
+        graded
+    }
+
+    /// grades every pixel of the image, alpha is kept
+    pub fn apply(&self, image: &mut DynamicImage) {
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let data = image.get_pixel(x, y).data;
+                let graded = self.apply_rgb([
+                    data[0] as f32 / 255.0,
+                    data[1] as f32 / 255.0,
+                    data[2] as f32 / 255.0,
+                ]);
+                let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                image.put_pixel(
+                    x,
+                    y,
+                    Rgba([byte(graded[0]), byte(graded[1]), byte(graded[2]), data[3]]),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GenericImage, Rgba};
+    use lut::Lut;
+
+    #[test]
+    fn test_identity_lut_leaves_pixels_unchanged() {
+        let mut image = DynamicImage::new_rgba8(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                image.put_pixel(x, y, Rgba([x as u8 * 17, y as u8 * 16, 200, 128]));
+            }
+        }
+        let original = image.clone();
+
+        Lut::identity(17).apply(&mut image);
+
+        assert_eq!(image.raw_pixels(), original.raw_pixels());
+    }
+
+    #[test]
+    fn test_cube_file_remaps_colors() {
+        // swaps red and blue
+        let swap = "TITLE \"swap\"\n# red changes fastest\nLUT_3D_SIZE 2\n\
+                    0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+        let inverted = "LUT_1D_SIZE 2\n1 1 1\n0 0 0\n";
+
+        let graded = Lut::from_cube(swap).unwrap().apply_rgb([0.8, 0.5, 0.2]);
+        let negative = Lut::from_cube(inverted).unwrap().apply_rgb([0.8, 0.5, 0.2]);
+
+        assert_approx_eq!(graded[0], 0.2);
+        assert_approx_eq!(graded[1], 0.5);
+        assert_approx_eq!(graded[2], 0.8);
+        assert_approx_eq!(negative[0], 0.2);
+        assert_approx_eq!(negative[2], 0.8);
+        assert!(Lut::from_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    }
+}
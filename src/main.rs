@@ -1,29 +1,23 @@
 extern crate cgmath;
 extern crate image;
-extern crate num_cpus;
-extern crate threadpool;
+extern crate raytracer;
 extern crate wavefront_obj;
 
-mod light;
-mod objects;
-mod raycast;
-mod render;
-mod scene;
-mod types;
-
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
+use std::process;
 use std::time::{Duration, Instant};
 
 use cgmath::prelude::*;
 
-use cgmath::Deg;
-use cgmath::Quaternion;
-use light::*;
-use objects::{Material, Mesh, ObjectBuilder, Plane, Sphere};
-use render::render;
-use scene::{Camera, SceneBuilder};
-use types::{Color, Direction, Point};
+use raytracer::cli::{parse_args, Config, USAGE};
+use raytracer::light::*;
+use raytracer::objects::{Material, Mesh, MeshOptions, ObjectBuilder, Plane};
+use raytracer::render::try_render_with_options;
+use raytracer::scene::{Scene, SceneBuilder};
+use raytracer::scene_file::read_scene;
+use raytracer::types::{Color, Direction, Point};
 
 fn format_time(duration: &Duration) -> f64 {
     duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
@@ -31,31 +25,37 @@ fn format_time(duration: &Duration) -> f64 {
 
 use std::env;
 
-fn main() {
-    let idx: f64 = env::args()
-        .collect::<Vec<String>>()
-        .get(1)
-        .unwrap_or(&String::from("0"))
-        .parse()
-        .unwrap_or(45.0);
-    println!("rendering with {:?}° rot.", idx);
-    let rotation = Deg(idx * 2.0);
-
-    let teapot_read = wavefront_obj::obj::parse(String::from(include_str!("../teapot.obj")));
-
-    if let Err(err) = teapot_read {
-        panic!("{:?}", err);
+fn read_model(path: &str) -> String {
+    let mut source = String::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_string(&mut source)) {
+        eprintln!("could not read {}: {}", path, err);
+        process::exit(1);
     }
+    source
+}
+
+/// the teapot, or the model given with `--model`, on a floor in front of a wall
+fn builtin_scene(config: &Config) -> Scene {
+    println!("rendering with {:?}° rot.", config.rotation);
 
-    let teapot = teapot_read.unwrap();
-    // find first object
-    let object = teapot
-        .objects
-        .iter()
-        .find(|p| p.vertices.len() > 0)
-        .expect("no object found");
+    let model = match config.model {
+        Some(ref path) => read_model(path),
+        None => String::from(include_str!("../teapot.obj")),
+    };
+    let name = config.model.as_deref().unwrap_or("teapot.obj");
+    let teapot = wavefront_obj::obj::parse(model).unwrap_or_else(|err| {
+        eprintln!("could not parse {}: {:?}", name, err);
+        process::exit(1);
+    });
+    let (_, mesh) = Mesh::from_obj_all(&teapot, &MeshOptions::default())
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| {
+            eprintln!("{} has no faces", name);
+            process::exit(1);
+        });
 
-    let scene = SceneBuilder::new()
+    SceneBuilder::new()
         .add_object(
             ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
                 .at_position(Point::new(0.0, -4.0, 0.0))
@@ -69,47 +69,82 @@ fn main() {
                 .into(),
         )
         .add_object(
-            ObjectBuilder::create_for(Mesh::create(object.clone()))
+            ObjectBuilder::create_for(mesh)
                 .with_material(Material::reflective_color(
                     Color::from_rgb(0.6, 0.6, 0.6),
                     0.2,
                     0.02,
                 ))
-                .scale(1.0)
-                .rotation(Quaternion::one() + Quaternion::from_angle_y(rotation))
+                .rotation_euler(0.0, config.rotation, 0.0)
                 .at_position(Point::new(0.0, -2.0, -6.0))
                 .into(),
         )
-        .add_light(Light::Directional(DirectionalLight {
+        .add_light(DirectionalLight {
             direction: Direction::new(0.25, 0.0, -1.0).normalize(),
             color: Color::from_rgb(1.0, 1.0, 1.0),
             intensity: 20.0,
-        }))
-        .add_light(Light::Directional(DirectionalLight {
+            casts_shadows: true,
+            softness: 0.0,
+            layers: ALL_LAYERS,
+        })
+        .add_light(DirectionalLight {
             direction: Direction::new(0.0, -1.0, -1.0),
             color: Color::from_rgb(1.0, 1.0, 1.0),
             intensity: 10.0,
-        }))
-        .finish();
+            casts_shadows: true,
+            softness: 0.0,
+            layers: ALL_LAYERS,
+        })
+        .finish()
+}
 
-    let camera = Camera {
-        width: 1000,
-        height: 1000,
-        fov: 90.0,
+fn main() {
+    let config = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let (scene, camera) = match config.scene {
+        Some(ref path) => read_scene(path, config.camera()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        }),
+        None => (builtin_scene(&config), config.camera()),
     };
 
     let before_render = Instant::now();
-    let image = render(scene, camera);
+    let output =
+        try_render_with_options(scene, camera, config.render_options()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    let image = output.image;
     let before_save = Instant::now();
-    let ref mut fout = File::create(&Path::new("test.png")).unwrap();
-    match image.save(fout, image::PNG) {
-        Err(err) => println!("{:?}", err),
-        Ok(_) => {}
-    };
+    let mut fout = File::create(Path::new(&config.out)).unwrap_or_else(|err| {
+        eprintln!("could not create {}: {}", config.out, err);
+        process::exit(1);
+    });
+    if let Err(err) = image.save(&mut fout, image::PNG) {
+        eprintln!("could not write {}: {}", config.out, err);
+        process::exit(1);
+    }
 
     println!(
         "render: {:?}, save: {:?}",
         format_time(&before_save.duration_since(before_render)),
         format_time(&before_save.elapsed())
     );
+    println!(
+        "tiles: {:?}, average: {:?}",
+        output.timing.tiles.len(),
+        format_time(&output.timing.average())
+    );
+    for tile in output.timing.slowest(3) {
+        println!(
+            "slow tile at {}x{}: {:?}",
+            tile.x,
+            tile.y,
+            format_time(&tile.duration)
+        );
+    }
 }
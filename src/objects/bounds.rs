@@ -0,0 +1,271 @@
+use cgmath::prelude::*;
+use cgmath::Matrix4;
+use raycast::Ray;
+use types::Point;
+
+/// an axis aligned box
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        let (tmin, tmax) = self.slab_interval(ray);
+        tmax >= tmin && tmax >= 0.0
+    }
+
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    fn slab_interval(&self, ray: &Ray) -> (f64, f64) {
+        self.slab_interval_scalar(ray)
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn slab_interval(&self, ray: &Ray) -> (f64, f64) {
+        self.slab_interval_simd(ray)
+    }
+
+    /// the distances along the ray between which it is inside all three slabs
+    #[cfg_attr(all(feature = "simd", target_arch = "x86_64"), allow(dead_code))]
+    fn slab_interval_scalar(&self, ray: &Ray) -> (f64, f64) {
+        let pmin = self.min;
+        let pmax = self.max;
+
+        let tx1 = (pmin.x - ray.origin.x) * ray.inv_direction.x;
+        let tx2 = (pmax.x - ray.origin.x) * ray.inv_direction.x;
+
+        let mut tmin = tx1.min(tx2);
+        let mut tmax = tx1.max(tx2);
+
+        let ty1 = (pmin.y - ray.origin.y) * ray.inv_direction.y;
+        let ty2 = (pmax.y - ray.origin.y) * ray.inv_direction.y;
+
+        tmin = tmin.max(ty1.min(ty2));
+        tmax = tmax.min(ty1.max(ty2));
+
+        let tz1 = (pmin.z - ray.origin.z) * ray.inv_direction.z;
+        let tz2 = (pmax.z - ray.origin.z) * ray.inv_direction.z;
+
+        tmin = tmin.max(tz1.min(tz2));
+        tmax = tmax.min(tz1.max(tz2));
+
+        (tmin, tmax)
+    }
+
+    /// the x and y slabs side by side in one sse2 register. `_mm_min_pd` and `_mm_max_pd`
+    /// return their second operand if either is nan, unlike `f64::min` and `f64::max`, which
+    /// return the other one. that case is patched up so both paths agree to the bit.
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn slab_interval_simd(&self, ray: &Ray) -> (f64, f64) {
+        use std::arch::x86_64::*;
+
+        // sse2 is part of every x86_64 cpu
+        unsafe {
+            let origin = _mm_set_pd(ray.origin.y, ray.origin.x);
+            let inv_direction = _mm_set_pd(ray.inv_direction.y, ray.inv_direction.x);
+            let t1 = _mm_mul_pd(
+                _mm_sub_pd(_mm_set_pd(self.min.y, self.min.x), origin),
+                inv_direction,
+            );
+            let t2 = _mm_mul_pd(
+                _mm_sub_pd(_mm_set_pd(self.max.y, self.max.x), origin),
+                inv_direction,
+            );
+
+            let t2_is_nan = _mm_cmpunord_pd(t2, t2);
+            let pick = |result: __m128d| {
+                _mm_or_pd(_mm_and_pd(t2_is_nan, t1), _mm_andnot_pd(t2_is_nan, result))
+            };
+            let near = pick(_mm_min_pd(t1, t2));
+            let far = pick(_mm_max_pd(t1, t2));
+
+            let (mut near_xy, mut far_xy) = ([0.0; 2], [0.0; 2]);
+            _mm_storeu_pd(near_xy.as_mut_ptr(), near);
+            _mm_storeu_pd(far_xy.as_mut_ptr(), far);
+
+            let tz1 = (self.min.z - ray.origin.z) * ray.inv_direction.z;
+            let tz2 = (self.max.z - ray.origin.z) * ray.inv_direction.z;
+
+            let tmin = near_xy[0].max(near_xy[1]).max(tz1.min(tz2));
+            let tmax = far_xy[0].min(far_xy[1]).min(tz1.max(tz2));
+            (tmin, tmax)
+        }
+    }
+
+    /// the smallest box holding all of the points
+    pub fn around<I: IntoIterator<Item = Point>>(points: I) -> Option<BoundingBox> {
+        points.into_iter().fold(None, |bounds, p| {
+            Some(match bounds {
+                None => BoundingBox { min: p, max: p },
+                Some(BoundingBox { min, max }) => BoundingBox {
+                    min: Point::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    max: Point::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                },
+            })
+        })
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox::around(vec![self.min, self.max, other.min, other.max]).unwrap()
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    pub fn corners(&self) -> Vec<Point> {
+        let (min, max) = (self.min, self.max);
+        vec![
+            Point::new(min.x, min.y, min.z),
+            Point::new(max.x, min.y, min.z),
+            Point::new(min.x, max.y, min.z),
+            Point::new(max.x, max.y, min.z),
+            Point::new(min.x, min.y, max.z),
+            Point::new(max.x, min.y, max.z),
+            Point::new(min.x, max.y, max.z),
+            Point::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// the box around this one after it has been moved by the matrix
+    pub fn transform(&self, matrix: &Matrix4<f64>) -> BoundingBox {
+        BoundingBox::around(
+            self.corners()
+                .into_iter()
+                .map(|p| matrix.transform_point(p)),
+        )
+        .unwrap()
+    }
+}
+
+/// a sphere around an object, a cheaper test than the box for rays that miss it by far
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point,
+    pub radius: f64,
+}
+
+impl BoundingSphere {
+    /// a sphere holding all of the points, centered on the box around them
+    pub fn around<I: IntoIterator<Item = Point>>(points: I) -> Option<BoundingSphere> {
+        let points: Vec<Point> = points.into_iter().collect();
+        let center = BoundingBox::around(points.iter().cloned())?.center();
+        let radius = points
+            .iter()
+            .map(|p| (p - center).magnitude())
+            .fold(0.0, f64::max);
+        Some(BoundingSphere { center, radius })
+    }
+
+    /// whether the ray comes within the radius of the center, ahead of its origin
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        let to_center = self.center - ray.origin;
+        let r2 = self.radius * self.radius;
+        let along = to_center.dot(ray.direction);
+        if along < 0.0 && to_center.magnitude2() > r2 {
+            return false;
+        }
+        to_center.magnitude2() - along * along / ray.direction.magnitude2() <= r2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Deg, Matrix4};
+    use objects::{BoundingBox, BoundingSphere};
+    use types::Point;
+
+    #[test]
+    fn test_rotated_box_grows_to_hold_its_corners() {
+        let unit = BoundingBox {
+            min: Point::new(-1.0, -1.0, -1.0),
+            max: Point::new(1.0, 1.0, 1.0),
+        };
+
+        let rotated = unit.transform(&Matrix4::from_angle_y(Deg(45.0)));
+
+        assert_approx_eq!(rotated.max.x, 2.0f64.sqrt());
+        assert_approx_eq!(rotated.min.z, -(2.0f64.sqrt()));
+        assert_approx_eq!(rotated.max.y, 1.0);
+    }
+
+    #[test]
+    fn test_bounding_sphere_rejects_rays_that_pass_by() {
+        use raycast::{Ray, RayType};
+        use types::Direction;
+
+        let unit = BoundingBox {
+            min: Point::new(-1.0, -1.0, -1.0),
+            max: Point::new(1.0, 1.0, 1.0),
+        };
+        let sphere = BoundingSphere::around(unit.corners()).unwrap();
+        let ray = |x: f64, z: f64| {
+            Ray::create(
+                Point::new(x, 0.0, 5.0),
+                Direction::new(0.0, 0.0, z),
+                RayType::Prime,
+            )
+        };
+
+        assert_approx_eq!(sphere.radius, 3.0f64.sqrt());
+        assert!(sphere.intersects(&ray(1.7, -1.0)));
+        assert!(!sphere.intersects(&ray(1.8, -1.0)));
+        assert!(!sphere.intersects(&ray(0.0, 1.0)));
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    #[test]
+    fn test_simd_slabs_agree_with_scalar_ones() {
+        use random::Random;
+        use raycast::{Ray, RayType};
+        use types::Direction;
+
+        let mut random = Random::seeded(11);
+        let mut value = |scale: f64| (random.next_f64() - 0.5) * scale;
+        // rays parallel to an axis and starting on a slab plane divide 0 by 0
+        let origins = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, -1.0, 0.5)];
+        let directions = [
+            Direction::new(1.0, 0.0, 0.0),
+            Direction::new(0.0, -1.0, 0.0),
+        ];
+
+        for i in 0..10_000 {
+            let (a, b) = (
+                Point::new(value(4.0), value(4.0), value(4.0)),
+                Point::new(value(4.0), value(4.0), value(4.0)),
+            );
+            let bounds = if i % 10 == 0 {
+                BoundingBox {
+                    min: Point::new(-1.0, -1.0, -1.0),
+                    max: Point::new(1.0, 1.0, 1.0),
+                }
+            } else {
+                BoundingBox::around(vec![a, b]).unwrap()
+            };
+            let ray = if i % 10 == 0 {
+                Ray::create(
+                    origins[i % 20 / 10],
+                    directions[i % 20 / 10],
+                    RayType::Prime,
+                )
+            } else {
+                Ray::create(
+                    Point::new(value(10.0), value(10.0), value(10.0)),
+                    Direction::new(value(2.0), value(2.0), value(2.0)),
+                    RayType::Prime,
+                )
+            };
+
+            let scalar = bounds.slab_interval_scalar(&ray);
+            let simd = bounds.slab_interval_simd(&ray);
+
+            assert_eq!(scalar.0.to_bits(), simd.0.to_bits());
+            assert_eq!(scalar.1.to_bits(), simd.1.to_bits());
+        }
+    }
+}
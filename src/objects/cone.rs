@@ -0,0 +1,235 @@
+use cgmath::prelude::*;
+use objects::{BoundingBox, Structure, TextureCoords};
+use raycast::{Intersection, Ray};
+use types::{Direction, Point, PARALLEL_EPSILON};
+
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+
+/// a finite cone standing on its base, a disc around the origin in the xz plane,
+/// with its apex at `(0, height, 0)`.
+pub struct Cone {
+    pub radius: f64,
+    pub height: f64,
+}
+
+impl Cone {
+    pub fn create(radius: f64, height: f64) -> Cone {
+        Cone { radius, height }
+    }
+
+    /// distances to the mantle, solving `x² + z² = (r/h)² (h - y)²` and keeping hits between
+    /// the base and the apex.
+    fn intersect_side(&self, ray: &Ray) -> Vec<f64> {
+        let (o, d) = (ray.origin, ray.direction);
+        let k2 = (self.radius / self.height).powi(2);
+        let oy = self.height - o.y;
+
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.z * d.z + k2 * oy * d.y);
+        let c = o.x * o.x + o.z * o.z - k2 * oy * oy;
+
+        let roots = if a.abs() < PARALLEL_EPSILON {
+            // the ray runs parallel to the mantle and crosses it at most once
+            if b.abs() < PARALLEL_EPSILON {
+                vec![]
+            } else {
+                vec![-c / b]
+            }
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                vec![]
+            } else {
+                let sqrt = discriminant.sqrt();
+                vec![(-b - sqrt) / (2.0 * a), (-b + sqrt) / (2.0 * a)]
+            }
+        };
+
+        roots
+            .into_iter()
+            .filter(|&t| {
+                let y = o.y + d.y * t;
+                t >= 0.0 && y >= 0.0 && y <= self.height
+            })
+            .collect()
+    }
+
+    fn intersect_base(&self, ray: &Ray) -> Option<f64> {
+        if ray.direction.y.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let t = -ray.origin.y / ray.direction.y;
+        let hit = ray.at(t);
+        if t >= 0.0 && hit.x * hit.x + hit.z * hit.z <= self.radius * self.radius {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    /// all hits on the mantle and the base, nearest first, flagged with whether they are on the
+    /// base
+    fn intersect(&self, ray: &Ray) -> Vec<(f64, bool)> {
+        let mut hits: Vec<(f64, bool)> = self
+            .intersect_side(ray)
+            .into_iter()
+            .map(|t| (t, false))
+            .chain(self.intersect_base(ray).map(|t| (t, true)))
+            .collect();
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    fn intersection_at(&self, ray: &Ray, distance: f64, on_base: bool) -> Intersection {
+        let hit_point = ray.at(distance);
+        Intersection::new(
+            distance,
+            hit_point,
+            self.texture_coord(&hit_point, on_base),
+            self.surface_normal(&hit_point, on_base),
+        )
+    }
+
+    fn surface_normal(&self, hit_point: &Point, on_base: bool) -> Direction {
+        if on_base {
+            return Direction::new(0.0, -1.0, 0.0);
+        }
+
+        let rho = (hit_point.x * hit_point.x + hit_point.z * hit_point.z).sqrt();
+        if rho < 1e-12 {
+            // the apex has no defined normal, point it straight up
+            return Direction::new(0.0, 1.0, 0.0);
+        }
+
+        Direction::new(hit_point.x, rho * self.radius / self.height, hit_point.z).normalize()
+    }
+
+    fn texture_coord(&self, hit_point: &Point, on_base: bool) -> TextureCoords {
+        if on_base {
+            return TextureCoords {
+                x: (0.5 + 0.5 * hit_point.x / self.radius) as f32,
+                y: (0.5 + 0.5 * hit_point.z / self.radius) as f32,
+            };
+        }
+
+        TextureCoords {
+            x: (1.0 + (hit_point.z.atan2(hit_point.x) as f32) / PI) * 0.5,
+            y: (1.0 - hit_point.y / self.height) as f32,
+        }
+    }
+}
+
+impl Structure for Cone {
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray)
+            .first()
+            .map(|&(distance, on_base)| self.intersection_at(ray, distance, on_base))
+    }
+
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        self.intersect(ray)
+            .into_iter()
+            .map(|(distance, on_base)| self.intersection_at(ray, distance, on_base))
+            .collect()
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(BoundingBox {
+            min: Point::new(-self.radius, 0.0, -self.radius),
+            max: Point::new(self.radius, self.height, self.radius),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+    use objects::{Cone, Object, ObjectBuilder, Structure};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_side_hit() {
+        let cone = Cone::create(1.0, 2.0);
+        let ray = Ray::create(
+            Point::new(-5.0, 1.0, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        let hit = cone.get_intersection(&ray).unwrap();
+        let normal = hit.surface_normal();
+
+        // halfway up the radius has shrunk to 0.5
+        assert_approx_eq!(hit.distance(), 4.5);
+        assert!(normal.x < 0.0 && normal.y > 0.0);
+        assert_approx_eq!(normal.magnitude(), 1.0);
+        assert_approx_eq!(normal.dot(Direction::new(1.0, 2.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_base_hit() {
+        let cone = Cone::create(1.0, 2.0);
+        let ray = Ray::create(
+            Point::new(0.5, -3.0, 0.0),
+            Direction::new(0.0, 1.0, 0.0),
+            RayType::Prime,
+        );
+
+        let hit = cone.get_intersection(&ray).unwrap();
+
+        assert_approx_eq!(hit.distance(), 3.0);
+        assert_eq!(hit.surface_normal(), Direction::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn test_apex_grazing() {
+        let cone = Cone::create(1.0, 2.0);
+        let above = Ray::create(
+            Point::new(-5.0, 2.0 + 1e-6, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+        let through = Ray::create(
+            Point::new(-5.0, 2.0, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        assert!(cone.get_intersection(&above).is_none());
+        let hit = cone.get_intersection(&through).unwrap();
+        assert_approx_eq!(hit.distance(), 5.0);
+        assert!(hit.surface_normal().x.is_finite());
+        assert_approx_eq!(hit.surface_normal().magnitude(), 1.0);
+    }
+
+    #[test]
+    fn test_miss_beside_the_cone() {
+        let cone = Cone::create(1.0, 2.0);
+        let ray = Ray::create(
+            Point::new(-5.0, 1.0, 0.6),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        assert!(cone.get_intersection(&ray).is_none());
+    }
+
+    #[test]
+    fn test_world_position_is_honored() {
+        let obj: Object = ObjectBuilder::create_for(Cone::create(1.0, 2.0))
+            .at_position(Point::new(3.0, -1.0, 0.0))
+            .into();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        let hit = obj.intersect(&ray).unwrap();
+
+        assert_approx_eq!(hit.distance(), 2.5);
+    }
+}
@@ -0,0 +1,275 @@
+use objects::{BoundingBox, Structure, Transformation, WorldPosition};
+use raycast::{Intersection, Ray};
+
+/// one operand of a csg operation, placed relative to the node it belongs to.
+/// operands have to be closed solids, whether a ray starts inside of them is derived from the
+/// number of surface crossings ahead of it.
+pub struct CsgChild {
+    structure: Box<dyn Structure + Send + Sync>,
+    transformation: Transformation,
+}
+
+impl CsgChild {
+    /// an error if the position scales an axis by zero
+    pub fn new<S: Structure + Send + Sync + 'static>(
+        structure: S,
+        position: &WorldPosition,
+    ) -> Result<CsgChild, String> {
+        Ok(CsgChild {
+            structure: Box::new(structure),
+            transformation: Transformation::from_position(position)?,
+        })
+    }
+
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        let (local_ray, length) = self.transformation.ray_to_object(ray);
+        self.structure
+            .get_intersections(&local_ray)
+            .iter()
+            .map(|local| self.transformation.intersection_to_world(local, length))
+            .collect()
+    }
+
+    fn traversal_cost(&self, ray: &Ray) -> usize {
+        let (local_ray, _) = self.transformation.ray_to_object(ray);
+        self.structure.traversal_cost(&local_ray)
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.structure
+            .bounds()
+            .map(|bounds| self.transformation.bounds_to_world(&bounds))
+    }
+}
+
+/// combines two solids into one by their volumes
+pub enum CsgNode {
+    Union(CsgChild, CsgChild),
+    Intersection(CsgChild, CsgChild),
+    /// the first solid with the second one cut out of it
+    Difference(CsgChild, CsgChild),
+}
+
+impl CsgNode {
+    fn children(&self) -> (&CsgChild, &CsgChild) {
+        match *self {
+            CsgNode::Union(ref a, ref b) => (a, b),
+            CsgNode::Intersection(ref a, ref b) => (a, b),
+            CsgNode::Difference(ref a, ref b) => (a, b),
+        }
+    }
+
+    fn contains(&self, inside_a: bool, inside_b: bool) -> bool {
+        match *self {
+            CsgNode::Union(..) => inside_a || inside_b,
+            CsgNode::Intersection(..) => inside_a && inside_b,
+            CsgNode::Difference(..) => inside_a && !inside_b,
+        }
+    }
+}
+
+impl Structure for CsgNode {
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.get_intersections(ray).into_iter().next()
+    }
+
+    /// walks the crossings of both children in order and keeps those where the ray moves into
+    /// or out of the combined volume.
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        let (a, b) = self.children();
+        let hits_a = a.get_intersections(ray);
+        let hits_b = b.get_intersections(ray);
+        let mut inside_a = hits_a.len() % 2 == 1;
+        let mut inside_b = hits_b.len() % 2 == 1;
+        let mut inside = self.contains(inside_a, inside_b);
+
+        let mut hits_a = hits_a.into_iter().peekable();
+        let mut hits_b = hits_b.into_iter().peekable();
+        let mut result = vec![];
+        loop {
+            let from_a = match (hits_a.peek(), hits_b.peek()) {
+                (Some(hit_a), Some(hit_b)) => hit_a.distance() <= hit_b.distance(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            let hit = if from_a {
+                inside_a = !inside_a;
+                hits_a.next().unwrap()
+            } else {
+                inside_b = !inside_b;
+                hits_b.next().unwrap()
+            };
+
+            let now_inside = self.contains(inside_a, inside_b);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            // the surface of a cut out solid faces into it
+            let hit = match *self {
+                CsgNode::Difference(..) if !from_a => Intersection::new(
+                    hit.distance(),
+                    hit.hit_point(),
+                    hit.texture_coord(),
+                    -hit.surface_normal(),
+                ),
+                _ => hit,
+            };
+            result.push(hit);
+        }
+
+        result
+    }
+
+    fn traversal_cost(&self, ray: &Ray) -> usize {
+        let (a, b) = self.children();
+        a.traversal_cost(ray) + b.traversal_cost(ray)
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        let (a, b) = self.children();
+        match *self {
+            CsgNode::Union(..) => match (a.bounds(), b.bounds()) {
+                (Some(a), Some(b)) => Some(a.union(&b)),
+                _ => None,
+            },
+            // the result lies within either child
+            CsgNode::Intersection(..) => a.bounds().or_else(|| b.bounds()),
+            CsgNode::Difference(..) => a.bounds(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{One, Quaternion};
+    use objects::{CsgChild, CsgNode, Mesh, Sphere, Structure, WorldPosition};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point, Scale};
+    use wavefront_obj::obj;
+
+    fn at(position: Point) -> WorldPosition {
+        WorldPosition {
+            position,
+            rotation: Quaternion::one(),
+            scale: Scale::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn bitten_sphere() -> CsgNode {
+        CsgNode::Difference(
+            CsgChild::new(Sphere::create(2.0), &at(Point::new(0.0, 0.0, 0.0))).unwrap(),
+            CsgChild::new(Sphere::create(1.0), &at(Point::new(0.0, 0.0, 2.0))).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_difference_cuts_a_concave_bite() {
+        let node = bitten_sphere();
+        let center = Ray::create(
+            Point::new(0.0, 0.0, 10.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+        let off_center = Ray::create(
+            Point::new(0.5, 0.0, 10.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let hit = node.get_intersection(&center).unwrap();
+        let side = node.get_intersection(&off_center).unwrap();
+
+        // the bite's floor lies where the small sphere ends, facing back out of the cut
+        assert_approx_eq!(hit.distance(), 9.0);
+        assert_approx_eq!(hit.surface_normal().z, 1.0);
+        // on a convex surface the normal would lean away from the axis
+        assert!(side.surface_normal().x < 0.0);
+    }
+
+    #[test]
+    fn test_difference_keeps_the_untouched_surface() {
+        let node = bitten_sphere();
+        let ray = Ray::create(
+            Point::new(10.0, 0.0, 0.0),
+            Direction::new(-1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        let hits = node.get_intersections(&ray);
+
+        assert_eq!(hits.len(), 2);
+        assert_approx_eq!(hits[0].distance(), 8.0);
+        assert_approx_eq!(hits[1].distance(), 12.0);
+    }
+
+    #[test]
+    fn test_union_and_intersection_of_overlapping_spheres() {
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 10.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+        let children = || {
+            (
+                CsgChild::new(Sphere::create(1.0), &at(Point::new(0.0, 0.0, 0.5))).unwrap(),
+                CsgChild::new(Sphere::create(1.0), &at(Point::new(0.0, 0.0, -0.5))).unwrap(),
+            )
+        };
+
+        let (a, b) = children();
+        let union = CsgNode::Union(a, b).get_intersections(&ray);
+        let (a, b) = children();
+        let intersection = CsgNode::Intersection(a, b).get_intersections(&ray);
+
+        assert_eq!(union.len(), 2);
+        assert_approx_eq!(union[0].distance(), 8.5);
+        assert_approx_eq!(union[1].distance(), 11.5);
+        assert_eq!(intersection.len(), 2);
+        assert_approx_eq!(intersection[0].distance(), 9.5);
+        assert_approx_eq!(intersection[1].distance(), 10.5);
+    }
+
+    #[test]
+    fn test_mesh_cube_is_a_closed_solid() {
+        let cube = || {
+            let set = obj::parse(String::from(include_str!("../../cube.obj"))).unwrap();
+            Mesh::create(set.objects.into_iter().next().unwrap())
+        };
+        let big = WorldPosition {
+            scale: Scale::new(2.0, 2.0, 2.0),
+            ..at(Point::new(0.0, 0.0, 0.0))
+        };
+        // a bite out of the middle of the front face
+        let node = CsgNode::Difference(
+            CsgChild::new(cube(), &big).unwrap(),
+            CsgChild::new(Sphere::create(0.5), &at(Point::new(0.0, 0.0, 1.0))).unwrap(),
+        );
+        let toward_front = |x: f64| {
+            Ray::create(
+                Point::new(x, 0.0, 10.0),
+                Direction::new(0.0, 0.0, -1.0),
+                RayType::Prime,
+            )
+        };
+
+        // the center ray runs along the diagonal the front and back faces are split at
+        let through = cube().get_intersections(&toward_front(0.0));
+        let bite = node.get_intersections(&toward_front(0.0));
+        let beside = node.get_intersections(&toward_front(0.8));
+
+        assert_eq!(through.len(), 2);
+        assert_approx_eq!(through[0].distance(), 9.5);
+        assert_approx_eq!(through[1].distance(), 10.5);
+        assert_eq!(bite.len(), 2);
+        assert_approx_eq!(bite[0].distance(), 9.5);
+        assert_approx_eq!(bite[0].surface_normal().z, 1.0);
+        assert_approx_eq!(bite[1].distance(), 11.0);
+        assert_eq!(beside.len(), 2);
+        assert_approx_eq!(beside[0].distance(), 9.0);
+        assert_approx_eq!(beside[1].distance(), 11.0);
+    }
+}
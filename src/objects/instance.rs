@@ -0,0 +1,136 @@
+use objects::{BoundingBox, Structure, SurfacePatch};
+use raycast::{Intersection, Ray};
+
+use std::sync::Arc;
+
+/// a placement of geometry that is shared with other objects, e.g. the same mesh standing in
+/// the scene many times. the owning `Object` holds this placement's position, the triangles
+/// and their bvh exist only once.
+pub struct Instance<S: Structure> {
+    shared: Arc<S>,
+}
+
+impl<S: Structure> Instance<S> {
+    pub fn of(shared: &Arc<S>) -> Instance<S> {
+        Instance {
+            shared: shared.clone(),
+        }
+    }
+}
+
+impl<S: Structure> Structure for Instance<S> {
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.shared.get_intersection(ray)
+    }
+
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        self.shared.get_intersections(ray)
+    }
+
+    fn get_intersection_packet(&self, rays: &[Ray]) -> Vec<Option<Intersection>> {
+        self.shared.get_intersection_packet(rays)
+    }
+
+    fn traversal_cost(&self, ray: &Ray) -> usize {
+        self.shared.traversal_cost(ray)
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.shared.bounds()
+    }
+
+    fn surface_patches(&self, count: usize) -> Vec<SurfacePatch> {
+        self.shared.surface_patches(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use objects::{Instance, Mesh, ObjectBuilder, Structure};
+    use raycast::{Intersection, Ray, RayType};
+    use scene::SceneBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use types::{Direction, Point};
+    use wavefront_obj::obj;
+
+    /// a unit square facing the camera
+    fn square() -> Mesh {
+        let source = "o square\n\
+                      v -0.5 -0.5 0\nv 0.5 -0.5 0\nv 0.5 0.5 0\nv -0.5 0.5 0\n\
+                      f 1 2 3\nf 1 3 4\n";
+        let object = obj::parse(String::from(source)).unwrap().objects.remove(0);
+        Mesh::create(object)
+    }
+
+    #[test]
+    fn test_instances_share_mesh_and_render_at_their_positions() {
+        let mesh = Arc::new(square());
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Instance::of(&mesh))
+                    .at_position(Point::new(-2.0, 0.0, -5.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Instance::of(&mesh))
+                    .at_position(Point::new(2.0, 0.0, -10.0))
+                    .into(),
+            )
+            .finish();
+        let toward = |x: f64, z: f64| {
+            Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(x, 0.0, z),
+                RayType::Prime,
+            )
+        };
+
+        let left = scene.trace(&toward(-2.0, -5.0)).unwrap();
+        let right = scene.trace(&toward(2.0, -10.0)).unwrap();
+
+        assert_eq!(Arc::strong_count(&mesh), 3);
+        assert_eq!(left.object_id(), 0);
+        assert_eq!(right.object_id(), 1);
+        assert_approx_eq!(left.hit_point().x, -2.0);
+        assert_approx_eq!(left.hit_point().z, -5.0);
+        assert_approx_eq!(right.hit_point().x, 2.0);
+        assert_approx_eq!(right.hit_point().z, -10.0);
+        assert!(scene.trace(&toward(0.0, -1.0)).is_none());
+    }
+
+    /// counts the packets it is asked to intersect
+    struct PacketCounter {
+        packets: AtomicUsize,
+    }
+
+    impl Structure for PacketCounter {
+        fn get_intersection(&self, _ray: &Ray) -> Option<Intersection> {
+            None
+        }
+
+        fn get_intersection_packet(&self, rays: &[Ray]) -> Vec<Option<Intersection>> {
+            self.packets.fetch_add(1, Ordering::SeqCst);
+            rays.iter().map(|_| None).collect()
+        }
+    }
+
+    #[test]
+    fn test_instances_pass_packets_on_whole() {
+        let counter = Arc::new(PacketCounter {
+            packets: AtomicUsize::new(0),
+        });
+        let ray = || {
+            Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(0.0, 0.0, -1.0),
+                RayType::Prime,
+            )
+        };
+
+        let hits = Instance::of(&counter).get_intersection_packet(&[ray(), ray()]);
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(counter.packets.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,44 +1,267 @@
 use cgmath::prelude::*;
-use objects::{Sphere, Structure, TextureCoords, WorldPosition};
-use raycast::{Intersection, Ray, RayType};
-use std::cmp::{max, min};
-use types::{Direction, Point, Scale};
+use objects::{BoundingBox, Coloration, Structure, TextureCoords, Transformation};
+use raycast::{Intersection, Ray};
+use std::io::{self, Write};
+use std::sync::Arc;
+use types::{Direction, Point, DETERMINANT_EPSILON};
 use wavefront_obj::obj;
 
-#[derive(Debug, Clone)]
-struct BoundingBox {
-    min: Point,
-    max: Point,
+/// an axis of the obj file, optionally flipped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    NegX,
+    NegY,
+    NegZ,
 }
 
-impl BoundingBox {
-    pub fn intersects(&self, ray: &Ray, position: &WorldPosition) -> bool {
-        let pmin = position.translate(self.min);
-        let pmax = position.translate(self.max);
+impl Axis {
+    fn pick(&self, v: &obj::Vertex) -> f64 {
+        match *self {
+            Axis::X => v.x,
+            Axis::Y => v.y,
+            Axis::Z => v.z,
+            Axis::NegX => -v.x,
+            Axis::NegY => -v.y,
+            Axis::NegZ => -v.z,
+        }
+    }
 
-        let tx1 = (pmin.x - ray.origin.x) * ray.inv_direction.x;
-        let tx2 = (pmax.x - ray.origin.x) * ray.inv_direction.x;
+    fn unit(&self) -> Direction {
+        match *self {
+            Axis::X => Direction::new(1.0, 0.0, 0.0),
+            Axis::Y => Direction::new(0.0, 1.0, 0.0),
+            Axis::Z => Direction::new(0.0, 0.0, 1.0),
+            Axis::NegX => Direction::new(-1.0, 0.0, 0.0),
+            Axis::NegY => Direction::new(0.0, -1.0, 0.0),
+            Axis::NegZ => Direction::new(0.0, 0.0, -1.0),
+        }
+    }
+}
 
-        let mut tmin = tx1.min(tx2);
-        let mut tmax = tx1.max(tx2);
+/// which axis of the obj file ends up on each axis of the mesh. tools disagree on which way
+/// is up and on handedness, this corrects that without editing the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisRemap {
+    pub x: Axis,
+    pub y: Axis,
+    pub z: Axis,
+}
 
-        let ty1 = (pmin.y - ray.origin.y) * ray.inv_direction.y;
-        let ty2 = (pmax.y - ray.origin.y) * ray.inv_direction.y;
+impl AxisRemap {
+    pub fn identity() -> AxisRemap {
+        AxisRemap {
+            x: Axis::X,
+            y: Axis::Y,
+            z: Axis::Z,
+        }
+    }
 
-        tmin = tmin.max(ty1.min(ty2));
-        tmax = tmax.min(ty1.max(ty2));
+    /// turns a file with y pointing up into one with z pointing up
+    pub fn y_up_to_z_up() -> AxisRemap {
+        AxisRemap {
+            x: Axis::X,
+            y: Axis::NegZ,
+            z: Axis::Y,
+        }
+    }
 
-        let tz1 = (pmin.z - ray.origin.z) * ray.inv_direction.z;
-        let tz2 = (pmax.z - ray.origin.z) * ray.inv_direction.z;
+    /// turns a file with z pointing up, as many cad tools write them, into the y up space
+    /// of the renderer
+    pub fn z_up_to_y_up() -> AxisRemap {
+        AxisRemap {
+            x: Axis::X,
+            y: Axis::Z,
+            z: Axis::NegY,
+        }
+    }
+
+    fn apply(&self, v: &obj::Vertex) -> obj::Vertex {
+        obj::Vertex {
+            x: self.x.pick(v),
+            y: self.y.pick(v),
+            z: self.z.pick(v),
+        }
+    }
+
+    /// mirroring turns the winding of every triangle around
+    fn flips_handedness(&self) -> bool {
+        let (x, y, z) = (self.x.unit(), self.y.unit(), self.z.unit());
+        x.dot(y.cross(z)) < 0.0
+    }
+}
 
-        tmin = tmin.max(tz1.min(tz2));
-        tmax = tmax.min(tz1.max(tz2));
+/// moves the surface of a mesh along its normals, by the brightness of a height map
+#[derive(Clone)]
+pub struct Displacement {
+    /// looked up at the obj texture coordinates, or the position within the face if the file
+    /// has none
+    pub map: Coloration,
+    /// the offset of the brightest height
+    pub scale: f64,
+    /// every edge of a face is split into this many parts before displacing
+    pub subdivisions: u32,
+}
 
-        tmax >= tmin && tmax >= 0.0
+impl Displacement {
+    fn height(&self, coords: &TextureCoords) -> f64 {
+        let color = self.map.color(coords);
+        (color.red + color.green + color.blue) as f64 / 3.0 * self.scale
     }
 }
 
-const EPSILON: f64 = 1e-13;
+/// how obj data is turned into a mesh
+#[derive(Clone)]
+pub struct MeshOptions {
+    pub axes: AxisRemap,
+    /// interpolate averaged vertex normals where the file has none, instead of shading
+    /// every face flat
+    pub smooth_normals: bool,
+    /// the number of triangles below which a bvh node is not split any further
+    pub leaf_size: usize,
+    /// the bvh is not split deeper than this, whatever the leaf size
+    pub max_depth: usize,
+    /// faces are subdivided and displaced before the bvh is built. the normals used come from
+    /// the file or `smooth_normals`, flat shaded faces pull apart at their edges
+    pub displacement: Option<Displacement>,
+    /// turn the normal of faces hit from behind towards the ray, so open meshes and the
+    /// inside of closed ones are shaded like their front. refraction needs the normals of
+    /// closed meshes to keep pointing outward, so this is off by default
+    pub double_sided: bool,
+    /// rays more parallel than this to a triangle miss it, see `DETERMINANT_EPSILON`
+    pub determinant_epsilon: f64,
+    /// called while the mesh is built, so loading a large model can show that it is not stuck
+    pub progress: Option<MeshProgressCallback>,
+}
+
+/// how far building a mesh got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshProgress {
+    /// triangles made from the faces of the obj so far
+    pub triangles: usize,
+    /// nodes of the bvh built so far
+    pub nodes: usize,
+}
+
+pub type MeshProgressCallback = Arc<dyn Fn(MeshProgress) + Send + Sync>;
+
+/// triangles are reported in batches of this many, every node of the tree on its own
+const PROGRESS_INTERVAL: usize = 1024;
+
+/// counts the work of a build and passes it on to the callback, if there is one
+struct BuildProgress<'a> {
+    callback: Option<&'a MeshProgressCallback>,
+    done: MeshProgress,
+}
+
+impl<'a> BuildProgress<'a> {
+    fn new(callback: Option<&'a MeshProgressCallback>) -> BuildProgress<'a> {
+        BuildProgress {
+            callback,
+            done: MeshProgress {
+                triangles: 0,
+                nodes: 0,
+            },
+        }
+    }
+
+    fn triangles(&mut self, count: usize) {
+        let before = self.done.triangles / PROGRESS_INTERVAL;
+        self.done.triangles += count;
+        if self.done.triangles / PROGRESS_INTERVAL > before {
+            self.report();
+        }
+    }
+
+    fn node(&mut self) {
+        self.done.nodes += 1;
+        self.report();
+    }
+
+    fn report(&self) {
+        if let Some(callback) = self.callback {
+            callback(self.done);
+        }
+    }
+}
+
+impl Default for MeshOptions {
+    fn default() -> MeshOptions {
+        MeshOptions {
+            axes: AxisRemap::identity(),
+            smooth_normals: false,
+            leaf_size: 250,
+            max_depth: 32,
+            displacement: None,
+            double_sided: false,
+            determinant_epsilon: DETERMINANT_EPSILON,
+            progress: None,
+        }
+    }
+}
+
+fn has_triangles(object: &obj::Object) -> bool {
+    object
+        .geometry
+        .iter()
+        .flat_map(|geom| geom.shapes.iter())
+        .any(|shape| matches!(shape.primitive, obj::Primitive::Triangle(..)))
+}
+
+/// the object with only the faces belonging to `group`
+fn only_group(object: &obj::Object, group: &str) -> obj::Object {
+    only_shapes(object, |shape| {
+        shape.groups.iter().any(|name| name == group)
+    })
+}
+
+/// the object split into a part for every group, named after it, and one with the faces
+/// outside of any group, named after the object. an object with faces in one group at most
+/// stays whole
+fn split_groups(object: &obj::Object) -> Vec<(String, obj::Object)> {
+    let mut groups: Vec<&str> = Vec::new();
+    for shape in object.geometry.iter().flat_map(|geom| geom.shapes.iter()) {
+        for name in &shape.groups {
+            if !groups.contains(&name.as_str()) {
+                groups.push(name);
+            }
+        }
+    }
+    if groups.len() < 2 {
+        return vec![(object.name.clone(), object.clone())];
+    }
+
+    let ungrouped = only_shapes(object, |shape| shape.groups.is_empty());
+    let mut parts = vec![(object.name.clone(), ungrouped)];
+    parts.extend(
+        groups
+            .iter()
+            .map(|&group| (group.to_string(), only_group(object, group))),
+    );
+    parts
+}
+
+/// the object with only the faces `keep` is true for
+fn only_shapes<F: Fn(&obj::Shape) -> bool>(object: &obj::Object, keep: F) -> obj::Object {
+    obj::Object {
+        geometry: object
+            .geometry
+            .iter()
+            .map(|geom| obj::Geometry {
+                material_name: geom.material_name.clone(),
+                shapes: geom
+                    .shapes
+                    .iter()
+                    .filter(|shape| keep(shape))
+                    .cloned()
+                    .collect(),
+            })
+            .collect(),
+        ..object.clone()
+    }
+}
 
 pub struct Triangle {
     p1: Point,
@@ -69,6 +292,64 @@ impl Triangle {
         }
     }
 
+    fn face_normal(&self) -> Direction {
+        (self.p2 - self.p1).cross(self.p3 - self.p1).normalize()
+    }
+
+    /// splits the triangle into `subdivisions`² smaller ones and moves every corner along the
+    /// normal interpolated there, by the height found at its texture coordinates
+    fn displace(
+        &self,
+        coords: Option<(TextureCoords, TextureCoords, TextureCoords)>,
+        displacement: &Displacement,
+    ) -> Vec<Triangle> {
+        let n = displacement.subdivisions.max(1);
+        let face_normal = self.face_normal();
+        let corner = |i: u32, j: u32| {
+            let (b1, b2) = (i as f64 / n as f64, j as f64 / n as f64);
+            let b0 = 1.0 - b1 - b2;
+            let point = Point::from_vec(
+                self.p1.to_vec() * b0 + self.p2.to_vec() * b1 + self.p3.to_vec() * b2,
+            );
+            let normal = match self.normals {
+                Some((n1, n2, n3)) => (n1 * b0 + n2 * b1 + n3 * b2).normalize(),
+                None => face_normal,
+            };
+            let uv = match coords {
+                Some((ref t1, ref t2, ref t3)) => TextureCoords {
+                    x: t1.x * b0 as f32 + t2.x * b1 as f32 + t3.x * b2 as f32,
+                    y: t1.y * b0 as f32 + t2.y * b1 as f32 + t3.y * b2 as f32,
+                },
+                None => TextureCoords {
+                    x: b1 as f32,
+                    y: b2 as f32,
+                },
+            };
+            point + normal * displacement.height(&uv)
+        };
+        let triangle = |p1, p2, p3| Triangle {
+            p1,
+            p2,
+            p3,
+            normals: None,
+        };
+
+        let mut triangles = Vec::new();
+        for i in 0..n {
+            for j in 0..n - i {
+                triangles.push(triangle(corner(i, j), corner(i + 1, j), corner(i, j + 1)));
+                if i + j + 1 < n {
+                    triangles.push(triangle(
+                        corner(i + 1, j),
+                        corner(i + 1, j + 1),
+                        corner(i, j + 1),
+                    ));
+                }
+            }
+        }
+        triangles
+    }
+
     fn center(&self) -> Point {
         Point {
             x: (self.p1.x + self.p2.x + self.p3.x) / 3.0,
@@ -98,12 +379,9 @@ impl Triangle {
         self
     }
 
-    pub fn surface_normal(&self, u: f64, v: f64, position: &WorldPosition) -> Direction {
+    pub fn surface_normal(&self, u: f64, v: f64) -> Direction {
         if let Some((n1, n2, n3)) = self.normals {
-            let n1 = position.rotation.rotate_vector(n1);
-            let n2 = position.rotation.rotate_vector(n2);
-            let n3 = position.rotation.rotate_vector(n3);
-            let w = (1.0 - u - v);
+            let w = 1.0 - u - v;
             (n1 * w + n2 * u + n3 * v).normalize()
         } else {
             let vec_a = self.p2 - self.p1;
@@ -113,23 +391,40 @@ impl Triangle {
         }
     }
 
+    /// the direction in which the u texture coordinate grows across the triangle,
+    /// or `None` if the texture coordinates are degenerate.
+    pub fn tangent(
+        &self,
+        uv1: &TextureCoords,
+        uv2: &TextureCoords,
+        uv3: &TextureCoords,
+    ) -> Option<Direction> {
+        let edge_1 = self.p2 - self.p1;
+        let edge_2 = self.p3 - self.p1;
+        let (du1, dv1) = ((uv2.x - uv1.x) as f64, (uv2.y - uv1.y) as f64);
+        let (du2, dv2) = ((uv3.x - uv1.x) as f64, (uv3.y - uv1.y) as f64);
+
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < DETERMINANT_EPSILON {
+            return None;
+        }
+
+        Some(((edge_1 * dv2 - edge_2 * dv1) / det).normalize())
+    }
+
     /// implements möller-trumbore
     /// http://webserver2.tecgraf.puc-rio.br/~mgattass/cg/trbRR/Fast%20MinimumStorage%20RayTriangle%20Intersection.pdf
-    pub fn intersects(
-        &self,
-        ray: &Ray,
-        position: &WorldPosition,
-    ) -> Option<(Direction, TextureCoords, f64)> {
-        let point_0 = position.translate(self.p1);
-        let point_1 = position.translate(self.p2);
-        let point_2 = position.translate(self.p3);
+    pub fn intersects(&self, ray: &Ray, epsilon: f64) -> Option<(Direction, TextureCoords, f64)> {
+        let point_0 = self.p1;
+        let point_1 = self.p2;
+        let point_2 = self.p3;
         let edge_1 = point_1 - point_0;
         let edge_2 = point_2 - point_0;
 
         let pvec = ray.direction.cross(edge_2);
 
         let det = edge_1.dot(pvec);
-        if det < EPSILON && det > -EPSILON {
+        if det.abs() < epsilon * edge_1.magnitude() * edge_2.magnitude() {
             return None;
         }
 
@@ -138,7 +433,7 @@ impl Triangle {
         let tvec = ray.origin - point_0;
         let u = tvec.dot(pvec) * inv_det;
 
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -150,21 +445,47 @@ impl Triangle {
         }
 
         let t = edge_2.dot(qvec) * inv_det;
+        if t < 0.0 {
+            return None;
+        }
 
-        let normal = self.surface_normal(u, v, position);
+        let normal = self.surface_normal(u, v);
 
         Some((normal, TextureCoords { x: 0.0, y: 0.0 }, t))
     }
 }
 
+/// only the triangles are kept, the obj data they were built from is dropped
 pub struct Mesh {
-    mesh: obj::Object,
     root: MeshTreeNode,
+    double_sided: bool,
+    determinant_epsilon: f64,
 }
 
+/// triangles keep their index in the mesh, so hits at the same distance are always resolved
+/// to the same triangle however the tree is split
 enum MeshTreeNode {
     Node(BoundingBox, Box<MeshTreeNode>, Box<MeshTreeNode>),
-    Leaf(BoundingBox, Vec<Triangle>),
+    Leaf(BoundingBox, Vec<IndexedTriangle>),
+}
+
+type IndexedTriangle = (usize, Triangle);
+
+type TriangleHit = (usize, (Direction, TextureCoords, f64));
+
+/// the closer of two hits, the lower triangle index on a tie
+fn nearer(a: Option<TriangleHit>, b: Option<TriangleHit>) -> Option<TriangleHit> {
+    match (a, b) {
+        (Some(x), Some(y)) => {
+            if (y.1).2 < (x.1).2 || ((y.1).2 == (x.1).2 && y.0 < x.0) {
+                Some(y)
+            } else {
+                Some(x)
+            }
+        }
+        (x, None) => x,
+        (None, y) => y,
+    }
 }
 
 #[inline]
@@ -190,8 +511,8 @@ enum SplitResult<T> {
 }
 
 impl SplitRule {
-    fn sort_to(&self, t: Triangle) -> SplitResult<Triangle> {
-        let center = t.center();
+    fn sort_to(&self, t: IndexedTriangle) -> SplitResult<IndexedTriangle> {
+        let center = t.1.center();
         match self {
             SplitRule::X(ref bp) => {
                 if center.x < *bp {
@@ -219,19 +540,76 @@ impl SplitRule {
 }
 
 impl MeshTreeNode {
-    pub fn create(triangles: Vec<Triangle>) -> MeshTreeNode {
+    #[cfg(test)]
+    fn create(triangles: Vec<Triangle>, leaf_size: usize, max_depth: usize) -> MeshTreeNode {
+        MeshTreeNode::create_reporting(
+            triangles,
+            leaf_size,
+            max_depth,
+            &mut BuildProgress::new(None),
+        )
+    }
+
+    fn create_reporting(
+        triangles: Vec<Triangle>,
+        leaf_size: usize,
+        max_depth: usize,
+        progress: &mut BuildProgress<'_>,
+    ) -> MeshTreeNode {
+        MeshTreeNode::build(
+            triangles.into_iter().enumerate().collect(),
+            leaf_size,
+            max_depth,
+            progress,
+        )
+    }
+
+    /// splits in the order the triangles are given, the same triangles always end up in the
+    /// same tree
+    fn build(
+        triangles: Vec<IndexedTriangle>,
+        leaf_size: usize,
+        max_depth: usize,
+        progress: &mut BuildProgress<'_>,
+    ) -> MeshTreeNode {
+        progress.node();
         let bb = MeshTreeNode::create_bounding_box(&triangles);
 
-        if triangles.len() <= 250 {
+        if triangles.len() <= leaf_size || max_depth == 0 {
             return MeshTreeNode::Leaf(bb, triangles);
         }
 
         let (left, right) = MeshTreeNode::split_triangles(&bb, triangles);
 
-        MeshTreeNode::Node(bb, Box::new(left), Box::new(right))
+        // all centers on one side of the plane (e.g. coincident triangles), splitting again
+        // would not get any further
+        if left.is_empty() || right.is_empty() {
+            let mut triangles = left;
+            triangles.extend(right);
+            return MeshTreeNode::Leaf(bb, triangles);
+        }
+
+        MeshTreeNode::Node(
+            bb,
+            Box::new(MeshTreeNode::build(
+                left,
+                leaf_size,
+                max_depth - 1,
+                progress,
+            )),
+            Box::new(MeshTreeNode::build(
+                right,
+                leaf_size,
+                max_depth - 1,
+                progress,
+            )),
+        )
     }
 
-    fn split_triangles(bb: &BoundingBox, triangles: Vec<Triangle>) -> (MeshTreeNode, MeshTreeNode) {
+    fn split_triangles(
+        bb: &BoundingBox,
+        triangles: Vec<IndexedTriangle>,
+    ) -> (Vec<IndexedTriangle>, Vec<IndexedTriangle>) {
         let delta_x = (bb.min.x - bb.max.x).abs();
         let delta_y = (bb.min.y - bb.max.y).abs();
         let delta_z = (bb.min.z - bb.max.z).abs();
@@ -255,15 +633,15 @@ impl MeshTreeNode {
                 SplitResult::Right(tri) => right.push(tri),
             }
         }
-        (MeshTreeNode::create(left), MeshTreeNode::create(right))
+        (left, right)
     }
 
-    fn create_bounding_box(triangles: &Vec<Triangle>) -> BoundingBox {
-        let first_vert = triangles.get(0).unwrap().p1;
-        let pmin = first_vert.clone();
-        let pmax = first_vert.clone();
+    fn create_bounding_box(triangles: &[IndexedTriangle]) -> BoundingBox {
+        let first_vert = triangles.first().unwrap().1.p1;
+        let pmin = first_vert;
+        let pmax = first_vert;
 
-        let (pmin, pmax) = triangles.iter().fold((pmin, pmax), |(pmin, pmax), t| {
+        let (pmin, pmax) = triangles.iter().fold((pmin, pmax), |(pmin, pmax), (_, t)| {
             (
                 Point {
                     x: min4(pmin.x, t.p1.x, t.p2.x, t.p3.x),
@@ -284,102 +662,901 @@ impl MeshTreeNode {
         }
     }
 
-    fn intersect(
-        &self,
-        ray: &Ray,
-        position: &WorldPosition,
-    ) -> Option<(Direction, TextureCoords, f64)> {
+    fn bounding_box(&self) -> &BoundingBox {
+        match self {
+            MeshTreeNode::Leaf(bbox, _) => bbox,
+            MeshTreeNode::Node(bbox, _, _) => bbox,
+        }
+    }
+
+    /// every triangle of the tree, in mesh order
+    fn triangles(&self) -> Vec<&Triangle> {
+        let mut triangles = Vec::new();
+        self.collect_triangles(&mut triangles);
+        triangles.sort_by_key(|&&(index, _)| index);
+        triangles
+            .into_iter()
+            .map(|(_, triangle)| triangle)
+            .collect()
+    }
+
+    fn collect_triangles<'a>(&'a self, triangles: &mut Vec<&'a IndexedTriangle>) {
+        match self {
+            MeshTreeNode::Leaf(_, leaf) => triangles.extend(leaf.iter()),
+            MeshTreeNode::Node(_, a, b) => {
+                a.collect_triangles(triangles);
+                b.collect_triangles(triangles);
+            }
+        }
+    }
+
+    /// the mesh indices of the triangles in every leaf, from left to right
+    #[cfg(test)]
+    fn leaves(&self) -> Vec<Vec<usize>> {
+        match self {
+            MeshTreeNode::Leaf(_, triangles) => vec![triangles.iter().map(|t| t.0).collect()],
+            MeshTreeNode::Node(_, a, b) => {
+                let mut leaves = a.leaves();
+                leaves.extend(b.leaves());
+                leaves
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn depth(&self) -> usize {
+        match self {
+            MeshTreeNode::Leaf(..) => 0,
+            MeshTreeNode::Node(_, a, b) => 1 + a.depth().max(b.depth()),
+        }
+    }
+
+    /// the number of bounding boxes and triangles a ray is tested against
+    fn traversal_cost(&self, ray: &Ray) -> usize {
         match self {
             MeshTreeNode::Leaf(bbox, triangles) => {
-                if !bbox.intersects(ray, position) {
+                if bbox.intersects(ray) {
+                    1 + triangles.len()
+                } else {
+                    1
+                }
+            }
+            MeshTreeNode::Node(bbox, a, b) => {
+                if bbox.intersects(ray) {
+                    1 + a.traversal_cost(ray) + b.traversal_cost(ray)
+                } else {
+                    1
+                }
+            }
+        }
+    }
+
+    fn intersect(&self, ray: &Ray, epsilon: f64) -> Option<TriangleHit> {
+        match self {
+            MeshTreeNode::Leaf(bbox, triangles) => {
+                if !bbox.intersects(ray) {
                     return None;
                 }
 
                 triangles
                     .iter()
-                    .filter_map(|triangle| triangle.intersects(ray, position))
-                    .min_by(|f1, f2| f1.2.partial_cmp(&f2.2).unwrap())
+                    .filter_map(|(index, triangle)| {
+                        triangle.intersects(ray, epsilon).map(|hit| (*index, hit))
+                    })
+                    .fold(None, |nearest, hit| nearer(nearest, Some(hit)))
             }
             MeshTreeNode::Node(bbox, a, b) => {
-                if !bbox.intersects(ray, position) {
+                if !bbox.intersects(ray) {
                     return None;
                 }
 
-                let left_match = a.intersect(ray, position);
-                let right_match = b.intersect(ray, position);
-
-                match (left_match, right_match) {
-                    (Some(x), None) => return Some(x),
-                    (None, Some(x)) => return Some(x),
-                    (None, None) => None,
-                    (Some(x), Some(y)) => {
-                        if x.2 < y.2 {
-                            Some(x)
-                        } else {
-                            Some(y)
-                        }
-                    }
+                nearer(a.intersect(ray, epsilon), b.intersect(ray, epsilon))
+            }
+        }
+    }
+
+    /// every triangle the ray hits, in no particular order
+    fn intersect_all(&self, ray: &Ray, epsilon: f64, hits: &mut Vec<TriangleHit>) {
+        match self {
+            MeshTreeNode::Leaf(bbox, triangles) => {
+                if bbox.intersects(ray) {
+                    hits.extend(triangles.iter().filter_map(|(index, triangle)| {
+                        triangle.intersects(ray, epsilon).map(|hit| (*index, hit))
+                    }));
                 }
             }
+            MeshTreeNode::Node(bbox, a, b) => {
+                if bbox.intersects(ray) {
+                    a.intersect_all(ray, epsilon, hits);
+                    b.intersect_all(ray, epsilon, hits);
+                }
+            }
+        }
+    }
+
+    /// `intersect` for the rays with the given indices, nearer hits replace the ones in
+    /// `nearest`. a subtree is skipped as soon as none of them hit its box. the indices of
+    /// the rays inside a box go into one of `buffers`, which are reused from node to node
+    fn intersect_packet(
+        &self,
+        rays: &[Ray],
+        active: &[usize],
+        epsilon: f64,
+        nearest: &mut [Option<TriangleHit>],
+        buffers: &mut Vec<Vec<usize>>,
+    ) {
+        match self {
+            MeshTreeNode::Leaf(..) => {
+                for &i in active {
+                    nearest[i] = nearer(nearest[i].take(), self.intersect(&rays[i], epsilon));
+                }
+            }
+            MeshTreeNode::Node(bbox, a, b) => {
+                let mut inside = buffers.pop().unwrap_or_default();
+                inside.clear();
+                inside.extend(
+                    active
+                        .iter()
+                        .cloned()
+                        .filter(|&i| bbox.intersects(&rays[i])),
+                );
+                if !inside.is_empty() {
+                    a.intersect_packet(rays, &inside, epsilon, nearest, buffers);
+                    b.intersect_packet(rays, &inside, epsilon, nearest, buffers);
+                }
+                buffers.push(inside);
+            }
         }
     }
 }
 
 impl Structure for Mesh {
-    fn get_intersection(&self, ray: &Ray, position: &WorldPosition) -> Option<Intersection> {
-        self.intersect(ray, position).map(|result| {
-            let (normal, texc, distance) = result;
-            let hit_point = ray.origin + ray.direction * distance;
-            Intersection::new(distance, hit_point, texc, normal)
-        })
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray)
+            .map(|result| self.to_intersection(ray, result))
+    }
+
+    /// every triangle hit, nearest first. a ray through an edge or a corner hits all the
+    /// triangles meeting there at the same distance, they count as one crossing so csg sees
+    /// the right number of them
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        let mut hits = Vec::new();
+        self.root
+            .intersect_all(ray, self.determinant_epsilon, &mut hits);
+        hits.sort_by(|x, y| {
+            let (a, b) = ((x.1).2, (y.1).2);
+            a.partial_cmp(&b).unwrap().then(x.0.cmp(&y.0))
+        });
+
+        let mut crossings: Vec<(Direction, TextureCoords, f64)> = Vec::with_capacity(hits.len());
+        for (_, hit) in hits {
+            let repeated = crossings.last().is_some_and(|last| {
+                (hit.2 - last.2).abs() <= 1e-9 * (1.0 + hit.2)
+                    && (hit.0.dot(ray.direction) > 0.0) == (last.0.dot(ray.direction) > 0.0)
+            });
+            if !repeated {
+                crossings.push(hit);
+            }
+        }
+        crossings
+            .into_iter()
+            .map(|hit| self.to_intersection(ray, hit))
+            .collect()
+    }
+
+    fn get_intersection_packet(&self, rays: &[Ray]) -> Vec<Option<Intersection>> {
+        let all: Vec<usize> = (0..rays.len()).collect();
+        let mut nearest = vec![None; rays.len()];
+        self.root.intersect_packet(
+            rays,
+            &all,
+            self.determinant_epsilon,
+            &mut nearest,
+            &mut Vec::new(),
+        );
+        nearest
+            .into_iter()
+            .zip(rays)
+            .map(|(result, ray)| result.map(|(_, result)| self.to_intersection(ray, result)))
+            .collect()
+    }
+
+    fn traversal_cost(&self, ray: &Ray) -> usize {
+        self.root.traversal_cost(ray)
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.root.bounding_box().clone())
     }
 }
 
 impl Mesh {
-    fn intersect(
-        &self,
-        ray: &Ray,
-        position: &WorldPosition,
-    ) -> Option<(Direction, TextureCoords, f64)> {
-        self.root.intersect(ray, position)
+    fn intersect(&self, ray: &Ray) -> Option<(Direction, TextureCoords, f64)> {
+        self.root
+            .intersect(ray, self.determinant_epsilon)
+            .map(|(_, hit)| hit)
+    }
+
+    fn to_intersection(&self, ray: &Ray, result: (Direction, TextureCoords, f64)) -> Intersection {
+        let (mut normal, texc, distance) = result;
+        if self.double_sided && normal.dot(ray.direction) > 0.0 {
+            normal = -normal;
+        }
+        Intersection::new(distance, ray.at(distance), texc, normal)
     }
 
     pub fn create(obj: obj::Object) -> Mesh {
-        Mesh {
-            root: MeshTreeNode::create(Mesh::build_triangles(&obj)),
-            mesh: obj,
+        Mesh::create_with_options(obj, MeshOptions::default())
+    }
+
+    /// writes the triangles the mesh renders, after remapping, smoothing and displacement, as
+    /// obj text in the world space of `transformation`, usually that of the object holding
+    /// the mesh. every corner gets its own vertex and normal, flat faces repeat their face normal
+    pub fn to_obj<W: Write>(
+        &self,
+        transformation: &Transformation,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let triangles = self.root.triangles();
+        writeln!(writer, "o mesh")?;
+        for triangle in &triangles {
+            for &p in &[triangle.p1, triangle.p2, triangle.p3] {
+                let p = transformation.point_to_world(p);
+                writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+            }
         }
+        for triangle in &triangles {
+            for &(u, v) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)] {
+                let n = transformation.normal_to_world(triangle.surface_normal(u, v));
+                writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+            }
+        }
+        for i in 0..triangles.len() {
+            let (a, b, c) = (3 * i + 1, 3 * i + 2, 3 * i + 3);
+            writeln!(writer, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        Ok(())
     }
 
-    fn build_triangles(obj: &obj::Object) -> Vec<Triangle> {
-        obj.geometry
+    /// a mesh for every object of the file that has faces, with the object's name. objects
+    /// with faces in several groups give a mesh for every group instead, named after the
+    /// group, and one for their faces outside of any group. a face in more than one group is
+    /// part of the mesh of each
+    pub fn from_obj_all(set: &obj::ObjSet, options: &MeshOptions) -> Vec<(String, Mesh)> {
+        set.objects
             .iter()
-            .map(|geom| {
-                geom.shapes
+            .flat_map(split_groups)
+            .filter(|(_, object)| has_triangles(object))
+            .map(|(name, object)| (name, Mesh::create_with_options(object, options.clone())))
+            .collect()
+    }
+
+    /// the object called `name`. if there is none, the faces of the first group called `name`
+    pub fn from_obj_named(set: &obj::ObjSet, name: &str, options: &MeshOptions) -> Option<Mesh> {
+        let object = set
+            .objects
+            .iter()
+            .find(|object| object.name == name && has_triangles(object))
+            .cloned()
+            .or_else(|| {
+                set.objects
                     .iter()
-                    .filter_map(|shape| match shape.primitive {
-                        obj::Primitive::Triangle(vidx1, vidx2, vidx3) => {
-                            let v1 = obj.vertices[vidx1.0];
-                            let v2 = obj.vertices[vidx2.0];
-                            let v3 = obj.vertices[vidx3.0];
-
-                            if vidx1.2.is_some() && vidx2.2.is_some() && vidx3.2.is_some() {
-                                let n1 = obj.normals[vidx1.2.unwrap()];
-                                let n2 = obj.normals[vidx2.2.unwrap()];
-                                let n3 = obj.normals[vidx3.2.unwrap()];
-                                Some(
-                                    Triangle::from_obj_vertices(&v1, &v2, &v3)
-                                        .with_normals(&n1, &n2, &n3),
-                                )
-                            } else {
-                                Some(Triangle::from_obj_vertices(&v1, &v2, &v3))
-                            }
-                        }
-                        _ => None,
-                    })
-                    .collect::<Vec<Triangle>>()
+                    .map(|object| only_group(object, name))
+                    .find(has_triangles)
+            })?;
+        Some(Mesh::create_with_options(object, options.clone()))
+    }
+
+    pub fn create_with_options(obj: obj::Object, options: MeshOptions) -> Mesh {
+        let triangles = Mesh::build_triangles(&obj, &options);
+        let mut progress = BuildProgress::new(options.progress.as_ref());
+        progress.done.triangles = triangles.len();
+        Mesh {
+            root: MeshTreeNode::create_reporting(
+                triangles,
+                options.leaf_size,
+                options.max_depth,
+                &mut progress,
+            ),
+            double_sided: options.double_sided,
+            determinant_epsilon: options.determinant_epsilon,
+        }
+    }
+
+    /// the parser already splits quads and larger polygons into fans of triangles, the points
+    /// and lines left over have no area and are skipped
+    fn build_triangles(obj: &obj::Object, options: &MeshOptions) -> Vec<Triangle> {
+        let mut progress = BuildProgress::new(options.progress.as_ref());
+        let remap = |v: &obj::Vertex| options.axes.apply(v);
+        let flip = options.axes.flips_handedness();
+        let faces: Vec<(obj::VTNIndex, obj::VTNIndex, obj::VTNIndex)> = obj
+            .geometry
+            .iter()
+            .flat_map(|geom| geom.shapes.iter())
+            .filter_map(|shape| match shape.primitive {
+                obj::Primitive::Triangle(vidx1, vidx2, vidx3) if flip => {
+                    Some((vidx1, vidx3, vidx2))
+                }
+                obj::Primitive::Triangle(vidx1, vidx2, vidx3) => Some((vidx1, vidx2, vidx3)),
+                _ => None,
+            })
+            .collect();
+        let smooth_normals = if options.smooth_normals {
+            Some(Mesh::vertex_normals(obj, &faces, options))
+        } else {
+            None
+        };
+
+        let coords = |vidx: obj::VTNIndex| {
+            vidx.1.map(|tidx| TextureCoords {
+                x: obj.tex_vertices[tidx].u as f32,
+                y: obj.tex_vertices[tidx].v as f32,
+            })
+        };
+
+        let triangles = faces
+            .iter()
+            .flat_map(|&(vidx1, vidx2, vidx3)| {
+                let v1 = remap(&obj.vertices[vidx1.0]);
+                let v2 = remap(&obj.vertices[vidx2.0]);
+                let v3 = remap(&obj.vertices[vidx3.0]);
+                let triangle = Triangle::from_obj_vertices(&v1, &v2, &v3);
+
+                let triangle = if let (Some(nidx1), Some(nidx2), Some(nidx3)) =
+                    (vidx1.2, vidx2.2, vidx3.2)
+                {
+                    let n1 = remap(&obj.normals[nidx1]);
+                    let n2 = remap(&obj.normals[nidx2]);
+                    let n3 = remap(&obj.normals[nidx3]);
+                    triangle.with_normals(&n1, &n2, &n3)
+                } else if let Some(ref normals) = smooth_normals {
+                    triangle.with_normals(&normals[vidx1.0], &normals[vidx2.0], &normals[vidx3.0])
+                } else {
+                    triangle
+                };
+
+                match options.displacement {
+                    Some(ref displacement) => {
+                        let face_coords = match (coords(vidx1), coords(vidx2), coords(vidx3)) {
+                            (Some(t1), Some(t2), Some(t3)) => Some((t1, t2, t3)),
+                            _ => None,
+                        };
+                        triangle.displace(face_coords, displacement)
+                    }
+                    None => vec![triangle],
+                }
+            })
+            .inspect(|_| progress.triangles(1))
+            .collect();
+        progress.report();
+        triangles
+    }
+
+    /// a normal for every vertex, averaging the normals of the faces around it weighted by
+    /// their area
+    fn vertex_normals(
+        obj: &obj::Object,
+        faces: &[(obj::VTNIndex, obj::VTNIndex, obj::VTNIndex)],
+        options: &MeshOptions,
+    ) -> Vec<obj::Normal> {
+        let mut sums = vec![Direction::zero(); obj.vertices.len()];
+        for &(vidx1, vidx2, vidx3) in faces {
+            let triangle = Triangle::from_obj_vertices(
+                &options.axes.apply(&obj.vertices[vidx1.0]),
+                &options.axes.apply(&obj.vertices[vidx2.0]),
+                &options.axes.apply(&obj.vertices[vidx3.0]),
+            );
+            // the length of the cross product is twice the area of the face
+            let weighted = (triangle.p2 - triangle.p1).cross(triangle.p3 - triangle.p1);
+            for vidx in &[vidx1.0, vidx2.0, vidx3.0] {
+                sums[*vidx] += weighted;
+            }
+        }
+
+        sums.into_iter()
+            .map(|sum| {
+                let normal = if sum.magnitude2() > 0.0 {
+                    sum.normalize()
+                } else {
+                    sum
+                };
+                obj::Vertex {
+                    x: normal.x,
+                    y: normal.y,
+                    z: normal.z,
+                }
             })
-            .flatten()
             .collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+    use image::{DynamicImage, GenericImage, Rgba};
+    use objects::mesh::MeshTreeNode;
+    use objects::{
+        Axis, AxisRemap, Coloration, Displacement, Mesh, MeshOptions, Structure, TextureCoords,
+        TextureFilter, Transformation, Triangle,
+    };
+    use raycast::{Ray, RayType};
+    use std::sync::{Arc, Mutex};
+    use types::{Color, Direction, Point, DETERMINANT_EPSILON};
+    use wavefront_obj::obj;
+
+    fn single_triangle() -> obj::Object {
+        let source = "o triangle\nv 1 2 3\nv 4 5 6\nv 7 8 10\nf 1 2 3\n";
+        obj::parse(String::from(source)).unwrap().objects.remove(0)
+    }
+
+    fn face_normal(triangle: &Triangle) -> Direction {
+        (triangle.p2 - triangle.p1)
+            .cross(triangle.p3 - triangle.p1)
+            .normalize()
+    }
+
+    #[test]
+    fn test_tangent_follows_u_direction() {
+        let triangle = Triangle {
+            p1: Point::new(0.0, 0.0, 0.0),
+            p2: Point::new(0.0, 0.0, -2.0),
+            p3: Point::new(0.0, 2.0, 0.0),
+            normals: None,
+        };
+
+        let tangent = triangle
+            .tangent(
+                &TextureCoords { x: 0.0, y: 0.0 },
+                &TextureCoords { x: 1.0, y: 0.0 },
+                &TextureCoords { x: 0.0, y: 1.0 },
+            )
+            .unwrap();
+
+        assert_approx_eq!(tangent.x, 0.0);
+        assert_approx_eq!(tangent.y, 0.0);
+        assert_approx_eq!(tangent.z, -1.0);
+    }
+
+    #[test]
+    fn test_y_up_to_z_up_swaps_vertex_components() {
+        let object = single_triangle();
+        let options = MeshOptions {
+            axes: AxisRemap::y_up_to_z_up(),
+            ..MeshOptions::default()
+        };
+
+        let plain = Mesh::build_triangles(&object, &MeshOptions::default());
+        let remapped = Mesh::build_triangles(&object, &options);
+
+        for (before, after) in [
+            (plain[0].p1, remapped[0].p1),
+            (plain[0].p2, remapped[0].p2),
+            (plain[0].p3, remapped[0].p3),
+        ]
+        .iter()
+        {
+            assert_eq!(*after, Point::new(before.x, -before.z, before.y));
+        }
+        // a rotation keeps the face pointing the same way relative to the geometry
+        let rotated = face_normal(&plain[0]);
+        assert_approx_eq!(face_normal(&remapped[0]).z, rotated.y);
+    }
+
+    #[test]
+    fn test_mirroring_keeps_face_normals_outward() {
+        let object = single_triangle();
+        let options = MeshOptions {
+            axes: AxisRemap {
+                x: Axis::NegX,
+                ..AxisRemap::identity()
+            },
+            ..MeshOptions::default()
+        };
+
+        let plain = face_normal(&Mesh::build_triangles(&object, &MeshOptions::default())[0]);
+        let mirrored = face_normal(&Mesh::build_triangles(&object, &options)[0]);
+
+        assert_approx_eq!(mirrored.x, -plain.x);
+        assert_approx_eq!(mirrored.y, plain.y);
+        assert_approx_eq!(mirrored.z, plain.z);
+    }
+
+    /// an octahedron, the lowest poly sphere there is, without any normals
+    fn octahedron() -> obj::Object {
+        let source = "o octahedron\n\
+                      v 1 0 0\nv -1 0 0\nv 0 1 0\nv 0 -1 0\nv 0 0 1\nv 0 0 -1\n\
+                      f 1 3 5\nf 3 2 5\nf 2 4 5\nf 4 1 5\n\
+                      f 3 1 6\nf 2 3 6\nf 4 2 6\nf 1 4 6\n";
+        obj::parse(String::from(source)).unwrap().objects.remove(0)
+    }
+
+    #[test]
+    fn test_smooth_normals_are_averaged_at_shared_vertices() {
+        let options = MeshOptions {
+            smooth_normals: true,
+            ..MeshOptions::default()
+        };
+
+        let flat = Mesh::build_triangles(&octahedron(), &MeshOptions::default());
+        let smooth = Mesh::build_triangles(&octahedron(), &options);
+
+        assert_eq!(flat.len(), 8);
+        for (flat, smooth) in flat.iter().zip(&smooth) {
+            let face = flat.surface_normal(0.0, 0.0);
+            let at_vertex = smooth.surface_normal(0.0, 0.0);
+            let outward = smooth.p1 - Point::new(0.0, 0.0, 0.0);
+
+            assert!(flat.normals.is_none());
+            assert!(face.dot(at_vertex) < 0.99);
+            assert_approx_eq!(at_vertex.dot(outward), 1.0);
+        }
+    }
+
+    /// a row of small triangles along the x axis
+    fn strip(count: usize) -> Vec<Triangle> {
+        (0..count)
+            .map(|i| {
+                let x = i as f64;
+                Triangle {
+                    p1: Point::new(x, 0.0, 0.0),
+                    p2: Point::new(x + 0.5, 0.0, 0.0),
+                    p3: Point::new(x, 1.0, 0.0),
+                    normals: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_double_sided_mesh_faces_rays_from_inside() {
+        let cube = obj::parse(String::from(include_str!("../../cube.obj")))
+            .unwrap()
+            .objects
+            .remove(0);
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, 1.0),
+            RayType::Prime,
+        );
+        let normal = |double_sided: bool| {
+            let mesh = Mesh::create_with_options(
+                cube.clone(),
+                MeshOptions {
+                    double_sided,
+                    ..MeshOptions::default()
+                },
+            );
+            mesh.get_intersection(&ray).unwrap().surface_normal()
+        };
+
+        assert_approx_eq!(normal(false).z, 1.0);
+        assert_approx_eq!(normal(true).z, -1.0);
+    }
+
+    #[test]
+    fn test_mesh_outlives_the_obj_it_was_built_from() {
+        let mesh = {
+            let set = obj::parse(String::from(include_str!("../../cube.obj"))).unwrap();
+            Mesh::from_obj_all(&set, &MeshOptions::default())
+                .remove(0)
+                .1
+        };
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 5.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let hit = mesh.get_intersection(&ray).unwrap();
+
+        assert_approx_eq!(hit.hit_point().z, 0.5);
+        assert_eq!(mesh.root.triangles().len(), 12);
+    }
+
+    #[test]
+    fn test_quad_faces_are_split_into_triangles() {
+        let source = "o cube\n\
+                      v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+                      v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+                      f 1 2 3 4\nf 6 5 8 7\nf 5 1 4 8\nf 2 6 7 3\nf 4 3 7 8\nf 5 6 2 1\n\
+                      l 1 2\n";
+        let cube = obj::parse(String::from(source)).unwrap().objects.remove(0);
+        let mesh = Mesh::create(cube);
+        let ray = Ray::create(
+            Point::new(0.3, 0.6, 5.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        assert_eq!(mesh.root.triangles().len(), 12);
+        assert_approx_eq!(mesh.get_intersection(&ray).unwrap().distance(), 4.0);
+    }
+
+    #[test]
+    fn test_smaller_leaves_build_a_deeper_tree() {
+        let coarse = MeshTreeNode::create(strip(300), 250, 32);
+        let fine = MeshTreeNode::create(strip(300), 1, 32);
+        let capped = MeshTreeNode::create(strip(300), 1, 3);
+
+        assert_eq!(coarse.depth(), 1);
+        assert!(fine.depth() > coarse.depth());
+        assert_eq!(capped.depth(), 3);
+    }
+
+    #[test]
+    fn test_identical_triangles_end_up_in_one_leaf() {
+        let triangles = (0..300)
+            .map(|_| Triangle {
+                p1: Point::new(0.0, 0.0, 0.0),
+                p2: Point::new(1.0, 0.0, 0.0),
+                p3: Point::new(0.0, 1.0, 0.0),
+                normals: None,
+            })
+            .collect();
+
+        let tree = MeshTreeNode::create(triangles, 1, usize::MAX);
+
+        assert_eq!(tree.depth(), 0);
+    }
+
+    #[test]
+    fn test_builds_of_the_same_mesh_are_identical() {
+        let teapot = obj::parse(String::from(include_str!("../../teapot.obj")))
+            .unwrap()
+            .objects
+            .into_iter()
+            .find(|o| !o.vertices.is_empty())
+            .unwrap();
+        let options = MeshOptions::default();
+        let build = || {
+            MeshTreeNode::create(
+                Mesh::build_triangles(&teapot, &options),
+                options.leaf_size,
+                options.max_depth,
+            )
+        };
+
+        assert!(build().leaves().len() > 1);
+        assert_eq!(build().leaves(), build().leaves());
+    }
+
+    #[test]
+    fn test_progress_is_reported_while_building() {
+        let teapot = obj::parse(String::from(include_str!("../../teapot.obj")))
+            .unwrap()
+            .objects
+            .into_iter()
+            .find(|o| !o.vertices.is_empty())
+            .unwrap();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let options = MeshOptions {
+            progress: Some(Arc::new(move |progress| {
+                sink.lock().unwrap().push(progress)
+            })),
+            ..MeshOptions::default()
+        };
+
+        let mesh = Mesh::create_with_options(teapot, options);
+        let reports = reports.lock().unwrap();
+        let last = reports.last().unwrap();
+
+        assert!(reports.iter().filter(|p| p.nodes == 0).count() >= 2);
+        assert!(reports
+            .windows(2)
+            .all(|w| w[0].triangles <= w[1].triangles && w[0].nodes <= w[1].nodes));
+        assert_eq!(last.triangles, mesh.root.triangles().len());
+        assert!(last.nodes > 1);
+    }
+
+    #[test]
+    fn test_equally_distant_hits_go_to_the_first_triangle() {
+        // overlapping in the same plane, but with centers on either side of the split
+        let triangle = |x1: f64, x2: f64| Triangle {
+            p1: Point::new(x1, -1.0, -1.0),
+            p2: Point::new(x2, -1.0, -1.0),
+            p3: Point::new(x1, 1.0, -1.0),
+            normals: None,
+        };
+        let ray = Ray::create(
+            Point::new(0.0, -0.5, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let first_left = MeshTreeNode::create(vec![triangle(-2.0, 1.0), triangle(2.0, -1.0)], 1, 8);
+        let first_right =
+            MeshTreeNode::create(vec![triangle(2.0, -1.0), triangle(-2.0, 1.0)], 1, 8);
+
+        assert_eq!(first_left.leaves().len(), 2);
+        assert_eq!(
+            first_left.intersect(&ray, DETERMINANT_EPSILON).unwrap().0,
+            0
+        );
+        assert_eq!(
+            first_right.intersect(&ray, DETERMINANT_EPSILON).unwrap().0,
+            0
+        );
+    }
+
+    /// two objects, the second split into a left and a right group and a face outside both
+    fn two_objects() -> obj::ObjSet {
+        let source = "o near\n\
+                      v 0 0 -1\nv 1 0 -1\nv 0 1 -1\n\
+                      f 1 2 3\n\
+                      o far\n\
+                      v -3 0 -5\nv -2 0 -5\nv -3 1 -5\nv 2 0 -5\nv 3 0 -5\nv 2 1 -5\n\
+                      v 0 2 -5\nv 1 2 -5\nv 0 3 -5\n\
+                      f 10 11 12\n\
+                      g left\nf 4 5 6\n\
+                      g right\nf 7 8 9\n";
+        obj::parse(String::from(source)).unwrap()
+    }
+
+    #[test]
+    fn test_select_objects_and_groups_by_name() {
+        let set = two_objects();
+
+        let options = MeshOptions::default();
+
+        let all = Mesh::from_obj_all(&set, &options);
+        let far = Mesh::from_obj_named(&set, "far", &options)
+            .unwrap()
+            .bounds()
+            .unwrap();
+        let right = Mesh::from_obj_named(&set, "right", &options)
+            .unwrap()
+            .bounds()
+            .unwrap();
+
+        let names: Vec<&str> = all.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["near", "far", "left", "right"]);
+        let ungrouped = all[1].1.bounds().unwrap();
+        assert_approx_eq!(ungrouped.min.y, 2.0);
+        assert_approx_eq!(far.min.x, -3.0);
+        assert_approx_eq!(far.max.x, 3.0);
+        assert_approx_eq!(right.min.x, 2.0);
+        assert_approx_eq!(right.max.z, -5.0);
+        assert!(Mesh::from_obj_named(&set, "missing", &options).is_none());
+    }
+
+    #[test]
+    fn test_selected_meshes_use_the_options() {
+        let set = two_objects();
+        let options = MeshOptions {
+            axes: AxisRemap {
+                x: Axis::NegX,
+                ..AxisRemap::identity()
+            },
+            ..MeshOptions::default()
+        };
+
+        let right = Mesh::from_obj_named(&set, "right", &options)
+            .unwrap()
+            .bounds()
+            .unwrap();
+        let near = Mesh::from_obj_all(&set, &options)
+            .remove(0)
+            .1
+            .bounds()
+            .unwrap();
+
+        assert_approx_eq!(right.min.x, -3.0);
+        assert_approx_eq!(near.min.x, -1.0);
+    }
+
+    #[test]
+    fn test_exported_obj_reimports_the_same_triangles() {
+        let source = "o quad\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+        let object = obj::parse(String::from(source)).unwrap().objects.remove(0);
+        let options = MeshOptions {
+            displacement: Some(Displacement {
+                map: Coloration::Color(Color::from_rgb(1.0, 1.0, 1.0)),
+                scale: 0.5,
+                subdivisions: 2,
+            }),
+            ..MeshOptions::default()
+        };
+        let mesh = Mesh::create_with_options(object, options);
+
+        let mut exported = Vec::new();
+        let identity = Transformation::from_matrix(Matrix4::identity()).unwrap();
+        mesh.to_obj(&identity, &mut exported).unwrap();
+        let reimported = Mesh::create(
+            obj::parse(String::from_utf8(exported).unwrap())
+                .unwrap()
+                .objects
+                .remove(0),
+        );
+        let before = mesh.root.triangles();
+        let after = reimported.root.triangles();
+
+        assert_eq!(before.len(), 8);
+        assert_eq!(after.len(), before.len());
+        for (a, b) in before.iter().zip(&after) {
+            // the parser may start a face at another corner, the winding stays the same
+            assert_approx_eq!((a.center() - b.center()).magnitude(), 0.0);
+            assert_approx_eq!(face_normal(a).dot(face_normal(b)), 1.0);
+            assert_approx_eq!(b.surface_normal(0.2, 0.3).z, 1.0);
+        }
+        assert_approx_eq!(after[0].p1.z, 0.5);
+    }
+
+    #[test]
+    fn test_exported_obj_is_in_world_space() {
+        let source = "o face\nv 0 0 0\nv 0 1 0\nv 1 0 -1\nf 1 2 3\n";
+        let object = obj::parse(String::from(source)).unwrap().objects.remove(0);
+        let mesh = Mesh::create(object);
+        // squashing along x tilts the normal of the slanted face towards x
+        let transformation = Transformation::from_matrix(
+            Matrix4::from_translation(Vector3::new(0.0, 2.0, 0.0))
+                * Matrix4::from_nonuniform_scale(0.5, 1.0, 1.0),
+        )
+        .unwrap();
+
+        let mut exported = Vec::new();
+        mesh.to_obj(&transformation, &mut exported).unwrap();
+        let reimported = obj::parse(String::from_utf8(exported).unwrap())
+            .unwrap()
+            .objects
+            .remove(0);
+
+        // the mesh may start the face at another corner
+        let mut corners: Vec<(f64, f64, f64)> = reimported
+            .vertices
+            .iter()
+            .map(|v| (v.x, v.y, v.z))
+            .collect();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            corners,
+            vec![(0.0, 2.0, 0.0), (0.0, 3.0, 0.0), (0.5, 2.0, -1.0)]
+        );
+        for normal in &reimported.normals {
+            assert_approx_eq!(normal.x, -2.0 / 5f64.sqrt());
+            assert_approx_eq!(normal.y, 0.0);
+            assert_approx_eq!(normal.z, -1.0 / 5f64.sqrt());
+        }
+    }
+
+    #[test]
+    fn test_displacement_moves_vertices_along_the_normal() {
+        // the first two corners sit on the white texel, the third on the black one
+        let source = "o face\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                      vt 0.25 0.5\nvt 0.75 0.5\n\
+                      f 1/1 2/1 3/2\n";
+        let object = obj::parse(String::from(source)).unwrap().objects.remove(0);
+        let mut map = DynamicImage::new_rgba8(2, 1);
+        map.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+        map.put_pixel(1, 0, Rgba([0, 0, 0, 255]));
+        let options = MeshOptions {
+            displacement: Some(Displacement {
+                map: Coloration::Texture(Arc::new(map), TextureFilter::Nearest),
+                scale: 0.5,
+                subdivisions: 3,
+            }),
+            ..MeshOptions::default()
+        };
+
+        let plain = Mesh::build_triangles(&object, &MeshOptions::default());
+        let displaced = Mesh::build_triangles(&object, &options);
+        let corners: Vec<Point> = displaced
+            .iter()
+            .flat_map(|triangle| vec![triangle.p1, triangle.p2, triangle.p3])
+            .collect();
+        let height_at = |x: f64, y: f64| {
+            corners
+                .iter()
+                .find(|p| (p.x - x).abs() < 1e-9 && (p.y - y).abs() < 1e-9)
+                .unwrap()
+                .z
+        };
+
+        assert_eq!(displaced.len(), 9);
+        assert_approx_eq!(face_normal(&plain[0]).z, 1.0);
+        assert_approx_eq!(height_at(0.0, 0.0), 0.5);
+        assert_approx_eq!(height_at(1.0, 0.0), 0.5);
+        assert_approx_eq!(height_at(0.0, 1.0), 0.0);
+        assert!(corners.iter().all(|p| p.z >= 0.0 && p.z <= 0.5));
+    }
+}
@@ -1,20 +1,37 @@
 use cgmath::prelude::*;
-use cgmath::{Quaternion, Vector3};
-use image::{DynamicImage, GenericImage};
-use raycast::{Intersection, IntersectionResult, Ray};
-use types::{Color, Point, Scale};
+use cgmath::{Deg, Matrix4, Quaternion, SquareMatrix};
+use image::hdr::HDRDecoder;
+use image::{DynamicImage, GenericImage, ImageBuffer, ImageError, ImageResult, Rgb};
+use light::ALL_LAYERS;
+use raycast::{Intersection, IntersectionResult, Ray, RayType, Refraction, SurfaceProperties};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+use types::{Color, Direction, Point, Scale};
 
+use std::sync::Arc;
+
+pub mod bounds;
+pub mod cone;
+pub mod csg;
+pub mod instance;
 pub mod mesh;
 pub mod plane;
 pub mod quad;
 pub mod sphere;
+pub mod torus;
 
+pub use self::bounds::*;
+pub use self::cone::*;
+pub use self::csg::*;
+pub use self::instance::*;
 pub use self::mesh::*;
 pub use self::plane::*;
 pub use self::quad::*;
 pub use self::sphere::*;
+pub use self::torus::*;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct TextureCoords {
     pub x: f32,
     pub y: f32,
@@ -23,13 +40,62 @@ pub struct TextureCoords {
 #[derive(Clone, Debug)]
 pub enum SurfaceType {
     Diffuse,
-    Reflective { reflectivity: f32 },
+    Reflective {
+        reflectivity: f32,
+    },
+    /// a conductor, its reflections are tinted by the surface color
+    Metallic {
+        reflectivity: f32,
+    },
+    /// a transparent dielectric like glass or water
+    Refractive {
+        index: f32,
+        transparency: f32,
+        absorption: Color,
+    },
+    /// invisible except for the shadows falling on it, which it keeps as alpha so rendered
+    /// objects can be composited onto a photo
+    ShadowCatcher,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+/// linear float texels, for textures that hold more than the 0..1 range of 8 bit images
+pub type HdrImage = ImageBuffer<Rgb<f32>, Vec<f32>>;
+
+/// decodes a radiance .hdr image
+pub fn read_hdr<R: BufRead>(reader: R) -> ImageResult<HdrImage> {
+    let decoder = HDRDecoder::new(reader)?;
+    let metadata = decoder.metadata();
+    let texels = decoder.read_image_hdr()?;
+    let raw = texels
+        .iter()
+        .flat_map(|texel| texel.data.to_vec())
+        .collect();
+
+    ImageBuffer::from_raw(metadata.width, metadata.height, raw).ok_or_else(|| {
+        ImageError::FormatError(String::from("hdr image is smaller than its header"))
+    })
 }
 
 #[derive(Clone)]
 pub enum Coloration {
     Color(Color),
-    Texture(DynamicImage),
+    /// textures are shared, cloning a material does not copy the image
+    Texture(Arc<DynamicImage>, TextureFilter),
+    /// sampled without converting to 8 bits, values above 1 are kept
+    HdrTexture(Arc<HdrImage>, TextureFilter),
+    /// lines of `width` every `spacing` along both texture axes
+    Grid {
+        line: Color,
+        background: Color,
+        spacing: f32,
+        width: f32,
+    },
 }
 
 fn wrap(val: f32, bound: u32) -> u32 {
@@ -43,16 +109,122 @@ fn wrap(val: f32, bound: u32) -> u32 {
     }
 }
 
+fn wrap_texel(texel: i32, bound: u32) -> u32 {
+    let signed_bound = bound as i32;
+    let wrapped = texel % signed_bound;
+    if wrapped < 0 {
+        (wrapped + signed_bound) as u32
+    } else {
+        wrapped as u32
+    }
+}
+
+/// interpolates between the four texels surrounding the given coordinates.
+/// texel centers sit at (i + 0.5) / size, so the fraction is measured from there.
+fn sample_bilinear<F: Fn(u32, u32) -> Color>(
+    (width, height): (u32, u32),
+    coords: &TextureCoords,
+    get: F,
+) -> Color {
+    let fx = coords.x * width as f32 - 0.5;
+    let fy = coords.y * height as f32 - 0.5;
+    let x0 = fx.floor();
+    let y0 = fy.floor();
+    let tx = fx - x0;
+    let ty = fy - y0;
+
+    let texel = |x: i32, y: i32| get(wrap_texel(x, width), wrap_texel(y, height));
+
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+    let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+
+    top * (1.0 - ty) + bottom * ty
+}
+
+fn sample_texture<F: Fn(u32, u32) -> Color>(
+    size: (u32, u32),
+    coords: &TextureCoords,
+    filter: TextureFilter,
+    get: F,
+) -> Color {
+    match filter {
+        TextureFilter::Nearest => get(wrap(coords.x, size.0), wrap(coords.y, size.1)),
+        TextureFilter::Bilinear => sample_bilinear(size, coords, get),
+    }
+}
+
 impl Coloration {
+    /// the value at the coordinates as stored, for maps holding data like roughness or height
     pub fn color(&self, coords: &TextureCoords) -> Color {
-        match *self {
-            Coloration::Color(ref c) => c.clone(),
-            Coloration::Texture(ref tex) => {
-                let tex_x = wrap(coords.x, tex.width());
-                let tex_y = wrap(coords.y, tex.height());
+        self.lookup(coords, false)
+    }
+
+    /// the color at the coordinates, with 8 bit textures decoded from srgb to linear before
+    /// they are filtered. surface colors are looked up this way
+    pub fn linear_color(&self, coords: &TextureCoords) -> Color {
+        self.lookup(coords, true)
+    }
 
-                Color::from_rgba(tex.get_pixel(tex_x, tex_y))
+    fn lookup(&self, coords: &TextureCoords, srgb: bool) -> Color {
+        match *self {
+            Coloration::Color(ref c) => *c,
+            Coloration::Texture(ref tex, filter) => {
+                sample_texture(tex.dimensions(), coords, filter, |x, y| {
+                    let texel = match **tex {
+                        DynamicImage::ImageLuma8(ref gray) => {
+                            Color::from_pixel(gray.get_pixel(x, y))
+                        }
+                        DynamicImage::ImageLumaA8(ref gray) => {
+                            Color::from_pixel(gray.get_pixel(x, y))
+                        }
+                        _ => Color::from_rgba8(tex.get_pixel(x, y)),
+                    };
+                    if srgb {
+                        texel.decode_srgb()
+                    } else {
+                        texel
+                    }
+                })
+            }
+            Coloration::HdrTexture(ref tex, filter) => {
+                sample_texture(tex.dimensions(), coords, filter, |x, y| {
+                    let texel = tex.get_pixel(x, y).data;
+                    Color::from_rgb(texel[0], texel[1], texel[2])
+                })
             }
+            Coloration::Grid {
+                line,
+                background,
+                spacing,
+                width,
+            } => {
+                let to_line = |c: f32| {
+                    let t = (c / spacing).rem_euclid(1.0);
+                    t.min(1.0 - t) * spacing
+                };
+                if to_line(coords.x) < width / 2.0 || to_line(coords.y) < width / 2.0 {
+                    line
+                } else {
+                    background
+                }
+            }
+        }
+    }
+}
+
+/// a number that is either the same across a surface or read from the red channel of a map
+#[derive(Clone)]
+pub enum ScalarMap {
+    Value(f32),
+    Map(Coloration),
+}
+
+impl ScalarMap {
+    pub fn value(&self, coords: &TextureCoords) -> f32 {
+        match *self {
+            ScalarMap::Value(value) => value,
+            ScalarMap::Map(ref map) => map.color(coords).red,
         }
     }
 }
@@ -62,44 +234,237 @@ pub struct Material {
     pub color: Coloration,
     pub albedo: f32,
     pub surface: SurfaceType,
+    /// texture coordinates are multiplied by this before the lookup, 2 tiles a texture twice
+    pub uv_scale: TextureCoords,
+    /// added to the scaled texture coordinates
+    pub uv_offset: TextureCoords,
+    /// light given off by the surface itself. its alpha stays 0, the light adds to whatever
+    /// is behind it
+    pub emission: Color,
+    /// spreads reflections over a cone, 0 is a mirror and 1 about 45 degrees. sharp if not set
+    pub roughness: Option<ScalarMap>,
+    /// how much reflections take on the surface color, from 0 for dielectrics to 1 for
+    /// metals. overrides the tint of `SurfaceType::Metallic` if set
+    pub metalness: Option<ScalarMap>,
+    /// multiplies everything the surface reflects, a colored mirror without the metallic
+    /// model. reflections keep their color if not set
+    pub reflection_tint: Option<Color>,
+}
+
+/// a grey diffuse surface, what objects get without a material of their own
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            color: Coloration::Color(Color::from_rgb(0.5, 0.5, 0.5)),
+            albedo: 0.1,
+            surface: SurfaceType::Diffuse,
+            uv_scale: TextureCoords { x: 1.0, y: 1.0 },
+            uv_offset: TextureCoords { x: 0.0, y: 0.0 },
+            emission: Color::from_rgba(0.0, 0.0, 0.0, 0.0),
+            roughness: None,
+            metalness: None,
+            reflection_tint: None,
+        }
+    }
+}
+
+/// materials shared by name between the objects of a scene
+#[derive(Clone, Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Arc<Material>>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> MaterialLibrary {
+        MaterialLibrary::default()
+    }
+
+    /// replaces a material already registered under the same name
+    pub fn add<S: Into<String>>(&mut self, name: S, material: Material) {
+        self.materials.insert(name.into(), Arc::new(material));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Material>> {
+        self.materials.get(name).cloned()
+    }
+}
+
+/// albedo and reflectivity are energy fractions, anything outside [0, 1]
+/// would make a surface emit more light than it receives.
+fn unit_interval(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
 }
 
 impl Material {
+    /// the surface color at the given texture coordinates of the geometry
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        self.color.linear_color(&self.transform_uv(coords))
+    }
+
+    pub fn roughness_at(&self, coords: &TextureCoords) -> f32 {
+        self.roughness
+            .as_ref()
+            .map_or(0.0, |roughness| roughness.value(&self.transform_uv(coords)))
+    }
+
+    pub fn metalness_at(&self, coords: &TextureCoords) -> f32 {
+        match self.metalness {
+            Some(ref metalness) => metalness.value(&self.transform_uv(coords)),
+            None if matches!(self.surface, SurfaceType::Metallic { .. }) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    fn transform_uv(&self, coords: &TextureCoords) -> TextureCoords {
+        TextureCoords {
+            x: coords.x * self.uv_scale.x + self.uv_offset.x,
+            y: coords.y * self.uv_scale.y + self.uv_offset.y,
+        }
+    }
+
     pub fn new(color: Coloration, albedo: f32) -> Material {
         Material {
             color,
-            albedo,
-            surface: SurfaceType::Diffuse,
+            albedo: unit_interval(albedo),
+            ..Material::default()
         }
     }
 
     pub fn diffuse_color(color: Color, albedo: f32) -> Material {
+        Material::new(Coloration::Color(color), albedo)
+    }
+
+    pub fn reflective_color(color: Color, albedo: f32, refl: f32) -> Material {
         Material {
-            color: Coloration::Color(color),
-            albedo,
-            surface: SurfaceType::Diffuse,
+            surface: SurfaceType::Reflective {
+                reflectivity: unit_interval(refl),
+            },
+            ..Material::diffuse_color(color, albedo)
         }
     }
 
-    pub fn reflective_color(color: Color, albedo: f32, refl: f32) -> Material {
+    pub fn metallic_color(color: Color, albedo: f32, refl: f32) -> Material {
         Material {
-            color: Coloration::Color(color),
-            albedo,
-            surface: SurfaceType::Reflective { reflectivity: refl },
+            surface: SurfaceType::Metallic {
+                reflectivity: unit_interval(refl),
+            },
+            ..Material::diffuse_color(color, albedo)
         }
     }
 
-    pub fn diffuse_texture(image: DynamicImage, albedo: f32) -> Material {
+    /// glass and the like. the albedo is the diffuse part of the surface, lit like any
+    /// other diffuse surface beside what shines through
+    pub fn refractive_color(
+        color: Color,
+        albedo: f32,
+        index: f32,
+        transparency: f32,
+        absorption: Color,
+    ) -> Material {
         Material {
-            color: Coloration::Texture(image),
-            albedo,
-            surface: SurfaceType::Diffuse,
+            surface: SurfaceType::Refractive {
+                index,
+                transparency: unit_interval(transparency),
+                absorption,
+            },
+            ..Material::diffuse_color(color, albedo)
         }
     }
+
+    pub fn shadow_catcher() -> Material {
+        Material {
+            surface: SurfaceType::ShadowCatcher,
+            ..Material::diffuse_color(Color::from_rgb(0.0, 0.0, 0.0), 0.0)
+        }
+    }
+
+    /// a surface that glows in the given color, e.g. an area light. emissive objects with
+    /// surface patches light the scene like a `Light` does
+    pub fn emissive(color: Color, intensity: f32) -> Material {
+        Material {
+            emission: Color::from_rgba(
+                color.red * intensity,
+                color.green * intensity,
+                color.blue * intensity,
+                0.0,
+            ),
+            ..Material::diffuse_color(Color::from_rgb(0.0, 0.0, 0.0), 0.0)
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission.red > 0.0 || self.emission.green > 0.0 || self.emission.blue > 0.0
+    }
+
+    pub fn diffuse_texture<I: Into<Arc<DynamicImage>>>(image: I, albedo: f32) -> Material {
+        Material::filtered_texture(image, TextureFilter::Nearest, albedo)
+    }
+
+    /// a diffuse material textured with the png or jpeg image at `path`
+    pub fn textured_from_path<P: AsRef<Path>>(path: P, albedo: f32) -> ImageResult<Material> {
+        Ok(Material::diffuse_texture(::image::open(path)?, albedo))
+    }
+
+    pub fn filtered_texture<I: Into<Arc<DynamicImage>>>(
+        image: I,
+        filter: TextureFilter,
+        albedo: f32,
+    ) -> Material {
+        Material::new(Coloration::Texture(image.into(), filter), albedo)
+    }
+}
+
+/// a small piece of a surface, the parallelogram spanned by `u` and `v` around `center`.
+/// its normal is `u × v`.
+#[derive(Debug, Clone)]
+pub struct SurfacePatch {
+    pub center: Point,
+    pub u: Direction,
+    pub v: Direction,
+}
+
+impl SurfacePatch {
+    pub fn area(&self) -> f64 {
+        self.u.cross(self.v).magnitude()
+    }
+
+    pub fn normal(&self) -> Direction {
+        self.u.cross(self.v).normalize()
+    }
 }
 
+/// the geometry of an object, in its own coordinate system.
+/// rays are moved into object space by the owning `Object` before they get here.
 pub trait Structure {
-    fn get_intersection(&self, ray: &Ray, position: &WorldPosition) -> Option<Intersection>;
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection>;
+
+    /// every crossing of the surface ahead of the ray, nearest first. solids report where the
+    /// ray enters and leaves them, which is what csg combines.
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        self.get_intersection(ray).into_iter().collect()
+    }
+
+    /// the nearest hit of each ray of a bundle. structures with an acceleration structure
+    /// walk it once for all of them, the results are the same as for single rays.
+    fn get_intersection_packet(&self, rays: &[Ray]) -> Vec<Option<Intersection>> {
+        rays.iter().map(|ray| self.get_intersection(ray)).collect()
+    }
+
+    /// how many primitive and bounding volume tests finding the intersection takes
+    fn traversal_cost(&self, _ray: &Ray) -> usize {
+        1
+    }
+
+    /// a box around the geometry, `None` if it is unbounded like a plane
+    fn bounds(&self) -> Option<BoundingBox> {
+        None
+    }
+
+    /// about `count` patches covering the whole surface, used to sample emissive geometry.
+    /// shapes that can't be sampled return none and do not light the scene.
+    fn surface_patches(&self, _count: usize) -> Vec<SurfacePatch> {
+        vec![]
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -110,40 +475,302 @@ pub struct WorldPosition {
 }
 
 impl WorldPosition {
+    /// scales, rotates and moves a point from object into world space
     pub fn translate(&self, vec: Point) -> Point {
-        self.rotation.rotate_point(vec) * self.scale + self.position.to_vec()
+        self.position
+            + self
+                .rotation
+                .rotate_vector(vec.to_vec().mul_element_wise(self.scale))
+    }
+}
+
+/// the matrices between object and world space, computed once per object
+#[derive(Debug, Clone)]
+pub struct Transformation {
+    forward: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+    normal: Matrix4<f64>,
+}
+
+impl Transformation {
+    /// an error if a scale factor is zero, see `from_matrix`
+    pub fn from_position(position: &WorldPosition) -> Result<Transformation, String> {
+        Transformation::from_matrix(
+            Matrix4::from_translation(position.position.to_vec())
+                * Matrix4::from(position.rotation)
+                * Matrix4::from_nonuniform_scale(
+                    position.scale.x,
+                    position.scale.y,
+                    position.scale.z,
+                ),
+        )
+    }
+
+    /// any invertible affine transformation from object into world space. normals are moved
+    /// by the inverse transpose, which keeps them perpendicular to surfaces that are scaled
+    /// differently along each axis. an error if the matrix flattens space, or its
+    /// determinant is not a normal float
+    pub fn from_matrix(forward: Matrix4<f64>) -> Result<Transformation, String> {
+        let inverse = forward
+            .invert()
+            .filter(|_| forward.determinant().is_normal())
+            .ok_or_else(|| format!("the transformation {:?} is not invertible", forward))?;
+
+        Ok(Transformation {
+            forward,
+            inverse,
+            normal: inverse.transpose(),
+        })
+    }
+
+    /// moves the ray into object space. the direction is normalized again, the returned
+    /// factor converts object space distances back into world space ones.
+    pub fn ray_to_object(&self, ray: &Ray) -> (Ray, f64) {
+        let direction = self.inverse.transform_vector(ray.direction);
+        let length = direction.magnitude();
+        let local = Ray::create(
+            self.inverse.transform_point(ray.origin),
+            direction / length,
+            ray.ray_type,
+        );
+        (local, length)
+    }
+
+    pub fn intersection_to_world(&self, local: &Intersection, length: f64) -> Intersection {
+        Intersection::new(
+            local.distance() / length,
+            self.forward.transform_point(local.hit_point()),
+            local.texture_coord(),
+            self.normal_to_world(local.surface_normal()),
+        )
+    }
+
+    pub fn patch_to_world(&self, patch: &SurfacePatch) -> SurfacePatch {
+        SurfacePatch {
+            center: self.forward.transform_point(patch.center),
+            u: self.forward.transform_vector(patch.u),
+            v: self.forward.transform_vector(patch.v),
+        }
+    }
+
+    pub fn point_to_world(&self, point: Point) -> Point {
+        self.forward.transform_point(point)
+    }
+
+    pub fn bounds_to_world(&self, bounds: &BoundingBox) -> BoundingBox {
+        bounds.transform(&self.forward)
+    }
+
+    pub fn normal_to_world(&self, normal: Direction) -> Direction {
+        self.normal.transform_vector(normal).normalize()
+    }
+}
+
+/// which kinds of rays see an object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadows: bool,
+    /// reflection and refraction rays
+    pub reflections: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Visibility {
+        Visibility {
+            camera: true,
+            shadows: true,
+            reflections: true,
+        }
+    }
+}
+
+impl Visibility {
+    pub fn sees(&self, ray_type: RayType) -> bool {
+        match ray_type {
+            RayType::Prime => self.camera,
+            RayType::Shadow => self.shadows,
+            RayType::Reflection | RayType::Refraction => self.reflections,
+        }
     }
 }
 
 pub struct Object {
-    material: Material,
+    id: usize,
+    material: Arc<Material>,
+    /// false if the object was built without a material and shows the default one
+    own_material: bool,
+    visibility: Visibility,
+    light_layers: u32,
     position: WorldPosition,
-    structure: Box<Structure + Send + Sync>,
+    transformation: Transformation,
+    /// in world space, `None` for unbounded structures
+    bounding_sphere: Option<BoundingSphere>,
+    structure: Box<dyn Structure + Send + Sync>,
 }
 
 impl Object {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn position(&self) -> &WorldPosition {
+        &self.position
+    }
+
+    pub fn transformation(&self) -> &Transformation {
+        &self.transformation
+    }
+
+    pub fn with_id(mut self, id: usize) -> Object {
+        self.id = id;
+        self
+    }
+
+    /// gives the object `material` if it was built without a material of its own
+    pub fn with_default_material(mut self, material: &Arc<Material>) -> Object {
+        if !self.own_material {
+            self.material = material.clone();
+        }
+        self
+    }
+
+    pub fn is_visible_to(&self, ray: &Ray) -> bool {
+        self.visibility.sees(ray.ray_type)
+    }
+
+    pub fn bounding_sphere(&self) -> Option<&BoundingSphere> {
+        self.bounding_sphere.as_ref()
+    }
+
+    /// false if the ray surely misses the object, checked before the exact intersection
+    pub fn may_be_hit_by(&self, ray: &Ray) -> bool {
+        self.bounding_sphere
+            .as_ref()
+            .is_none_or(|sphere| sphere.intersects(ray))
+    }
+
+    pub fn is_visible_to_camera(&self) -> bool {
+        self.visibility.camera
+    }
+
+    /// the world space distance to the nearest hit, without looking up the material there
+    pub fn hit_distance(&self, ray: &Ray) -> Option<f64> {
+        let (local_ray, length) = self.transformation.ray_to_object(ray);
+        self.structure
+            .get_intersection(&local_ray)
+            .map(|local| local.distance() / length)
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Option<IntersectionResult> {
+        let (local_ray, length) = self.transformation.ray_to_object(ray);
         self.structure
-            .get_intersection(ray, &self.position)
-            .map(|intersection| {
-                IntersectionResult::create(
-                    &intersection,
-                    self.color_at(intersection.texture_coord()),
-                    self.material.albedo,
-                    self.reflectivity_at(intersection.texture_coord()),
-                )
-            })
+            .get_intersection(&local_ray)
+            .map(|local| self.to_result(&local, length))
+    }
+
+    /// `intersect` for each of the rays, the structure is traversed once for all of them
+    pub fn intersect_packet(&self, rays: &[&Ray]) -> Vec<Option<IntersectionResult>> {
+        let (local_rays, lengths): (Vec<Ray>, Vec<f64>) = rays
+            .iter()
+            .map(|ray| self.transformation.ray_to_object(ray))
+            .unzip();
+        self.structure
+            .get_intersection_packet(&local_rays)
+            .iter()
+            .zip(lengths)
+            .map(|(local, length)| local.as_ref().map(|local| self.to_result(local, length)))
+            .collect()
+    }
+
+    /// every surface crossing along the ray, nearest first
+    pub fn intersect_all(&self, ray: &Ray) -> Vec<IntersectionResult> {
+        let (local_ray, length) = self.transformation.ray_to_object(ray);
+        self.structure
+            .get_intersections(&local_ray)
+            .iter()
+            .map(|local| self.to_result(local, length))
+            .collect()
+    }
+
+    fn to_result(&self, local: &Intersection, length: f64) -> IntersectionResult {
+        let intersection = self.transformation.intersection_to_world(local, length);
+        IntersectionResult::create(
+            &intersection,
+            local.hit_point(),
+            self.id,
+            SurfaceProperties {
+                albedo: self.material.albedo,
+                color: self.color_at(intersection.texture_coord()),
+                reflectivity: self.reflectivity_at(intersection.texture_coord()),
+                metalness: self.material.metalness_at(&intersection.texture_coord()),
+                reflection_tint: self
+                    .material
+                    .reflection_tint
+                    .unwrap_or_else(|| Color::from_rgb(1.0, 1.0, 1.0)),
+                roughness: self.material.roughness_at(&intersection.texture_coord()),
+                refraction: self.refraction(),
+                light_layers: self.light_layers,
+                emission: self.material.emission,
+                shadow_catcher: matches!(self.material.surface, SurfaceType::ShadowCatcher),
+            },
+        )
+    }
+
+    pub fn traversal_cost(&self, ray: &Ray) -> usize {
+        let (local_ray, _) = self.transformation.ray_to_object(ray);
+        self.structure.traversal_cost(&local_ray)
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
     }
 
-    fn reflectivity_at(&self, texture_coordinates: TextureCoords) -> Option<f32> {
+    pub fn emission(&self) -> Color {
+        self.material.emission
+    }
+
+    /// patches of the surface in world space, for sampling the light an emissive object gives off
+    pub fn surface_patches(&self, count: usize) -> Vec<SurfacePatch> {
+        self.structure
+            .surface_patches(count)
+            .iter()
+            .map(|patch| self.transformation.patch_to_world(patch))
+            .collect()
+    }
+
+    /// the object's bounds in world space
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.structure
+            .bounds()
+            .map(|bounds| self.transformation.bounds_to_world(&bounds))
+    }
+
+    fn reflectivity_at(&self, _texture_coordinates: TextureCoords) -> Option<f32> {
         match self.material.surface {
             SurfaceType::Reflective { reflectivity } => Some(reflectivity),
+            SurfaceType::Metallic { reflectivity } => Some(reflectivity),
+            _ => None,
+        }
+    }
+
+    fn refraction(&self) -> Option<Refraction> {
+        match self.material.surface {
+            SurfaceType::Refractive {
+                index,
+                transparency,
+                absorption,
+            } => Some(Refraction {
+                index,
+                transparency,
+                absorption,
+            }),
             _ => None,
         }
     }
 
     fn color_at(&self, texture_coordinates: TextureCoords) -> Color {
-        self.material.color.color(&texture_coordinates)
+        self.material.color_at(&texture_coordinates)
     }
 }
 
@@ -152,44 +779,92 @@ where
     E: 'static,
 {
     fn from(builder: ObjectBuilder<E>) -> Self {
+        let position = WorldPosition {
+            position: builder.position,
+            rotation: builder.rotation,
+            scale: builder.scale,
+        };
+
+        let transformation = Transformation::from_position(&position)
+            .expect("scale_axes only accepts invertible scales");
+        let bounding_sphere = builder.structure.bounds().and_then(|bounds| {
+            BoundingSphere::around(
+                bounds
+                    .corners()
+                    .into_iter()
+                    .map(|p| transformation.forward.transform_point(p)),
+            )
+        });
+
         Object {
-            material: builder.material,
+            id: 0,
+            own_material: builder.material.is_some(),
+            material: builder
+                .material
+                .unwrap_or_else(|| Arc::new(Material::default())),
+            visibility: builder.visibility,
+            light_layers: builder.light_layers,
             structure: builder.structure,
-            position: WorldPosition {
-                position: builder.position,
-                rotation: builder.rotation,
-                scale: builder.scale,
-            },
+            transformation,
+            bounding_sphere,
+            position,
         }
     }
 }
 
 pub struct ObjectBuilder<E: Structure + Send + Sync> {
-    material: Material,
+    /// the scene's default material if not set
+    material: Option<Arc<Material>>,
+    visibility: Visibility,
+    light_layers: u32,
     structure: Box<E>,
     position: Point,
     rotation: Quaternion<f64>,
     scale: Scale,
 }
 
+impl ObjectBuilder<Plane> {
+    /// an infinite floor at `height` facing up, with dark lines every `spacing` world units
+    /// along x and z on a light gray ground
+    pub fn ground_grid(height: f64, spacing: f32) -> ObjectBuilder<Plane> {
+        let grid = Coloration::Grid {
+            line: Color::from_rgb(0.1, 0.1, 0.1),
+            background: Color::from_rgb(0.7, 0.7, 0.7),
+            spacing,
+            width: spacing * 0.04,
+        };
+        ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+            .at_position(Point::new(0.0, height, 0.0))
+            .with_material(Material::new(grid, 0.8))
+    }
+}
+
 impl<E: Structure + Send + Sync> ObjectBuilder<E> {
     pub fn create_for(object: E) -> ObjectBuilder<E> {
         ObjectBuilder {
-            material: Material {
-                color: Coloration::Color(Color::from_rgb(0.5, 0.5, 0.5)),
-                surface: SurfaceType::Diffuse,
-                albedo: 0.1,
-            },
+            material: None,
+            visibility: Visibility::default(),
+            light_layers: ALL_LAYERS,
             position: Point::new(0.0, 0.0, 0.0),
             rotation: Quaternion::one(),
             structure: Box::new(object),
-            scale: 1.0,
+            scale: Scale::new(1.0, 1.0, 1.0),
         }
     }
 
-    pub fn scale(mut self, scale: Scale) -> ObjectBuilder<E> {
+    /// scales the object by the same factor along every axis, an error if it is zero
+    pub fn scale(self, factor: f64) -> Result<ObjectBuilder<E>, String> {
+        self.scale_axes(Scale::new(factor, factor, factor))
+    }
+
+    /// stretches or squashes the object along its own axes, before it is rotated. an error if
+    /// a factor is zero or not finite, the object would have no inverse transformation
+    pub fn scale_axes(mut self, scale: Scale) -> Result<ObjectBuilder<E>, String> {
+        if !(scale.x * scale.y * scale.z).is_normal() {
+            return Err(format!("can't scale an object by {:?}", scale));
+        }
         self.scale = scale;
-        self
+        Ok(self)
     }
 
     pub fn rotation(mut self, rotation: Quaternion<f64>) -> ObjectBuilder<E> {
@@ -197,22 +872,72 @@ impl<E: Structure + Send + Sync> ObjectBuilder<E> {
         self
     }
 
+    /// rotates by `x` degrees around the x axis first, then by `y` around the y axis and
+    /// `z` around the z axis, all counterclockwise looking down the axis
+    pub fn rotation_euler(self, x: f64, y: f64, z: f64) -> ObjectBuilder<E> {
+        self.rotation(
+            Quaternion::from_angle_z(Deg(z))
+                * Quaternion::from_angle_y(Deg(y))
+                * Quaternion::from_angle_x(Deg(x)),
+        )
+    }
+
     pub fn at_position(mut self, position: Point) -> ObjectBuilder<E> {
         self.position = position;
         self
     }
 
     pub fn with_material(mut self, material: Material) -> ObjectBuilder<E> {
-        self.material = material;
+        self.material = Some(Arc::new(material));
+        self
+    }
+
+    /// shares the material registered as `name`, an error if there is none
+    pub fn with_material_named(
+        mut self,
+        library: &MaterialLibrary,
+        name: &str,
+    ) -> Result<ObjectBuilder<E>, String> {
+        self.material = Some(
+            library
+                .get(name)
+                .ok_or_else(|| format!("no material named {:?}", name))?,
+        );
+        Ok(self)
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> ObjectBuilder<E> {
+        self.visibility = visibility;
+        self
+    }
+
+    /// only lights on one of these layers shine on the object
+    pub fn light_layers(mut self, layers: u32) -> ObjectBuilder<E> {
+        self.light_layers = layers;
         self
     }
 }
 
 #[cfg(test)]
 mod test {
-    use cgmath::{Quaternion, Zero};
-    use objects::{Object, ObjectBuilder, Sphere, WorldPosition};
-    use types::Point;
+    use cgmath::{Deg, InnerSpace, Matrix4, One, Quaternion, Rotation, Rotation3, Transform};
+    use image::hdr::HDREncoder;
+    use image::{DynamicImage, GenericImage, ImageFormat, Rgb, Rgba};
+    use objects::{
+        read_hdr, Coloration, Material, Object, ObjectBuilder, ScalarMap, Sphere, SurfaceType,
+        TextureCoords, TextureFilter, Transformation, WorldPosition,
+    };
+    use raycast::{Ray, RayType};
+    use scene::SceneBuilder;
+    use std::sync::Arc;
+    use types::{Color, Direction, Point, Scale};
+
+    fn two_texel_image() -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(2, 1);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
+        image
+    }
 
     #[test]
     fn test_create_sphere() {
@@ -232,10 +957,326 @@ mod test {
                     y: 0.0,
                     z: 0.0
                 },
-                rotation: Quaternion::zero(),
-                scale: 1.0
+                rotation: Quaternion::one(),
+                scale: Scale::new(1.0, 1.0, 1.0)
             }
         );
         assert_eq!(obj.material.albedo, 0.1);
     }
+
+    #[test]
+    fn test_flat_scales_and_matrices_are_rejected() {
+        let builder = || ObjectBuilder::create_for(Sphere::create(1.0));
+
+        assert!(builder().scale(0.0).is_err());
+        assert!(builder().scale(-2.0).is_ok());
+        assert!(builder().scale_axes(Scale::new(1.0, 0.0, 1.0)).is_err());
+        assert!(builder()
+            .scale_axes(Scale::new(1.0, f64::INFINITY, 1.0))
+            .is_err());
+        assert!(
+            Transformation::from_matrix(Matrix4::from_nonuniform_scale(1.0, 1.0, 0.0)).is_err()
+        );
+        assert!(Transformation::from_position(&WorldPosition {
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Scale::new(0.0, 0.0, 0.0),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_bilinear_midpoint_averages_texels() {
+        let coloration = Coloration::Texture(Arc::new(two_texel_image()), TextureFilter::Bilinear);
+        let color = coloration.color(&TextureCoords { x: 0.5, y: 0.5 });
+
+        assert_approx_eq!(color.red, 0.5);
+        assert_approx_eq!(color.green, 0.0);
+        assert_approx_eq!(color.blue, 0.5);
+    }
+
+    #[test]
+    fn test_nearest_picks_single_texel() {
+        let coloration = Coloration::Texture(Arc::new(two_texel_image()), TextureFilter::Nearest);
+        let color = coloration.color(&TextureCoords { x: 0.25, y: 0.5 });
+
+        assert_approx_eq!(color.red, 1.0);
+        assert_approx_eq!(color.blue, 0.0);
+    }
+
+    #[test]
+    fn test_normals_stay_perpendicular_under_non_uniform_scale() {
+        let transformation = Transformation::from_matrix(
+            Matrix4::from_angle_z(Deg(30.0)) * Matrix4::from_nonuniform_scale(3.0, 1.0, 0.5),
+        )
+        .unwrap();
+        // a point on the unit sphere with its normal and a tangent
+        let normal = Direction::new(1.0, 1.0, 1.0).normalize();
+        let tangent = Direction::new(1.0, -1.0, 0.0).normalize();
+
+        let world_normal = transformation.normal_to_world(normal);
+        let world_tangent = transformation.forward.transform_vector(tangent);
+        let naive = transformation.forward.transform_vector(normal).normalize();
+
+        assert_approx_eq!(world_normal.dot(world_tangent), 0.0);
+        assert!(naive.dot(world_tangent).abs() > 0.1);
+    }
+
+    #[test]
+    fn test_hdr_texture_keeps_values_above_one() {
+        let mut encoded = Vec::new();
+        HDREncoder::new(&mut encoded)
+            .encode(&[Rgb([4.0, 0.5, 16.0]), Rgb([0.0, 0.0, 0.0])], 2, 1)
+            .unwrap();
+        let image = read_hdr(&encoded[..]).unwrap();
+
+        for &filter in &[TextureFilter::Nearest, TextureFilter::Bilinear] {
+            let coloration = Coloration::HdrTexture(Arc::new(image.clone()), filter);
+            let color = coloration.color(&TextureCoords { x: 0.25, y: 0.5 });
+
+            assert_approx_eq!(color.red, 4.0);
+            assert_approx_eq!(color.green, 0.5);
+            assert_approx_eq!(color.blue, 16.0);
+        }
+    }
+
+    #[test]
+    fn test_texture_loaded_from_path() {
+        // unique per run, so parallel test runs don't share the file
+        let name = format!("raytracer-texture-from-path-{}.png", ::std::process::id());
+        let path = ::std::env::temp_dir().join(name);
+        let mut file = ::std::fs::File::create(&path).unwrap();
+        two_texel_image().save(&mut file, ImageFormat::PNG).unwrap();
+
+        let material = Material::textured_from_path(&path, 0.5);
+        // removed before asserting, so a failure doesn't leave it behind
+        ::std::fs::remove_file(&path).unwrap();
+        let material = material.unwrap();
+        let left = material.color_at(&TextureCoords { x: 0.25, y: 0.5 });
+        let right = material.color_at(&TextureCoords { x: 0.75, y: 0.5 });
+
+        assert_approx_eq!(material.albedo, 0.5);
+        assert_approx_eq!(left.red, 1.0);
+        assert_approx_eq!(left.blue, 0.0);
+        assert_approx_eq!(right.blue, 1.0);
+        assert!(Material::textured_from_path(path.with_extension("missing"), 0.5).is_err());
+    }
+
+    #[test]
+    fn test_refractive_material_keeps_its_albedo() {
+        let white = Color::from_rgb(1.0, 1.0, 1.0);
+        let glass = Material::refractive_color(white, 0.2, 1.5, 0.9, white);
+        let clamped = Material::refractive_color(white, 1.5, 1.5, 0.9, white);
+
+        assert_approx_eq!(glass.albedo, 0.2);
+        assert_approx_eq!(clamped.albedo, 1.0);
+    }
+
+    #[test]
+    fn test_objects_share_materials_by_name() {
+        let builder = SceneBuilder::new().add_material(
+            "red",
+            Material::diffuse_color(Color::from_rgb(1.0, 0.0, 0.0), 0.5),
+        );
+        let first: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .with_material_named(builder.materials(), "red")
+            .unwrap()
+            .into();
+        let second: Object = ObjectBuilder::create_for(Sphere::create(2.0))
+            .with_material_named(builder.materials(), "red")
+            .unwrap()
+            .into();
+
+        assert!(Arc::ptr_eq(&first.material, &second.material));
+        assert_approx_eq!(first.material.albedo, 0.5);
+        assert!(builder.materials().get("blue").is_none());
+        assert!(ObjectBuilder::create_for(Sphere::create(1.0))
+            .with_material_named(builder.materials(), "blue")
+            .is_err());
+    }
+
+    #[test]
+    fn test_objects_without_a_material_take_the_scene_default() {
+        let scene = SceneBuilder::new()
+            .add_object(ObjectBuilder::create_for(Sphere::create(1.0)).into())
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 0.0, 0.0), 0.5))
+                    .into(),
+            )
+            .with_default_material(Material::diffuse_color(Color::from_rgb(0.0, 0.0, 1.0), 0.3))
+            .finish();
+        let plain: Object = ObjectBuilder::create_for(Sphere::create(1.0)).into();
+
+        assert_approx_eq!(scene.objects[0].material.albedo, 0.3);
+        assert_approx_eq!(scene.objects[1].material.albedo, 0.5);
+        assert_approx_eq!(plain.material.albedo, Material::default().albedo);
+    }
+
+    #[test]
+    fn test_uv_scale_tiles_the_texture() {
+        let plain = Material::diffuse_texture(two_texel_image(), 0.5);
+        let tiled = Material {
+            uv_scale: TextureCoords { x: 2.0, y: 2.0 },
+            ..plain.clone()
+        };
+
+        for &u in &[0.1, 0.3, 0.6, 0.8] {
+            let doubled = plain.color_at(&TextureCoords { x: u * 2.0, y: 0.5 });
+            let color = tiled.color_at(&TextureCoords { x: u, y: 0.5 });
+
+            assert_approx_eq!(color.red, doubled.red);
+            assert_approx_eq!(color.blue, doubled.blue);
+        }
+        // the right texel repeats in the first half of the surface
+        let quarter = tiled.color_at(&TextureCoords { x: 0.3, y: 0.5 });
+        assert_approx_eq!(quarter.blue, 1.0);
+        assert_approx_eq!(plain.color_at(&TextureCoords { x: 0.3, y: 0.5 }).red, 1.0);
+    }
+
+    #[test]
+    fn test_reflectivity_above_one_is_clamped() {
+        let material = Material::reflective_color(Color::from_rgb(1.0, 1.0, 1.0), 0.5, 1.5);
+
+        match material.surface {
+            SurfaceType::Reflective { reflectivity } => assert_eq!(reflectivity, 1.0),
+            _ => panic!("expected a reflective surface"),
+        }
+    }
+
+    #[test]
+    fn test_albedo_is_clamped_to_unit_interval() {
+        let white = Color::from_rgb(1.0, 1.0, 1.0);
+
+        assert_eq!(Material::diffuse_color(white, 3.0).albedo, 1.0);
+        assert_eq!(Material::diffuse_color(white, -0.5).albedo, 0.0);
+        assert_eq!(Material::reflective_color(white, 0.3, -1.0).albedo, 0.3);
+    }
+
+    #[test]
+    fn test_object_space_hit_matches_world_space() {
+        let obj: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .at_position(Point::new(1.0, 2.0, -10.0))
+            .rotation(Quaternion::from_angle_y(Deg(30.0)) * Quaternion::from_angle_x(Deg(45.0)))
+            .scale(2.0)
+            .unwrap()
+            .into();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(1.0, 2.0, -10.0).normalize(),
+            RayType::Prime,
+        );
+
+        let hit = obj.intersect(&ray).unwrap();
+        let world_hit = ray.at(hit.distance());
+
+        assert_approx_eq!(hit.distance(), 105.0f64.sqrt() - 2.0);
+        assert_approx_eq!(hit.hit_point().x, world_hit.x);
+        assert_approx_eq!(hit.hit_point().y, world_hit.y);
+        assert_approx_eq!(hit.hit_point().z, world_hit.z);
+        assert_approx_eq!(hit.surface_normal().dot(ray.direction), -1.0);
+    }
+
+    #[test]
+    fn test_euler_rotation_composes_the_axes_in_order() {
+        let turned: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .rotation_euler(0.0, 90.0, 0.0)
+            .into();
+        let tipped: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .rotation_euler(90.0, 90.0, 0.0)
+            .into();
+        let x_axis = Direction::new(1.0, 0.0, 0.0);
+        let y_axis = Direction::new(0.0, 1.0, 0.0);
+
+        let x_turned = turned.position().rotation.rotate_vector(x_axis);
+        // +y is tipped to +z around x, which the turn around y takes on to +x
+        let y_tipped = tipped.position().rotation.rotate_vector(y_axis);
+
+        assert_approx_eq!(turned.position().rotation.magnitude(), 1.0);
+        assert_approx_eq!(x_turned.x, 0.0);
+        assert_approx_eq!(x_turned.y, 0.0);
+        assert_approx_eq!(x_turned.z, -1.0);
+        assert_approx_eq!(y_tipped.x, 1.0);
+        assert_approx_eq!(y_tipped.y, 0.0);
+        assert_approx_eq!(y_tipped.z, 0.0);
+    }
+
+    #[test]
+    fn test_zero_rotation_leaves_vertices_unchanged() {
+        let vertex = Point::new(1.0, 2.0, 3.0);
+        let unrotated: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .rotation(Quaternion::from_angle_y(Deg(0.0)))
+            .into();
+        let zero_euler: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .rotation_euler(0.0, 0.0, 0.0)
+            .into();
+        let halves: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .rotation(Quaternion::from_angle_y(Deg(45.0)) * Quaternion::from_angle_y(Deg(45.0)))
+            .into();
+
+        assert_eq!(unrotated.position().translate(vertex), vertex);
+        assert_eq!(zero_euler.position().translate(vertex), vertex);
+        let turned = halves.position().translate(vertex);
+        assert_approx_eq!(turned.x, 3.0);
+        assert_approx_eq!(turned.y, 2.0);
+        assert_approx_eq!(turned.z, -1.0);
+    }
+
+    #[test]
+    fn test_ground_grid_draws_lines_at_integer_coordinates() {
+        let ground: Object = ObjectBuilder::ground_grid(-1.0, 1.0).into();
+        let color_at = |x: f64, z: f64| {
+            let ray = Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(x, -1.0, z).normalize(),
+                RayType::Prime,
+            );
+            ground.intersect(&ray).unwrap().color().red
+        };
+
+        assert_approx_eq!(color_at(1.01, -3.5), 0.1);
+        assert_approx_eq!(color_at(-2.5, -4.99), 0.1);
+        assert_approx_eq!(color_at(1.5, -3.5), 0.7);
+        assert_approx_eq!(color_at(-0.7, -2.2), 0.7);
+    }
+
+    #[test]
+    fn test_color_textures_are_decoded_and_maps_are_not() {
+        let mut image = DynamicImage::new_rgb8(1, 1);
+        image.put_pixel(0, 0, Rgba([188, 188, 188, 255]));
+        let texture = Coloration::Texture(Arc::new(image), TextureFilter::Nearest);
+        let center = TextureCoords { x: 0.5, y: 0.5 };
+
+        let color = Material::new(texture.clone(), 1.0).color_at(&center);
+        let roughness = ScalarMap::Map(texture).value(&center);
+
+        assert_approx_eq!(color.red, 0.5, 5e-3);
+        assert_approx_eq!(roughness, 188.0 / 255.0);
+    }
+
+    #[test]
+    fn test_objects_share_texture_pixels() {
+        let texture = Arc::new(two_texel_image());
+        let material = Material::diffuse_texture(texture.clone(), 0.5);
+        let first: Object = ObjectBuilder::create_for(Sphere::create(1.0))
+            .with_material(material.clone())
+            .into();
+        let second: Object = ObjectBuilder::create_for(Sphere::create(2.0))
+            .with_material(Material::filtered_texture(
+                texture.clone(),
+                TextureFilter::Bilinear,
+                0.5,
+            ))
+            .into();
+        drop(material);
+        let objects = [first, second];
+
+        for object in &objects {
+            match object.material.color {
+                Coloration::Texture(ref image, _) => assert!(Arc::ptr_eq(image, &texture)),
+                _ => panic!("expected a texture"),
+            }
+        }
+        assert_eq!(Arc::strong_count(&texture), 3);
+    }
 }
@@ -1,32 +1,106 @@
 use cgmath::prelude::*;
 use cgmath::Vector3;
-use objects::{Material, Structure, SurfaceType, TextureCoords, WorldPosition};
+use objects::{BoundingBox, Structure, TextureCoords};
 use raycast::{Intersection, Ray};
-use types::{Color, Direction, Point, Scale};
+use types::{Direction, Point, PARALLEL_EPSILON};
 
 pub struct Plane {
     pub normal: Direction,
+    pub double_sided: bool,
+    /// limits the plane to a rectangle, it is infinite if not set
+    pub extent: Option<Rectangle>,
+}
+
+/// the parallelogram spanned by two edges from a corner. texture coordinates run from 0 to 1
+/// along each edge
+#[derive(Debug, Clone, Copy)]
+pub struct Rectangle {
+    pub origin: Point,
+    pub u: Direction,
+    pub v: Direction,
+}
+
+impl Rectangle {
+    /// where the point lies in units of the edges, assuming it lies in the rectangle's plane
+    fn coords(&self, point: &Point) -> (f64, f64) {
+        let n = self.u.cross(self.v);
+        let local = point - self.origin;
+        let n2 = n.magnitude2();
+        (
+            n.dot(local.cross(self.v)) / n2,
+            n.dot(self.u.cross(local)) / n2,
+        )
+    }
 }
 
 impl Plane {
     pub fn create(normal: Direction) -> Plane {
-        Plane { normal }
+        Plane {
+            normal,
+            double_sided: false,
+            extent: None,
+        }
     }
 
-    fn intersect(&self, ray: &Ray, position: &WorldPosition) -> Option<f64> {
+    /// a plane that is visible from both sides, not only against its normal
+    pub fn create_double_sided(normal: Direction) -> Plane {
+        Plane {
+            normal,
+            double_sided: true,
+            extent: None,
+        }
+    }
+
+    /// the rectangle spanned by `u` and `v` from `origin`, seen from the side `u × v` points to
+    pub fn create_rectangle(origin: Point, u: Direction, v: Direction) -> Plane {
+        Plane {
+            normal: -u.cross(v).normalize(),
+            double_sided: false,
+            extent: Some(Rectangle { origin, u, v }),
+        }
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
         let normal = self.normal;
         let denom = normal.dot(ray.direction);
-        if denom > 1e-10 {
-            let v = position.position - ray.origin;
+        if denom > PARALLEL_EPSILON || (self.double_sided && denom < -PARALLEL_EPSILON) {
+            let on_plane = self.extent.map_or(Point::origin(), |extent| extent.origin);
+            let v = on_plane - ray.origin;
             let distance = v.dot(normal) / denom;
-            if distance >= 0.0 {
+            if distance >= 0.0 && self.contains(&ray.at(distance)) {
                 return Some(distance);
             }
         }
         None
     }
 
-    fn texture_coord(&self, hit_point: &Point, position: &WorldPosition) -> TextureCoords {
+    fn contains(&self, point: &Point) -> bool {
+        match self.extent {
+            Some(ref extent) => {
+                let (a, b) = extent.coords(point);
+                (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b)
+            }
+            None => true,
+        }
+    }
+
+    fn surface_normal(&self, ray: &Ray) -> Direction {
+        if self.normal.dot(ray.direction) < 0.0 {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+
+    fn texture_coord(&self, hit_point: &Point) -> TextureCoords {
+        if let Some(ref extent) = self.extent {
+            let (a, b) = extent.coords(hit_point);
+            return TextureCoords {
+                x: a as f32,
+                y: b as f32,
+            };
+        }
+
         let mut x_axis = self.normal.cross(Vector3 {
             x: 0.0,
             y: 0.0,
@@ -41,8 +115,8 @@ impl Plane {
             });
         }
 
-        let y_axis = self.normal.cross(x_axis.clone());
-        let hit_vec = *hit_point - position.position;
+        let y_axis = self.normal.cross(x_axis);
+        let hit_vec = hit_point.to_vec();
 
         TextureCoords {
             x: hit_vec.dot(x_axis) as f32,
@@ -52,15 +126,104 @@ impl Plane {
 }
 
 impl Structure for Plane {
-    fn get_intersection(&self, ray: &Ray, position: &WorldPosition) -> Option<Intersection> {
-        self.intersect(ray, position).map(|distance| {
-            let hit_point = ray.origin + ray.direction * distance;
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray).map(|distance| {
+            let hit_point = ray.at(distance);
             Intersection::new(
                 distance,
                 hit_point,
-                self.texture_coord(&hit_point, position),
-                -self.normal,
+                self.texture_coord(&hit_point),
+                self.surface_normal(ray),
             )
         })
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.extent.and_then(|extent| {
+            BoundingBox::around(vec![
+                extent.origin,
+                extent.origin + extent.u,
+                extent.origin + extent.v,
+                extent.origin + extent.u + extent.v,
+            ])
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+    use objects::{Plane, Structure};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_single_sided_plane_is_hidden_from_behind() {
+        let plane = Plane::create(Direction::new(0.0, -1.0, 0.0));
+        let from_above = Ray::create(
+            Point::new(0.0, 1.0, 0.0),
+            Direction::new(0.0, -1.0, 0.0),
+            RayType::Prime,
+        );
+        let from_below = Ray::create(
+            Point::new(0.0, -1.0, 0.0),
+            Direction::new(0.0, 1.0, 0.0),
+            RayType::Prime,
+        );
+
+        assert!(plane.get_intersection(&from_above).is_some());
+        assert!(plane.get_intersection(&from_below).is_none());
+    }
+
+    #[test]
+    fn test_rectangle_is_only_hit_within_its_extent() {
+        // a 4 by 2 floor with its corner at (1, 0, -1), seen from above
+        let floor = Plane::create_rectangle(
+            Point::new(1.0, 0.0, -1.0),
+            Direction::new(4.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -2.0),
+        );
+        let down_at = |x: f64, z: f64| {
+            Ray::create(
+                Point::new(x, 1.0, z),
+                Direction::new(0.0, -1.0, 0.0),
+                RayType::Prime,
+            )
+        };
+
+        let inside = floor.get_intersection(&down_at(4.0, -1.5)).unwrap();
+        let bounds = floor.bounds().unwrap();
+
+        assert_approx_eq!(inside.distance(), 1.0);
+        assert_approx_eq!(inside.texture_coord().x, 0.75);
+        assert_approx_eq!(inside.texture_coord().y, 0.25);
+        assert!(floor.get_intersection(&down_at(0.5, -1.5)).is_none());
+        assert!(floor.get_intersection(&down_at(4.0, -3.5)).is_none());
+        assert!(floor.get_intersection(&down_at(5.5, -0.5)).is_none());
+        assert_eq!(bounds.min, Point::new(1.0, 0.0, -3.0));
+        assert_eq!(bounds.max, Point::new(5.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_double_sided_plane_is_hit_from_both_sides() {
+        let plane = Plane::create_double_sided(Direction::new(0.0, -1.0, 0.0));
+        let from_above = Ray::create(
+            Point::new(0.0, 1.0, 0.0),
+            Direction::new(0.0, -1.0, 0.0),
+            RayType::Prime,
+        );
+        let from_below = Ray::create(
+            Point::new(0.0, -1.0, 0.0),
+            Direction::new(0.0, 1.0, 0.0),
+            RayType::Prime,
+        );
+
+        let above = plane.get_intersection(&from_above).unwrap();
+        let below = plane.get_intersection(&from_below).unwrap();
+
+        assert_approx_eq!(above.distance(), 1.0);
+        assert_approx_eq!(below.distance(), 1.0);
+        assert!(above.surface_normal().dot(from_above.direction) < 0.0);
+        assert!(below.surface_normal().dot(from_below.direction) < 0.0);
+    }
 }
@@ -1,54 +1,117 @@
-use cgmath::prelude::*;
-use objects::WorldPosition;
-use raycast::Ray;
-use types::Point;
+use objects::{BoundingBox, Structure, SurfacePatch, TextureCoords};
+use raycast::{Intersection, Ray};
+use types::{Direction, Point, PARALLEL_EPSILON};
 
+/// a rectangle in the xz plane, centered on the origin. it faces down along -y, which is the
+/// side an emissive quad lights, but rays hit it from both sides.
 pub struct Quad {
-    size: Point,
+    pub width: f64,
+    pub depth: f64,
 }
 
 impl Quad {
-    pub fn intersects(&self, ray: &Ray, position: &WorldPosition) -> bool {
-        let pmin = position.translate(Point::new(0.0, 0.0, 0.0));
-        let pmax = position.translate(self.size);
-
-        let tmin = (pmin.x - ray.origin.x) / ray.direction.x;
-        let tmax = (pmax.x - ray.origin.x) / ray.direction.x;
+    pub fn create(width: f64, depth: f64) -> Quad {
+        Quad { width, depth }
+    }
 
-        if tmin > tmax {
-            let (tmin, tmax) = (tmax, tmin);
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        if ray.direction.y.abs() < PARALLEL_EPSILON {
+            return None;
         }
 
-        let tymin = (pmin.y - ray.origin.y) / ray.direction.y;
-        let tymax = (pmax.y - ray.origin.y) / ray.direction.y;
-
-        if tymin > tymax {
-            let (tymin, tymax) = (tymax, tymin);
+        let distance = -ray.origin.y / ray.direction.y;
+        if distance < 0.0 {
+            return None;
         }
 
-        if tmin > tymax || tymin > tmax {
-            return false;
+        let hit_point = ray.at(distance);
+        if hit_point.x.abs() > self.width / 2.0 || hit_point.z.abs() > self.depth / 2.0 {
+            return None;
         }
 
-        if tymin > tmin {
-            let tmin = tymin;
-        }
+        Some(distance)
+    }
+}
 
-        if tymax > tmax {
-            let tmax = tymax;
-        }
+impl Structure for Quad {
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray).map(|distance| {
+            let hit_point = ray.at(distance);
+            Intersection::new(
+                distance,
+                hit_point,
+                TextureCoords {
+                    x: (hit_point.x / self.width + 0.5) as f32,
+                    y: (hit_point.z / self.depth + 0.5) as f32,
+                },
+                Direction::new(0.0, -1.0, 0.0),
+            )
+        })
+    }
 
-        let tzmin = (pmin.z - ray.origin.z) / ray.direction.z;
-        let tzmax = (pmax.z - ray.origin.z) / ray.direction.z;
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(BoundingBox {
+            min: Point::new(-self.width / 2.0, 0.0, -self.depth / 2.0),
+            max: Point::new(self.width / 2.0, 0.0, self.depth / 2.0),
+        })
+    }
 
-        if tzmin > tzmax {
-            let (tzmin, tzmax) = (tzmax, tzmin);
-        }
+    /// a grid of equally sized cells
+    fn surface_patches(&self, count: usize) -> Vec<SurfacePatch> {
+        let n = ((count as f64).sqrt().ceil() as usize).max(1);
+        let u = Direction::new(self.width / n as f64, 0.0, 0.0);
+        let v = Direction::new(0.0, 0.0, self.depth / n as f64);
 
-        if tmin > tzmax || tzmin > tmax {
-            return false;
-        }
+        (0..n * n)
+            .map(|cell| {
+                let (i, j) = ((cell % n) as f64, (cell / n) as f64);
+                SurfacePatch {
+                    center: Point::new(
+                        -self.width / 2.0 + (i + 0.5) * u.x,
+                        0.0,
+                        -self.depth / 2.0 + (j + 0.5) * v.z,
+                    ),
+                    u,
+                    v,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use objects::{Quad, Structure};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_patches_cover_the_quad_facing_down() {
+        let quad = Quad::create(2.0, 3.0);
+
+        let patches = quad.surface_patches(9);
+        let area: f64 = patches.iter().map(|patch| patch.area()).sum();
+
+        assert_eq!(patches.len(), 9);
+        assert_approx_eq!(area, 6.0);
+        assert_approx_eq!(patches[0].normal().y, -1.0);
+        assert!(patches
+            .iter()
+            .all(|patch| patch.center.x.abs() < 1.0 && patch.center.z.abs() < 1.5));
+    }
+
+    #[test]
+    fn test_rays_beside_the_quad_miss() {
+        let quad = Quad::create(2.0, 2.0);
+        let down = |x: f64| {
+            Ray::create(
+                Point::new(x, 1.0, 0.0),
+                Direction::new(0.0, -1.0, 0.0),
+                RayType::Prime,
+            )
+        };
 
-        true
+        assert_approx_eq!(quad.get_intersection(&down(0.5)).unwrap().distance(), 1.0);
+        assert!(quad.get_intersection(&down(1.5)).is_none());
     }
 }
@@ -1,7 +1,7 @@
 use cgmath::prelude::*;
-use objects::{Structure, TextureCoords, WorldPosition};
+use objects::{BoundingBox, Structure, TextureCoords};
 use raycast::{Intersection, Ray};
-use types::{Direction, Point, Scale};
+use types::{Direction, Point};
 
 use std::f32::consts::PI;
 
@@ -14,51 +14,114 @@ impl Sphere {
         Sphere { radius }
     }
 
-    fn intersect(&self, ray: &Ray, position: &WorldPosition) -> Option<f64> {
-        let l = position.position - ray.origin;
+    /// distances along the ray's line to where it enters and leaves the sphere, the entry
+    /// first. either can lie behind the origin, both do if the sphere is behind the ray
+    pub fn interval(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let l = Point::origin() - ray.origin;
         let adj2 = l.dot(ray.direction);
 
         let d2 = l.dot(l) - adj2.powi(2);
-        let radius2 = (self.radius * position.scale).powi(2);
+        let radius2 = self.radius.powi(2);
 
         if d2 > radius2 {
             return None;
         }
 
         let thc = (radius2 - d2).sqrt();
-        let t0 = adj2 - thc;
-        let t1 = adj2 + thc;
-        if t0 < 0.0 && t1 < 0.0 {
-            return None;
+        Some((adj2 - thc, adj2 + thc))
+    }
+
+    /// distances to where the ray enters and leaves the sphere, skipping those behind it
+    fn intersect(&self, ray: &Ray) -> Vec<f64> {
+        match self.interval(ray) {
+            // when the ray starts inside the sphere only the exit point lies ahead of it
+            Some((t0, t1)) => vec![t0, t1].into_iter().filter(|&t| t >= 0.0).collect(),
+            None => vec![],
         }
+    }
 
-        let distance = if t0 < t1 { t0 } else { t1 };
-        Some(distance)
+    fn intersection_at(&self, ray: &Ray, distance: f64) -> Intersection {
+        let hit_point = ray.at(distance);
+        Intersection::new(
+            distance,
+            hit_point,
+            self.texture_coord(&hit_point),
+            self.surface_normal(&hit_point),
+        )
     }
 
-    fn surface_normal(&self, hit_point: &Point, position: &WorldPosition) -> Direction {
-        (*hit_point - position.position).normalize()
+    fn surface_normal(&self, hit_point: &Point) -> Direction {
+        hit_point.to_vec().normalize()
     }
 
-    fn texture_coord(&self, hit_point: &Point, position: &WorldPosition) -> TextureCoords {
-        let hit_vec = *hit_point - position.position;
+    fn texture_coord(&self, hit_point: &Point) -> TextureCoords {
+        let hit_vec = hit_point.to_vec();
         TextureCoords {
             x: (1.0 + (hit_vec.z.atan2(hit_vec.x) as f32) / PI) * 0.5,
-            y: (hit_vec.y / (self.radius * position.scale)).acos() as f32 / PI,
+            // rounding can push the ratio just past +-1 at the poles, where acos is undefined
+            y: (hit_vec.y / self.radius).clamp(-1.0, 1.0).acos() as f32 / PI,
         }
     }
 }
 
 impl Structure for Sphere {
-    fn get_intersection(&self, ray: &Ray, position: &WorldPosition) -> Option<Intersection> {
-        self.intersect(ray, position).map(|distance| {
-            let hit_point = ray.origin + ray.direction * distance;
-            Intersection::new(
-                distance,
-                hit_point,
-                self.texture_coord(&hit_point, position),
-                self.surface_normal(&hit_point, position),
-            )
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray)
+            .first()
+            .map(|&distance| self.intersection_at(ray, distance))
+    }
+
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        self.intersect(ray)
+            .into_iter()
+            .map(|distance| self.intersection_at(ray, distance))
+            .collect()
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        let r = self.radius;
+        Some(BoundingBox {
+            min: Point::new(-r, -r, -r),
+            max: Point::new(r, r, r),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use objects::{Sphere, Structure};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_ray_from_inside_hits_the_exit_point() {
+        let sphere = Sphere::create(2.0);
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 1.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Refraction,
+        );
+
+        let (t0, t1) = sphere.interval(&ray).unwrap();
+        let hit = sphere.get_intersection(&ray).unwrap();
+
+        assert_approx_eq!(t0, -1.0);
+        assert_approx_eq!(t1, 3.0);
+        assert_approx_eq!(hit.distance(), 3.0);
+        assert_approx_eq!(hit.hit_point().z, -2.0);
+        assert_eq!(sphere.get_intersections(&ray).len(), 1);
+    }
+
+    #[test]
+    fn test_texture_coord_is_finite_at_poles() {
+        let sphere = Sphere::create(1.0);
+
+        let north = sphere.texture_coord(&Point::new(0.0, 1.0 + 1e-12, 0.0));
+        let south = sphere.texture_coord(&Point::new(0.0, -1.0 - 1e-12, 0.0));
+
+        assert!(north.x.is_finite() && north.y.is_finite());
+        assert!(south.x.is_finite() && south.y.is_finite());
+        assert_approx_eq!(north.y, 0.0);
+        assert_approx_eq!(south.y, 1.0);
+    }
+}
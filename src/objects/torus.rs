@@ -0,0 +1,238 @@
+use cgmath::prelude::*;
+use objects::{BoundingBox, Structure, TextureCoords};
+use raycast::{Intersection, Ray};
+use types::{Direction, Point};
+
+use std::f32::consts::PI;
+
+/// a ring around the y axis: a tube of `minor_radius` swept along a circle of `major_radius`
+/// in the xz plane.
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Torus {
+    pub fn create(major_radius: f64, minor_radius: f64) -> Torus {
+        Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    /// all distances at which the ray crosses the surface, nearest first.
+    fn intersect(&self, ray: &Ray) -> Vec<f64> {
+        let o = ray.origin.to_vec();
+        let d = ray.direction;
+        let r2 = self.major_radius * self.major_radius;
+        let minor2 = self.minor_radius * self.minor_radius;
+
+        // every hit lies within the bounding sphere, which bounds the search for roots
+        let bound = self.major_radius + self.minor_radius;
+        let (near, far) = match bounding_sphere_span(&o, &d, bound) {
+            Some(span) => span,
+            None => return vec![],
+        };
+
+        // (|p|² + R² - r²)² = 4R² (x² + z²) along p = o + t d, as a quartic in t
+        let dd = d.dot(d);
+        let od = o.dot(d);
+        let e = o.dot(o) - r2 - minor2;
+        let coefficients = [
+            e * e - 4.0 * r2 * (minor2 - o.y * o.y),
+            4.0 * od * e + 8.0 * r2 * o.y * d.y,
+            2.0 * dd * e + 4.0 * od * od + 4.0 * r2 * d.y * d.y,
+            4.0 * dd * od,
+            dd * dd,
+        ];
+
+        real_roots(&coefficients, near.max(0.0), far)
+    }
+
+    fn intersection_at(&self, ray: &Ray, distance: f64) -> Intersection {
+        let hit_point = ray.at(distance);
+        Intersection::new(
+            distance,
+            hit_point,
+            self.texture_coord(&hit_point),
+            self.surface_normal(&hit_point),
+        )
+    }
+
+    fn surface_normal(&self, hit_point: &Point) -> Direction {
+        let ring = Direction::new(hit_point.x, 0.0, hit_point.z);
+        let center = if ring.magnitude2() > 0.0 {
+            ring.normalize() * self.major_radius
+        } else {
+            ring
+        };
+        (hit_point.to_vec() - center).normalize()
+    }
+
+    fn texture_coord(&self, hit_point: &Point) -> TextureCoords {
+        let rho = (hit_point.x * hit_point.x + hit_point.z * hit_point.z).sqrt();
+        TextureCoords {
+            x: (1.0 + (hit_point.z.atan2(hit_point.x) as f32) / PI) * 0.5,
+            y: (1.0 + (hit_point.y.atan2(rho - self.major_radius) as f32) / PI) * 0.5,
+        }
+    }
+}
+
+fn bounding_sphere_span(
+    origin: &Direction,
+    direction: &Direction,
+    radius: f64,
+) -> Option<(f64, f64)> {
+    let b = origin.dot(*direction);
+    let c = origin.dot(*origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt = discriminant.sqrt();
+    let far = -b + sqrt;
+    if far < 0.0 {
+        None
+    } else {
+        Some((-b - sqrt, far))
+    }
+}
+
+/// evaluates the polynomial `coefficients[0] + coefficients[1] t + ...`
+fn evaluate(coefficients: &[f64], t: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |acc, &c| acc * t + c)
+}
+
+/// the real roots of a polynomial within `[low, high]`, ascending.
+/// the roots of the derivative split the range into monotonic pieces, each holding at most one
+/// root, which is then found by bisection.
+fn real_roots(coefficients: &[f64], low: f64, high: f64) -> Vec<f64> {
+    if coefficients.len() == 2 {
+        let root = -coefficients[0] / coefficients[1];
+        return if root >= low && root <= high {
+            vec![root]
+        } else {
+            vec![]
+        };
+    }
+
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, &c)| c * power as f64)
+        .collect();
+
+    let mut bounds = vec![low];
+    bounds.extend(real_roots(&derivative, low, high));
+    bounds.push(high);
+
+    bounds
+        .windows(2)
+        .filter_map(|piece| bisect(coefficients, piece[0], piece[1]))
+        .collect()
+}
+
+fn bisect(coefficients: &[f64], mut low: f64, mut high: f64) -> Option<f64> {
+    let mut value_low = evaluate(coefficients, low);
+    let value_high = evaluate(coefficients, high);
+    if value_low == 0.0 {
+        return Some(low);
+    }
+    if value_low.signum() == value_high.signum() {
+        return None;
+    }
+
+    for _ in 0..64 {
+        let mid = 0.5 * (low + high);
+        let value_mid = evaluate(coefficients, mid);
+        if value_mid.signum() == value_low.signum() {
+            low = mid;
+            value_low = value_mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some(0.5 * (low + high))
+}
+
+impl Structure for Torus {
+    fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect(ray)
+            .first()
+            .map(|&distance| self.intersection_at(ray, distance))
+    }
+
+    fn get_intersections(&self, ray: &Ray) -> Vec<Intersection> {
+        self.intersect(ray)
+            .into_iter()
+            .map(|distance| self.intersection_at(ray, distance))
+            .collect()
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(BoundingBox {
+            min: Point::new(-outer, -self.minor_radius, -outer),
+            max: Point::new(outer, self.minor_radius, outer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+    use objects::{Structure, Torus};
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_ray_through_the_hole_misses() {
+        let torus = Torus::create(2.0, 0.5);
+        let ray = Ray::create(
+            Point::new(0.0, 5.0, 0.0),
+            Direction::new(0.0, -1.0, 0.0),
+            RayType::Prime,
+        );
+
+        assert!(torus.get_intersection(&ray).is_none());
+    }
+
+    #[test]
+    fn test_ray_through_the_ring_hits_twice() {
+        let torus = Torus::create(2.0, 0.5);
+        let ray = Ray::create(
+            Point::new(2.0, 5.0, 0.0),
+            Direction::new(0.0, -1.0, 0.0),
+            RayType::Prime,
+        );
+
+        let distances = torus.intersect(&ray);
+        let hit = torus.get_intersection(&ray).unwrap();
+
+        assert_eq!(distances.len(), 2);
+        assert_approx_eq!(distances[0], 4.5);
+        assert_approx_eq!(distances[1], 5.5);
+        assert_approx_eq!(hit.distance(), 4.5);
+        assert_approx_eq!(hit.surface_normal().dot(Direction::new(0.0, 1.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn test_ray_along_the_axis_plane_hits_four_times() {
+        let torus = Torus::create(2.0, 0.5);
+        let ray = Ray::create(
+            Point::new(-5.0, 0.0, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            RayType::Prime,
+        );
+
+        let distances = torus.intersect(&ray);
+
+        assert_eq!(distances.len(), 4);
+        assert_approx_eq!(distances[0], 2.5);
+        assert_approx_eq!(distances[1], 3.5);
+        assert_approx_eq!(distances[2], 6.5);
+        assert_approx_eq!(distances[3], 7.5);
+    }
+}
@@ -0,0 +1,61 @@
+/// a small seeded pseudo random number generator (splitmix64).
+/// good enough for sampling patterns and cheap to create per pixel, so renders come out the
+/// same no matter how the work is split between threads.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn seeded(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    /// a generator for one pixel, independent of the order pixels are rendered in
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Random {
+        let mut mix = Random::seeded(seed ^ ((x as u64) << 32 | y as u64));
+        Random::seeded(mix.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// uniformly distributed in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use random::Random;
+
+    #[test]
+    fn test_same_seed_gives_same_sequence() {
+        let mut a = Random::for_pixel(7, 3, 4);
+        let mut b = Random::for_pixel(7, 3, 4);
+        let mut other = Random::for_pixel(7, 4, 3);
+
+        let first: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+        let second: Vec<u64> = (0..4).map(|_| b.next_u64()).collect();
+        let third: Vec<u64> = (0..4).map(|_| other.next_u64()).collect();
+
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+    }
+
+    #[test]
+    fn test_floats_are_in_unit_interval() {
+        let mut random = Random::seeded(1);
+        let values: Vec<f64> = (0..1000).map(|_| random.next_f64()).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+
+        assert!(values.iter().all(|&v| (0.0..1.0).contains(&v)));
+        assert!((mean - 0.5).abs() < 0.05);
+    }
+}
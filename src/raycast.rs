@@ -44,10 +44,11 @@ impl Intersection {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum RayType {
     Prime,
     Reflection,
+    Refraction,
     Shadow,
 }
 
@@ -60,54 +61,98 @@ pub struct Ray {
 }
 
 impl Ray {
-    pub fn create_prime(x: f64, y: f64, scene: &Scene, camera: &Camera) -> Ray {
-        let direction = camera.to_sensor_direction(x as f64, y as f64);
+    pub fn create(origin: Point, direction: Direction, ray_type: RayType) -> Ray {
         Ray {
-            origin: Point::new(0.0, 0.0, 0.0),
+            origin,
             inv_direction: Direction {
                 x: 1.0 / direction.x,
                 y: 1.0 / direction.y,
                 z: 1.0 / direction.z,
             },
-            direction: direction,
-            ray_type: RayType::Prime,
+            direction,
+            ray_type,
         }
     }
 
-    pub fn create_reflection(ray_direction: &Direction, int: &IntersectionResult) -> Ray {
+    /// the point `t` times the direction away from the origin
+    pub fn at(&self, t: f64) -> Point {
+        self.origin + self.direction * t
+    }
+
+    pub fn create_prime(x: f64, y: f64, _scene: &Scene, camera: &Camera) -> Ray {
+        let direction = camera
+            .rotation
+            .rotate_vector(camera.to_sensor_direction(x, y));
+        Ray::create(camera.position, direction, RayType::Prime)
+    }
+
+    pub fn create_reflection(
+        ray_direction: &Direction,
+        int: &IntersectionResult,
+        bias: f64,
+    ) -> Ray {
         let direction =
             ray_direction - (2.0 * ray_direction.dot(int.surface_normal()) * int.surface_normal());
-        Ray {
-            origin: int.reflection_origin(),
-            inv_direction: Direction {
-                x: 1.0 / direction.x,
-                y: 1.0 / direction.y,
-                z: 1.0 / direction.z,
-            },
-            direction: direction,
-            ray_type: RayType::Reflection,
-        }
+        Ray::create(
+            int.ray_origin(&direction, bias),
+            direction,
+            RayType::Reflection,
+        )
     }
 
-    pub fn create_shadow_ray(direction_to_light: Direction, int: &IntersectionResult) -> Ray {
-        Ray {
-            origin: int.reflection_origin(),
-            inv_direction: Direction {
-                x: 1.0 / direction_to_light.x,
-                y: 1.0 / direction_to_light.y,
-                z: 1.0 / direction_to_light.z,
-            },
-            direction: direction_to_light,
-            ray_type: RayType::Shadow,
+    /// bends the ray into (or out of) the surface following snell's law.
+    /// returns `None` on total internal reflection.
+    pub fn create_refraction(
+        ray_direction: &Direction,
+        int: &IntersectionResult,
+        index: f32,
+        bias: f64,
+    ) -> Option<Ray> {
+        let mut normal = int.surface_normal();
+        let mut cos_i = ray_direction.dot(normal).clamp(-1.0, 1.0);
+        let (mut eta_i, mut eta_t) = (1.0, index as f64);
+        if cos_i < 0.0 {
+            cos_i = -cos_i;
+        } else {
+            normal = -normal;
+            ::std::mem::swap(&mut eta_i, &mut eta_t);
+        }
+
+        let eta = eta_i / eta_t;
+        let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+        if k < 0.0 {
+            return None;
         }
+
+        let direction = (ray_direction * eta + normal * (eta * cos_i - k.sqrt())).normalize();
+        Some(Ray::create(
+            int.ray_origin(&direction, bias),
+            direction,
+            RayType::Refraction,
+        ))
+    }
+
+    pub fn create_shadow_ray(
+        direction_to_light: Direction,
+        int: &IntersectionResult,
+        bias: f64,
+    ) -> Ray {
+        Ray::create(
+            int.ray_origin(&direction_to_light, bias),
+            direction_to_light,
+            RayType::Shadow,
+        )
     }
 }
 
 #[derive(Debug)]
 pub struct IntersectionResult {
     distance: f64,
+    object_id: usize,
     hit_point: Point,
+    object_point: Point,
     surface_normal: Direction,
+    texture_coord: TextureCoords,
     surface: SurfaceProperties,
 }
 
@@ -115,31 +160,11 @@ impl PartialEq for IntersectionResult {
     fn eq(&self, other: &Self) -> bool {
         self.distance.eq(&other.distance)
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        self.distance.ne(&other.distance)
-    }
 }
 
 impl PartialOrd for IntersectionResult {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.distance.partial_cmp(&other.distance)
-    }
-
-    fn lt(&self, other: &Self) -> bool {
-        self.distance.lt(&other.distance)
-    }
-
-    fn le(&self, other: &Self) -> bool {
-        self.distance.le(&other.distance)
-    }
-
-    fn gt(&self, other: &Self) -> bool {
-        self.distance.gt(&other.distance)
-    }
-
-    fn ge(&self, other: &Self) -> bool {
-        self.distance.ge(&other.distance)
+        Some(self.cmp(other))
     }
 }
 
@@ -157,19 +182,18 @@ impl Ord for IntersectionResult {
 impl IntersectionResult {
     pub fn create(
         intersection: &Intersection,
-        color: Color,
-        albedo: f32,
-        reflectivity: Option<f32>,
+        object_point: Point,
+        object_id: usize,
+        surface: SurfaceProperties,
     ) -> IntersectionResult {
         IntersectionResult {
             distance: intersection.distance(),
+            object_id,
             surface_normal: intersection.surface_normal(),
             hit_point: intersection.hit_point(),
-            surface: SurfaceProperties {
-                reflectivity: reflectivity,
-                albedo: albedo,
-                color: color,
-            },
+            object_point,
+            texture_coord: intersection.texture_coord(),
+            surface,
         }
     }
 
@@ -177,12 +201,33 @@ impl IntersectionResult {
         self.distance
     }
 
+    pub fn object_id(&self) -> usize {
+        self.object_id
+    }
+
     pub fn hit_point(&self) -> &Point {
         &self.hit_point
     }
 
-    pub fn reflection_origin(&self) -> Point {
-        self.hit_point + self.surface_normal * 1e-13
+    /// the hit point in the object's own space, before its position, rotation and scale
+    pub fn object_point(&self) -> &Point {
+        &self.object_point
+    }
+
+    pub fn texture_coord(&self) -> &TextureCoords {
+        &self.texture_coord
+    }
+
+    /// origin for a secondary ray leaving the hit in `direction`, pushed off the surface to the
+    /// side the ray leaves on. `bias` is relative to the hit's distance from the world origin,
+    /// because the precision of the hit point shrinks as its coordinates grow.
+    pub fn ray_origin(&self, direction: &Direction, bias: f64) -> Point {
+        let offset = bias * self.hit_point.to_vec().magnitude().max(1.0);
+        if direction.dot(self.surface_normal) < 0.0 {
+            self.hit_point - self.surface_normal * offset
+        } else {
+            self.hit_point + self.surface_normal * offset
+        }
     }
 
     pub fn surface_normal(&self) -> Direction {
@@ -198,17 +243,52 @@ impl IntersectionResult {
     }
 
     pub fn reflectivity(&self) -> Option<f32> {
-        match self.surface.reflectivity {
-            Some(r) => {
-                if r < 1e-10 {
-                    None
-                } else {
-                    Some(r)
-                }
-            }
-            None => None,
+        self.surface.reflectivity.filter(|&r| r >= 1e-10)
+    }
+
+    pub fn metalness(&self) -> f32 {
+        self.surface.metalness
+    }
+
+    pub fn reflection_tint(&self) -> Color {
+        self.surface.reflection_tint
+    }
+
+    pub fn roughness(&self) -> f32 {
+        self.surface.roughness
+    }
+
+    pub fn refraction(&self) -> Option<&Refraction> {
+        self.surface.refraction.as_ref()
+    }
+
+    pub fn light_layers(&self) -> u32 {
+        self.surface.light_layers
+    }
+
+    pub fn emission(&self) -> Color {
+        self.surface.emission
+    }
+
+    pub fn is_shadow_catcher(&self) -> bool {
+        self.surface.shadow_catcher
+    }
+
+    /// the surface normal on the side the ray came from, so open and double sided surfaces
+    /// are shaded the same from both sides.
+    pub fn facing_normal(&self, ray_direction: &Direction) -> Direction {
+        if self.is_exit(ray_direction) {
+            -self.surface_normal
+        } else {
+            self.surface_normal
         }
     }
+
+    /// true if the ray reached this hit from inside the object, i.e. travelled against the
+    /// outward facing surface normal.
+    pub fn is_exit(&self, ray_direction: &Direction) -> bool {
+        ray_direction.dot(self.surface_normal) > 0.0
+    }
 }
 
 #[derive(Debug)]
@@ -216,4 +296,40 @@ pub struct SurfaceProperties {
     pub albedo: f32,
     pub color: Color,
     pub reflectivity: Option<f32>,
+    /// how much reflections are tinted by the surface color
+    pub metalness: f32,
+    /// multiplies the reflected light
+    pub reflection_tint: Color,
+    pub roughness: f32,
+    pub refraction: Option<Refraction>,
+    /// lights shine on this surface if they share one of these bits
+    pub light_layers: u32,
+    pub emission: Color,
+    pub shadow_catcher: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Refraction {
+    pub index: f32,
+    pub transparency: f32,
+    /// per-channel absorption coefficient per unit of distance travelled inside the medium
+    pub absorption: Color,
+}
+
+#[cfg(test)]
+mod test {
+    use raycast::{Ray, RayType};
+    use types::{Direction, Point};
+
+    #[test]
+    fn test_at_walks_along_the_direction() {
+        let ray = Ray::create(
+            Point::new(1.0, 2.0, 3.0),
+            Direction::new(0.0, -1.0, 0.5),
+            RayType::Prime,
+        );
+
+        assert_eq!(ray.at(2.0), Point::new(1.0, 0.0, 4.0));
+        assert_eq!(ray.at(0.0), ray.origin);
+    }
 }
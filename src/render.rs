@@ -1,5 +1,9 @@
 use std::cmp::min;
+use std::error::Error;
 use std::f32::consts::PI;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 
@@ -9,146 +13,4411 @@ use threadpool::ThreadPool;
 
 use image::Rgba;
 use image::{DynamicImage, GenericImage};
-use raycast::{IntersectionResult, Ray};
-use scene::{Camera, Scene};
+use light::{luminance, LightSample};
+use lut::Lut;
+use random::Random;
+use raycast::{IntersectionResult, Ray, RayType};
+use scene::{Camera, Culling, Lens, Scene};
 use std::time::{Duration, Instant};
-use types::Color;
+use types::{orthonormal_basis, Color, ColorSum, Direction, Point, SHADOW_BIAS};
 
-fn shade_diffuse(scene: &Scene, intersection: &IntersectionResult) -> Color {
+const GLOSSY_SAMPLES: usize = 16;
+
+/// 16384 × 16384, a gigabyte of rgba pixels
+pub const DEFAULT_MAX_PIXELS: u64 = 1 << 28;
+
+/// the fraction of the light that reaches the intersection, per channel.
+/// soft lights are sampled on a spiral across the cone they cover.
+fn light_visibility(
+    scene: &Scene,
+    intersection: &IntersectionResult,
+    direction_to_light: Direction,
+    distance: f64,
+    softness: f64,
+    options: &RenderOptions,
+) -> Color {
+    let transmitted = |direction: Direction| {
+        let shadow_ray = Ray::create_shadow_ray(direction, intersection, options.shadow_bias);
+        shadow_transmittance(scene, &shadow_ray, distance)
+    };
+
+    if softness <= 0.0 {
+        return transmitted(direction_to_light);
+    }
+
+    let (tangent, bitangent) = orthonormal_basis(&direction_to_light);
+    let radius = softness.tan();
+    let golden_angle = ::std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    let samples = options.light_samples.max(1);
+    let sum = (0..samples).fold(Color::from_rgba(0.0, 0.0, 0.0, 0.0), |sum, k| {
+        let r = radius * ((k as f64 + 0.5) / samples as f64).sqrt();
+        let theta = golden_angle * k as f64;
+        let offset = tangent * (r * theta.cos()) + bitangent * (r * theta.sin());
+        sum + transmitted((direction_to_light + offset).normalize())
+    });
+
+    sum * (1.0 / samples as f32)
+}
+
+/// the light passing along a shadow ray up to `distance`. opaque objects block it, refractive
+/// ones let their transparency through at every crossing and absorb light between entering
+/// and leaving them, which tints the shadows of colored glass. the rays are not bent.
+fn shadow_transmittance(scene: &Scene, shadow_ray: &Ray, distance: f64) -> Color {
+    let white = Color::from_rgb(1.0, 1.0, 1.0);
+    if !scene.occludes(shadow_ray, distance) {
+        return white;
+    }
+
+    let mut transmitted = white;
+    // the objects the ray is inside of and where it entered them
+    let mut entered: Vec<(usize, f64)> = Vec::new();
+    for hit in scene
+        .trace_all(shadow_ray)
+        .iter()
+        .take_while(|hit| hit.distance() < distance)
+    {
+        let refraction = match hit.refraction() {
+            Some(refraction) => refraction,
+            None => return Color::from_rgb(0.0, 0.0, 0.0),
+        };
+        let transparency = refraction.transparency;
+        transmitted = transmitted * Color::from_rgb(transparency, transparency, transparency);
+        if hit.is_exit(&shadow_ray.direction) {
+            // a ray starting inside the object entered it at its origin
+            let start = entered
+                .iter()
+                .rposition(|&(id, _)| id == hit.object_id())
+                .map_or(0.0, |index| entered.remove(index).1);
+            transmitted = transmitted * absorb(&refraction.absorption, hit.distance() - start);
+        } else {
+            entered.push((hit.object_id(), hit.distance()));
+        }
+    }
+    transmitted
+}
+
+/// light reaching the intersection from emissive objects, sampled over patches of their
+/// surface instead of waiting for a ray to run into them
+fn shade_emitters(
+    scene: &Scene,
+    intersection: &IntersectionResult,
+    normal: Direction,
+    options: &RenderOptions,
+) -> Color {
+    let mut color = Color::from_rgba(0.0, 0.0, 0.0, 0.0);
+    for emitter in scene
+        .objects
+        .iter()
+        .filter(|object| object.is_emissive() && object.id() != intersection.object_id())
+    {
+        for patch in emitter.surface_patches(options.light_samples) {
+            let to_patch = patch.center - intersection.hit_point();
+            let distance = to_patch.magnitude();
+            let direction = to_patch / distance;
+            let cos_surface = normal.dot(direction);
+            let cos_emitter = -patch.normal().dot(direction);
+            if cos_surface <= 0.0 || cos_emitter <= 0.0 {
+                continue;
+            }
+            let visible = if options.shadows() {
+                let shadow_ray =
+                    Ray::create_shadow_ray(direction, intersection, options.shadow_bias);
+                shadow_transmittance(scene, &shadow_ray, distance * (1.0 - 1e-6))
+            } else {
+                Color::from_rgb(1.0, 1.0, 1.0)
+            };
+
+            let geometry = cos_surface * cos_emitter * patch.area() / (distance * distance);
+            color = color
+                + intersection.color()
+                    * emitter.emission()
+                    * visible
+                    * (geometry as f32 * intersection.albedo() / PI);
+        }
+    }
+
+    color
+}
+
+/// which of the sampled lights cast shadow rays. only lights casting shadows from a
+/// direction can, with `RenderOptions::shadowed_lights` set only the ones of them with the
+/// largest estimated contribution, radiance times the cosine at the surface, do
+fn shadowed_lights(
+    samples: &[LightSample],
+    casts_shadows: &[bool],
+    normal: Direction,
+    options: &RenderOptions,
+) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..samples.len())
+        .filter(|&index| {
+            casts_shadows[index] && matches!(samples[index], LightSample::Directed { .. })
+        })
+        .collect();
+    let count = match options.shadowed_lights {
+        Some(count) => min(count, order.len()),
+        None => order.len(),
+    };
+    let estimate = |sample: &LightSample| match *sample {
+        LightSample::Directed {
+            direction,
+            ref radiance,
+            ..
+        } => luminance(radiance) as f64 * normal.dot(direction).max(0.0),
+        LightSample::Ambient(_) => 0.0,
+    };
+    order.sort_by(|&a, &b| {
+        estimate(&samples[b])
+            .partial_cmp(&estimate(&samples[a]))
+            .unwrap_or(::std::cmp::Ordering::Equal)
+    });
+
+    let mut shadowed = vec![false; samples.len()];
+    for &index in &order[..count] {
+        shadowed[index] = true;
+    }
+    shadowed
+}
+
+fn shade_diffuse(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &IntersectionResult,
+    options: &RenderOptions,
+) -> Color {
+    let normal = intersection.facing_normal(&ray.direction);
     let mut color = Color::from_rgb(0.0, 0.0, 0.0);
-    for light in &scene.lights {
-        let direction_to_light = (-light.direction()).normalize();
-        let shadow_ray = Ray::create_shadow_ray(direction_to_light, intersection);
-        let shadow_trace: Option<IntersectionResult> = scene.trace(&shadow_ray);
-        if shadow_trace.is_none() {
-            let light_intensity = light.intensity();
-            let light_power = (intersection.surface_normal().dot(direction_to_light) as f32).abs();
+    if intersection.albedo() > 0.0 {
+        color = color + shade_emitters(scene, intersection, normal, options);
+    }
+    let samples: Vec<_> = scene
+        .lights
+        .iter()
+        .filter(|light| light.lights(intersection.light_layers()))
+        .map(|light| (light, light.sample(intersection.hit_point())))
+        .collect();
+    let light_samples: Vec<_> = samples.iter().map(|&(_, sample)| sample).collect();
+    let casts_shadows: Vec<_> = samples
+        .iter()
+        .map(|&(light, _)| light.casts_shadows())
+        .collect();
+    let shadowed = shadowed_lights(&light_samples, &casts_shadows, normal, options);
+    for (index, &(light, sample)) in samples.iter().enumerate() {
+        let (direction_to_light, distance, radiance) = match sample {
+            LightSample::Directed {
+                direction,
+                distance,
+                radiance,
+            } => (direction, distance, radiance),
+            LightSample::Ambient(radiance) => {
+                // ambient light reaches every surface, whatever it faces
+                color = color + intersection.color() * radiance * intersection.albedo();
+                continue;
+            }
+        };
+        if normal.dot(direction_to_light) <= 0.0 {
+            continue;
+        }
+        let visibility = if options.shadows() && shadowed[index] {
+            light_visibility(
+                scene,
+                intersection,
+                direction_to_light,
+                distance,
+                light.softness(),
+                options,
+            )
+        } else {
+            Color::from_rgb(1.0, 1.0, 1.0)
+        };
+        if luminance(&visibility) > 0.0 {
+            let light_power = normal.dot(direction_to_light) as f32;
             let light_reflected = intersection.albedo() / PI;
             color = color
-                + (intersection.color()
-                    * light.color().clone()
-                    * light_power
-                    * light_intensity
-                    * light_reflected);
+                + (intersection.color() * radiance * light_power * visibility * light_reflected);
         }
     }
 
     color
 }
 
-fn get_color(scene: &Scene, ray: &Ray, intersection: &IntersectionResult, depth: u32) -> Color {
-    let mut color = shade_diffuse(scene, intersection);
-    if let Some(relf) = intersection.reflectivity() {
-        let reflection_ray = Ray::create_reflection(&ray.direction, intersection);
-        let reflection_color = cast_ray(scene, &reflection_ray, depth + 1) * relf;
+/// how many rays `trace_reflection` traces for the hit
+fn reflection_rays(intersection: &IntersectionResult, depth: u32) -> usize {
+    if intersection.roughness() > 0.0 && depth == 0 {
+        GLOSSY_SAMPLES
+    } else {
+        1
+    }
+}
+
+/// the light reflected towards the ray. rough surfaces average rays spread on a spiral
+/// around the mirror direction, deeper bounces follow only the mirror direction so the
+/// number of rays doesn't multiply with every bounce
+fn trace_reflection<R: TraceRecorder>(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &IntersectionResult,
+    depth: u32,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Color {
+    let mirror = Ray::create_reflection(&ray.direction, intersection, options.shadow_bias);
+    if reflection_rays(intersection, depth) == 1 {
+        return trace_ray(scene, &mirror, depth + 1, options, recorder);
+    }
+    let roughness = intersection.roughness() as f64;
+
+    let normal = intersection.facing_normal(&ray.direction);
+    let (tangent, bitangent) = orthonormal_basis(&mirror.direction);
+    let golden_angle = ::std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    let sum = (0..GLOSSY_SAMPLES).fold(Color::from_rgba(0.0, 0.0, 0.0, 0.0), |sum, k| {
+        let r = roughness * ((k as f64 + 0.5) / GLOSSY_SAMPLES as f64).sqrt();
+        let theta = golden_angle * k as f64;
+        let offset = tangent * (r * theta.cos()) + bitangent * (r * theta.sin());
+        let mut direction = (mirror.direction + offset).normalize();
+        // directions spread below the surface are folded back above it
+        if direction.dot(normal) < 0.0 {
+            direction -= normal * (2.0 * direction.dot(normal));
+        }
+        let glossy = Ray::create(
+            intersection.ray_origin(&direction, options.shadow_bias),
+            direction,
+            RayType::Reflection,
+        );
+        sum + trace_ray(scene, &glossy, depth + 1, options, recorder)
+    });
+
+    sum * (1.0 / GLOSSY_SAMPLES as f32)
+}
+
+/// a shadow catcher is black with the alpha of the shadow on it, over whatever lies behind.
+/// the shadow is the fraction of the direct light from shadow casting lights that is blocked.
+fn catch_shadow<R: TraceRecorder>(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &IntersectionResult,
+    depth: u32,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Color {
+    let normal = intersection.facing_normal(&ray.direction);
+    let (mut unblocked, mut total) = (0.0, 0.0);
+    for light in scene
+        .lights
+        .iter()
+        .filter(|light| light.casts_shadows() && light.lights(intersection.light_layers()))
+    {
+        let (direction_to_light, distance, radiance) = match light.sample(intersection.hit_point())
+        {
+            LightSample::Directed {
+                direction,
+                distance,
+                radiance,
+            } => (direction, distance, radiance),
+            // nothing to block, ambient light arrives from everywhere
+            LightSample::Ambient(_) => continue,
+        };
+        let power = normal.dot(direction_to_light) as f32 * luminance(&radiance);
+        if power <= 0.0 {
+            continue;
+        }
+        let visibility = if options.shadows() {
+            light_visibility(
+                scene,
+                intersection,
+                direction_to_light,
+                distance,
+                light.softness(),
+                options,
+            )
+        } else {
+            Color::from_rgb(1.0, 1.0, 1.0)
+        };
+        unblocked += power * luminance(&visibility);
+        total += power;
+    }
+    let shadow = if total > 0.0 {
+        1.0 - unblocked / total
+    } else {
+        0.0
+    };
+
+    let through = Ray::create(
+        *intersection.hit_point() + ray.direction * options.shadow_bias,
+        ray.direction,
+        ray.ray_type,
+    );
+    let behind = match scene.trace(&through) {
+        Some(hit) if depth + 1 < options.max_depth() => {
+            get_color(scene, &through, &hit, depth + 1, options, recorder)
+        }
+        None if scene.environment.is_some() => scene.background(&through),
+        _ => Color::from_rgba(0.0, 0.0, 0.0, 0.0),
+    };
+
+    Color::from_rgba(0.0, 0.0, 0.0, shadow).over(behind)
+}
+
+/// light arriving at a diffuse hit from the surfaces around it, gathered by a single bounce.
+/// the sample directions are cosine distributed over the hemisphere, so the reflected
+/// light is the average of what they see, scaled by the albedo.
+fn gather_indirect(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &IntersectionResult,
+    gather: &FinalGather,
+    options: &RenderOptions,
+) -> Color {
+    let black = Color::from_rgb(0.0, 0.0, 0.0);
+    if gather.samples == 0 || intersection.albedo() <= 0.0 {
+        return black;
+    }
+
+    let normal = intersection.facing_normal(&ray.direction);
+    let (tangent, bitangent) = orthonormal_basis(&normal);
+    let golden_angle = ::std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    let gathered = (0..gather.samples).fold(black, |sum, k| {
+        let u = (k as f64 + 0.5) / gather.samples as f64;
+        let r = u.sqrt();
+        let theta = golden_angle * k as f64;
+        let direction = (tangent * (r * theta.cos())
+            + bitangent * (r * theta.sin())
+            + normal * (1.0 - u).sqrt())
+        .normalize();
+        let gather_ray = Ray::create(
+            intersection.ray_origin(&direction, options.shadow_bias),
+            direction,
+            RayType::Reflection,
+        );
+
+        match scene.trace(&gather_ray) {
+            Some(hit) => sum + shade_diffuse(scene, &gather_ray, &hit, options),
+            None => sum,
+        }
+    });
+
+    gathered
+        * intersection.color()
+        * (intersection.albedo() * gather.strength / gather.samples as f32)
+}
+
+/// schlick's approximation of the fraction of light reflected at a dielectric boundary
+fn fresnel(ray_direction: &Direction, normal: &Direction, index: f32) -> f32 {
+    let cos_i = ray_direction.dot(*normal).clamp(-1.0, 1.0);
+    let (eta_i, eta_t) = if cos_i > 0.0 {
+        (index as f64, 1.0)
+    } else {
+        (1.0, index as f64)
+    };
+
+    let sin_t = eta_i / eta_t * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+
+    let r0 = ((eta_i - eta_t) / (eta_i + eta_t)).powi(2);
+    (r0 + (1.0 - r0) * (1.0 - cos_i.abs()).powi(5)) as f32
+}
+
+/// beer-lambert attenuation of light travelling `distance` through an absorbing medium
+fn absorb(absorption: &Color, distance: f64) -> Color {
+    let distance = distance as f32;
+    Color::from_rgb(
+        (-absorption.red * distance).exp(),
+        (-absorption.green * distance).exp(),
+        (-absorption.blue * distance).exp(),
+    )
+}
+
+fn get_color<R: TraceRecorder>(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &IntersectionResult,
+    depth: u32,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Color {
+    let record = recorder.record_hit(depth, ray, intersection);
+    if intersection.is_shadow_catcher() {
+        let color = catch_shadow(scene, ray, intersection, depth, options, recorder);
+        recorder.record_color(record, color);
+        return color;
+    }
+    let mut color = shade_diffuse(scene, ray, intersection, options);
+    if let (Some(gather), true) = (options.final_gather, options.shadows()) {
+        color = color + gather_indirect(scene, ray, intersection, &gather, options);
+    }
+    // emissive surfaces glow on the side they face
+    if ray.direction.dot(intersection.surface_normal()) < 0.0 {
+        color = color + intersection.emission();
+    }
+    let reflectivity = intersection
+        .reflectivity()
+        .filter(|_| options.spend_secondary_rays(reflection_rays(intersection, depth)));
+    if let Some(relf) = reflectivity {
+        let mut reflection_color =
+            trace_reflection(scene, ray, intersection, depth, options, recorder)
+                * intersection.reflection_tint()
+                * relf;
+        let metalness = intersection.metalness();
+        if metalness > 0.0 {
+            let white = Color::from_rgb(1.0, 1.0, 1.0);
+            reflection_color =
+                reflection_color * (white * (1.0 - metalness) + intersection.color() * metalness);
+        }
         color = color * (1.0 - relf) + reflection_color
     }
 
+    let refraction = intersection
+        .refraction()
+        .filter(|_| options.spend_secondary_rays(1));
+    if let Some(refraction) = refraction {
+        let kr = fresnel(
+            &ray.direction,
+            &intersection.surface_normal(),
+            refraction.index,
+        );
+        let reflection_ray =
+            Ray::create_reflection(&ray.direction, intersection, options.shadow_bias);
+        let mut transmitted = trace_ray(scene, &reflection_ray, depth + 1, options, recorder) * kr;
+        if let Some(refraction_ray) = Ray::create_refraction(
+            &ray.direction,
+            intersection,
+            refraction.index,
+            options.shadow_bias,
+        ) {
+            transmitted = transmitted
+                + trace_ray(scene, &refraction_ray, depth + 1, options, recorder) * (1.0 - kr);
+        }
+        color = color * (1.0 - refraction.transparency) + transmitted * refraction.transparency;
+
+        // the ray reached this hit through the medium, so everything seen from here
+        // was dimmed along the way
+        if intersection.is_exit(&ray.direction) {
+            color = color * absorb(&refraction.absorption, intersection.distance());
+        }
+    }
+
+    recorder.record_color(record, color);
     color
 }
 
-pub fn cast_ray(scene: &Scene, ray: &Ray, depth: u32) -> Color {
-    if depth >= 32 {
+fn trace_ray<R: TraceRecorder>(
+    scene: &Scene,
+    ray: &Ray,
+    depth: u32,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Color {
+    if depth >= options.max_depth() {
         return Color::from_rgb(0.0, 0.0, 0.0);
     }
 
-    scene
-        .trace(&ray)
-        .map(|int| get_color(scene, &ray, &int, depth))
-        .unwrap_or(Color::from_rgb(0.0, 0.0, 0.0))
+    match scene.trace(ray) {
+        Some(int) => get_color(scene, ray, &int, depth, options, recorder),
+        None => scene.background(ray),
+    }
+}
+
+pub fn cast_ray(scene: &Scene, ray: &Ray, depth: u32, options: &RenderOptions) -> Color {
+    trace_ray(scene, ray, depth, options, &mut NoRecording)
+}
+
+/// observes every hit shaded while following a ray through the scene
+trait TraceRecorder {
+    fn record_hit(&mut self, depth: u32, ray: &Ray, intersection: &IntersectionResult) -> usize;
+    fn record_color(&mut self, index: usize, color: Color);
+}
+
+struct NoRecording;
+
+impl TraceRecorder for NoRecording {
+    #[inline]
+    fn record_hit(&mut self, _: u32, _: &Ray, _: &IntersectionResult) -> usize {
+        0
+    }
+
+    #[inline]
+    fn record_color(&mut self, _: usize, _: Color) {}
+}
+
+/// what a prime ray hits first: the object, the distance and the surface normal there
+type PrimeHit = (usize, f64, Direction);
+
+/// keeps the first hit of the prime rays traced with it, that of the first sample of a
+/// pixel is what its aovs show
+#[derive(Default)]
+struct FirstHit {
+    hit: Option<PrimeHit>,
+}
+
+impl TraceRecorder for FirstHit {
+    fn record_hit(&mut self, depth: u32, _: &Ray, intersection: &IntersectionResult) -> usize {
+        if depth == 0 && self.hit.is_none() {
+            self.hit = Some((
+                intersection.object_id(),
+                intersection.distance(),
+                intersection.surface_normal(),
+            ));
+        }
+        0
+    }
+
+    fn record_color(&mut self, _: usize, _: Color) {}
+}
+
+#[derive(Debug)]
+pub struct TraceHit {
+    pub depth: u32,
+    pub ray_type: RayType,
+    pub hit_point: Point,
+    pub object_id: usize,
+    pub surface_normal: Direction,
+    /// the color of this hit, including everything gathered by its secondary rays
+    pub color: Color,
+}
+
+/// every hit along the path of a single pixel's ray, in the order they were shaded.
+/// the depth of each hit tells the tree apart: the secondary rays of a hit follow it
+/// with a greater depth.
+#[derive(Debug)]
+pub struct TraceDebug {
+    pub hits: Vec<TraceHit>,
+    pub color: Color,
+}
+
+impl TraceRecorder for TraceDebug {
+    fn record_hit(&mut self, depth: u32, ray: &Ray, intersection: &IntersectionResult) -> usize {
+        self.hits.push(TraceHit {
+            depth,
+            ray_type: ray.ray_type,
+            hit_point: *intersection.hit_point(),
+            object_id: intersection.object_id(),
+            surface_normal: intersection.surface_normal(),
+            color: Color::from_rgb(0.0, 0.0, 0.0),
+        });
+        self.hits.len() - 1
+    }
+
+    fn record_color(&mut self, index: usize, color: Color) {
+        self.hits[index].color = color;
+    }
+}
+
+/// follows the center ray of a single pixel and records each bounce on the way
+pub fn trace_pixel(
+    x: u32,
+    y: u32,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> TraceDebug {
+    let ray = Ray::create_prime(x as f64, y as f64, scene, camera);
+    let mut debug = TraceDebug {
+        hits: Vec::new(),
+        color: Color::from_rgb(0.0, 0.0, 0.0),
+    };
+    debug.color = trace_ray(scene, &ray, 0, options, &mut debug);
+    debug
+}
+
+pub fn sample(
+    x: f64,
+    y: f64,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> Option<Color> {
+    sample_in_view(
+        x,
+        y,
+        scene,
+        camera,
+        options,
+        &Culling::none(),
+        &mut NoRecording,
+    )
+}
+
+/// `sample` whose prime ray skips the objects culled for the camera
+fn sample_in_view<R: TraceRecorder>(
+    x: f64,
+    y: f64,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+    culling: &Culling,
+    recorder: &mut R,
+) -> Option<Color> {
+    // exposure scales the light, not the coverage
+    let exposure = Color::from_rgba(options.exposure, options.exposure, options.exposure, 1.0);
+    if let Some(ref lens) = camera.lens {
+        let color = through_lens(x, y, scene, camera, lens, options, recorder) * exposure;
+        return Some(color.clamp());
+    }
+
+    let ray = Ray::create_prime(x, y, scene, camera);
+    let hit = scene.trace_culled(&ray, culling);
+    shade_prime(scene, &ray, hit, options, recorder)
+}
+
+/// the pixel color of a prime ray and what it hit, `None` for a miss without an environment
+fn shade_prime<R: TraceRecorder>(
+    scene: &Scene,
+    ray: &Ray,
+    hit: Option<IntersectionResult>,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Option<Color> {
+    let exposure = Color::from_rgba(options.exposure, options.exposure, options.exposure, 1.0);
+    let color = match hit {
+        Some(inter) => get_color(scene, ray, &inter, 0, options, recorder),
+        None if scene.environment.is_some() => scene.background(ray),
+        None => return None,
+    };
+    Some((color * exposure).clamp())
 }
 
-pub fn sample(x: f64, y: f64, scene: &Scene, camera: &Camera) -> Option<Rgba<u8>> {
-    let ray = Ray::create_prime(x, y, &scene, &camera);
-    let trace = scene.trace(&ray);
-    trace.map(|inter| {
-        let color = get_color(&scene, &ray, &inter, 0);
-        color.clamp().to_rgba8()
-    })
+/// the samples `passes` of a few neighboring pixels, each round of their prime rays traced
+/// as one packet, and what their first samples hit if they are among them
+fn packet_samples(
+    pixels: &[(u32, u32)],
+    passes: Range<usize>,
+    job: &RenderJob,
+) -> (Vec<Vec<Sample>>, Vec<Option<PrimeHit>>) {
+    let (scene, camera, options) = (&*job.scene, &job.camera, &job.options);
+    let black = Color::from_rgb(0.0, 0.0, 0.0);
+    let offsets: Vec<Vec<(f64, f64)>> = pixels
+        .iter()
+        .map(|&(x, y)| sample_offsets(x, y, options))
+        .collect();
+    let mut samples: Vec<Vec<Sample>> = pixels.iter().map(|_| Vec::new()).collect();
+    let mut first_hits: Vec<Option<PrimeHit>> = pixels.iter().map(|_| None).collect();
+
+    for k in passes.filter(|&k| k < offsets[0].len()) {
+        let rays: Vec<Ray> = pixels
+            .iter()
+            .zip(&offsets)
+            .map(|(&(x, y), offsets)| {
+                let (dx, dy) = offsets[k];
+                Ray::create_prime(x as f64 + dx, y as f64 + dy, scene, camera)
+            })
+            .collect();
+        let hits = scene.trace_packet(&rays, &job.culling);
+        let pixel_samples = rays.iter().zip(hits).zip(samples.iter_mut()).zip(&offsets);
+        for (index, (((ray, hit), pixel), offsets)) in pixel_samples.enumerate() {
+            let mut first = FirstHit::default();
+            let color = shade_prime(scene, ray, hit, options, &mut first).unwrap_or(black);
+            if k == 0 {
+                first_hits[index] = first.hit;
+            }
+            pixel.push((color, offsets[k]));
+        }
+    }
+
+    (samples, first_hits)
 }
 
-pub fn average_color(samples: Vec<Rgba<u8>>) -> Rgba<u8> {
-    let sample_count = samples.len();
-    let data: [usize; 4] = samples.iter().fold([0, 0, 0, 0], |mut data, sample| {
-        data[0] = data[0] + sample.data[0] as usize;
-        data[1] = data[1] + sample.data[1] as usize;
-        data[2] = data[2] + sample.data[2] as usize;
-        data[3] = data[3] + sample.data[3] as usize;
-        data
+/// averages rays spread over the lens on a spiral. with chromatic aberration every channel
+/// is traced on its own, focused at its own distance.
+fn through_lens<R: TraceRecorder>(
+    x: f64,
+    y: f64,
+    scene: &Scene,
+    camera: &Camera,
+    lens: &Lens,
+    options: &RenderOptions,
+    recorder: &mut R,
+) -> Color {
+    let golden_angle = ::std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    let mut focused = |point: (f64, f64), shift: f64| {
+        let focal_distance = lens.focal_distance * (1.0 + shift * lens.chromatic_aberration);
+        let ray = camera.lens_ray(x, y, point, lens, focal_distance);
+        trace_ray(scene, &ray, 0, options, recorder)
+    };
+
+    let samples = options.lens_samples.max(1);
+    let sum = (0..samples).fold(Color::from_rgba(0.0, 0.0, 0.0, 0.0), |sum, k| {
+        let r = ((k as f64 + 0.5) / samples as f64).sqrt();
+        let theta = golden_angle * k as f64;
+        let point = (r * theta.cos(), r * theta.sin());
+
+        let green = focused(point, 0.0);
+        if lens.chromatic_aberration == 0.0 {
+            return sum + green;
+        }
+        let red = focused(point, 1.0);
+        let blue = focused(point, -1.0);
+        sum + Color::from_rgba(red.red, green.green, blue.blue, green.alpha)
     });
 
-    let data: [u8; 4] = [
-        (data[0] / sample_count) as u8,
-        (data[1] / sample_count) as u8,
-        (data[2] / sample_count) as u8,
-        (data[3] / sample_count) as u8,
-    ];
+    sum * (1.0 / samples as f32)
+}
+
+/// the color of a sample and its offset from the pixel center
+pub type Sample = (Color, (f64, f64));
 
-    Rgba(data)
+/// the average of the samples weighted by the reconstruction filter at their offsets from
+/// the pixel center, a plain average if none of them is in the filter's reach. the colors
+/// are summed as floats and only the result is rounded to 8 bits
+pub fn weighted_color(samples: &[Sample], filter: PixelFilter) -> Rgba<u8> {
+    let mut sum = ColorSum::default();
+    for &(color, (dx, dy)) in samples {
+        sum.add(color, filter.weight(dx, dy));
+    }
+    if sum.weight() <= 0.0 {
+        sum = ColorSum::default();
+        for &(color, _) in samples {
+            sum.add(color, 1.0);
+        }
+    }
+    sum.mean()
+        .unwrap_or_else(|| Color::from_rgba(0.0, 0.0, 0.0, 0.0))
+        .encode_srgb()
+        .to_rgba8()
 }
 
-pub fn super_sample(x: f64, y: f64, scene: &Scene, camera: &Camera) -> Option<Rgba<u8>> {
-    let black = Color::from_rgb(0.0, 0.0, 0.0).to_rgba8();
-    let samples = vec![
-        sample((x - 0.25), (y - 0.25), scene, camera).unwrap_or(black),
-        sample((x + 0.25), (y - 0.25), scene, camera).unwrap_or(black),
-        sample((x - 0.25), (y + 0.25), scene, camera).unwrap_or(black),
-        sample((x + 0.25), (y + 0.25), scene, camera).unwrap_or(black),
-        sample((x), (y), scene, camera).unwrap_or(black),
-    ];
+/// the pixel center first, so taking fewer samples keeps them centered
+const SUPER_SAMPLE_OFFSETS: [(f64, f64); 5] = [
+    (0.0, 0.0),
+    (-0.25, -0.25),
+    (0.25, 0.25),
+    (0.25, -0.25),
+    (-0.25, 0.25),
+];
+
+/// one jittered sample in each cell of an n×n grid over the pixel
+fn stratified_offsets(x: u32, y: u32, options: &RenderOptions) -> Vec<(f64, f64)> {
+    let n = (options.samples as f64).sqrt().round().max(1.0) as u32;
+    let mut random = Random::for_pixel(options.seed, x, y);
+    let cell = 1.0 / n as f64;
 
-    Some(average_color(samples))
+    (0..n * n)
+        .map(|i| {
+            let dx = ((i % n) as f64 + random.next_f64()) * cell - 0.5;
+            let dy = ((i / n) as f64 + random.next_f64()) * cell - 0.5;
+            (dx, dy)
+        })
+        .collect()
 }
 
-pub fn render(scene: Scene, camera: Camera) -> DynamicImage {
-    let workers = num_cpus::get();
-    let pool = ThreadPool::new(workers);
+/// the sub-pixel positions sampled for the pixel at `x`, `y`
+pub fn sample_offsets(x: u32, y: u32, options: &RenderOptions) -> Vec<(f64, f64)> {
+    match (options.quality, options.sampling) {
+        (RenderQuality::Preview, _) => SUPER_SAMPLE_OFFSETS[..1].to_vec(),
+        (RenderQuality::Final, Sampling::Fixed) => {
+            SUPER_SAMPLE_OFFSETS[..options.samples.clamp(1, SUPER_SAMPLE_OFFSETS.len())].to_vec()
+        }
+        (RenderQuality::Final, Sampling::Stratified) => stratified_offsets(x, y, options),
+    }
+}
 
-    let sw = camera.width;
-    let sh = camera.height;
+pub fn super_sample(
+    x: f64,
+    y: f64,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> Option<Rgba<u8>> {
+    let black = Color::from_rgb(0.0, 0.0, 0.0);
+    let samples: Vec<Sample> = sample_offsets(x as u32, y as u32, options)
+        .into_iter()
+        .map(|(dx, dy)| {
+            let color = sample(x + dx, y + dy, scene, camera, options).unwrap_or(black);
+            (color, (dx, dy))
+        })
+        .collect();
 
-    let tile_size = 128;
-    let cols = (camera.width as f32 / tile_size as f32).ceil() as u32;
-    let rows = (camera.height as f32 / tile_size as f32).ceil() as u32;
-    let jobs = cols * rows;
-    let asc = Arc::new(scene);
-    let camera = Arc::new(camera);
+    Some(weighted_color(&samples, options.filter))
+}
 
-    let (tx, rx) = channel();
-    for job_idx in 0..jobs {
-        let mx = tile_size * (job_idx % cols);
-        let my = tile_size * (job_idx / cols);
-        let black = Color::from_rgb(0.0, 0.0, 0.0).to_rgba8();
-        let mscene = asc.clone();
-        let tx = tx.clone();
-        let camera = camera.clone();
-        pool.execute(move || {
-            let start = Instant::now();
-            let tile_width = min(mx + tile_size, sw) - mx;
-            let tile_height = min(my + tile_size, sh) - my;
-            let mut image = DynamicImage::new_rgb8(tile_width, tile_height);
-
-            for x in 0..tile_width {
-                for y in 0..tile_height {
-                    let color = super_sample((mx + x) as f64, (my + y) as f64, &mscene, &camera)
-                        .unwrap_or(black);
-                    image.put_pixel(x, y, color);
-                }
+/// what the ray through the center of a pixel shows: its color, and the object, distance and
+/// surface normal of the hit
+struct CenterSample {
+    color: Color,
+    hit: Option<PrimeHit>,
+}
+
+impl CenterSample {
+    fn differs_from(&self, other: &CenterSample, detection: &EdgeDetection) -> bool {
+        let geometry = match (self.hit, other.hit) {
+            (Some((id_a, distance_a, normal_a)), Some((id_b, distance_b, normal_b))) => {
+                id_a != id_b
+                    || (distance_a - distance_b).abs()
+                        > detection.depth * distance_a.min(distance_b)
+                    || 1.0 - normal_a.dot(normal_b) > detection.normal
             }
-            tx.send((image, mx, my)).unwrap();
-        });
+            (None, None) => false,
+            _ => true,
+        };
+        let (a, b) = (self.color, other.color);
+        geometry
+            || (a.red - b.red).abs() > detection.color
+            || (a.green - b.green).abs() > detection.color
+            || (a.blue - b.blue).abs() > detection.color
     }
+}
 
-    let mut counter = 0;
-    rx.iter()
-        .inspect(|_| {
-            counter = counter + 1;
-            println!("{:?} of {:?} done", counter, jobs);
+/// the center samples of an area of the image and of the pixels around it, the first pass of
+/// `RenderOptions::edge_antialiasing`
+struct EdgeMask {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    samples: Vec<CenterSample>,
+    detection: EdgeDetection,
+}
+
+impl EdgeMask {
+    /// traces the center of every pixel in the area and one pixel around it, as far as
+    /// that is inside the camera's image
+    fn trace(
+        area: Crop,
+        scene: &Scene,
+        camera: &Camera,
+        options: &RenderOptions,
+        culling: &Culling,
+        detection: EdgeDetection,
+    ) -> EdgeMask {
+        let black = Color::from_rgb(0.0, 0.0, 0.0);
+        let (x0, y0) = (area.x.saturating_sub(1), area.y.saturating_sub(1));
+        let (x1, y1) = (
+            min(area.x + area.width + 1, camera.width),
+            min(area.y + area.height + 1, camera.height),
+        );
+        let samples = (y0..y1)
+            .flat_map(|py| (x0..x1).map(move |px| (px, py)))
+            .map(|(px, py)| {
+                let mut first = FirstHit::default();
+                let (x, y) = (px as f64, py as f64);
+                let color = sample_in_view(x, y, scene, camera, options, culling, &mut first);
+                CenterSample {
+                    color: color.unwrap_or(black),
+                    hit: first.hit,
+                }
+            })
+            .collect();
+
+        EdgeMask {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+            samples,
+            detection,
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> Option<&CenterSample> {
+        if x < self.x || y < self.y || x - self.x >= self.width || y - self.y >= self.height {
+            return None;
+        }
+        Some(&self.samples[((y - self.y) * self.width + (x - self.x)) as usize])
+    }
+
+    /// whether the pixel differs from any of the four next to it
+    fn is_edge(&self, x: u32, y: u32) -> bool {
+        let center = match self.get(x, y) {
+            Some(center) => center,
+            None => return false,
+        };
+        let neighbors = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        neighbors.iter().any(|&(nx, ny)| {
+            self.get(nx, ny)
+                .is_some_and(|neighbor| center.differs_from(neighbor, &self.detection))
         })
-        .take(jobs as usize)
-        .fold(DynamicImage::new_rgb8(sw, sh), |mut image, result| {
-            let (part, x, y) = result;
-            image.copy_from(&part, x, y);
-            image
+    }
+
+    /// the encoded color of the pixel's center sample and what it hit, `None` on an edge
+    /// where the pixel takes all of `sample_offsets`
+    fn center(&self, x: u32, y: u32) -> Option<(Rgba<u8>, Option<PrimeHit>)> {
+        if self.is_edge(x, y) {
+            return None;
+        }
+        self.get(x, y)
+            .map(|center| (center.color.encode_srgb().to_rgba8(), center.hit))
+    }
+}
+
+/// `super_sample` for a pixel known to miss everything, only looking up the background
+fn background_sample(
+    x: u32,
+    y: u32,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> Rgba<u8> {
+    let black = Color::from_rgb(0.0, 0.0, 0.0);
+    if scene.environment.is_none() {
+        return black.to_rgba8();
+    }
+    let samples: Vec<Sample> = sample_offsets(x, y, options)
+        .into_iter()
+        .map(|(dx, dy)| {
+            let ray = Ray::create_prime(x as f64 + dx, y as f64 + dy, scene, camera);
+            (
+                shade_prime(scene, &ray, None, options, &mut NoRecording).unwrap_or(black),
+                (dx, dy),
+            )
         })
+        .collect();
+    weighted_color(&samples, options.filter)
+}
+
+/// auxiliary buffers recorded from the first sample of every pixel, what its prime ray hit.
+///
+/// depth is stored as luma scaled by `RenderOptions::depth_range` (misses are white),
+/// normals are mapped from [-1, 1] to [0, 255] per axis and the object id is stored
+/// as `id + 1` across the rgb channels, leaving 0 for pixels that hit nothing.
+pub struct Aovs {
+    pub depth: DynamicImage,
+    pub normal: DynamicImage,
+    pub object_id: DynamicImage,
+}
+
+impl Aovs {
+    pub fn new(width: u32, height: u32) -> Aovs {
+        Aovs {
+            depth: DynamicImage::new_luma8(width, height),
+            normal: DynamicImage::new_rgb8(width, height),
+            object_id: DynamicImage::new_rgb8(width, height),
+        }
+    }
+
+    fn record(&mut self, x: u32, y: u32, hit: Option<PrimeHit>, depth_range: f64) {
+        let black = Rgba([0, 0, 0, 255]);
+        match hit {
+            Some((object_id, distance, normal)) => {
+                let depth = ((distance / depth_range).min(1.0) * 255.0).round() as u8;
+                let encode = |v: f64| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+                let id = object_id + 1;
+
+                self.depth.put_pixel(x, y, Rgba([depth, depth, depth, 255]));
+                self.normal.put_pixel(
+                    x,
+                    y,
+                    Rgba([encode(normal.x), encode(normal.y), encode(normal.z), 255]),
+                );
+                self.object_id.put_pixel(
+                    x,
+                    y,
+                    Rgba([(id >> 16) as u8, (id >> 8) as u8, id as u8, 255]),
+                );
+            }
+            None => {
+                self.depth.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                self.normal.put_pixel(x, y, black);
+                self.object_id.put_pixel(x, y, black);
+            }
+        }
+    }
+
+    fn copy_from(&mut self, part: &Aovs, x: u32, y: u32) {
+        self.depth.copy_from(&part.depth, x, y);
+        self.normal.copy_from(&part.normal, x, y);
+        self.object_id.copy_from(&part.object_id, x, y);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// colors each pixel by how many bounding boxes and triangles its ray was tested
+    /// against, from black over red and yellow to white at `RenderOptions::heatmap_range`
+    BvhHeatmap,
+    /// colors each hit by its texture coordinates, u in red and v in green, with a
+    /// checkerboard of 8×8 cells per texture in blue to show how the texture is stretched
+    Uv,
+}
+
+/// black -> red -> yellow -> white
+fn heatmap_color(value: f32) -> Color {
+    let t = value.clamp(0.0, 1.0) * 3.0;
+    Color::from_rgb(
+        t.min(1.0),
+        (t - 1.0).clamp(0.0, 1.0),
+        (t - 2.0).clamp(0.0, 1.0),
+    )
+}
+
+/// renders the center of a pixel with one of the debug views instead of shading it
+pub fn debug_sample(
+    x: f64,
+    y: f64,
+    scene: &Scene,
+    camera: &Camera,
+    options: &RenderOptions,
+    view: DebugView,
+) -> Rgba<u8> {
+    let ray = Ray::create_prime(x, y, scene, camera);
+    match view {
+        DebugView::BvhHeatmap => {
+            let cost = scene.traversal_cost(&ray);
+            heatmap_color(cost as f32 / options.heatmap_range as f32).to_rgba8()
+        }
+        DebugView::Uv => match scene.trace(&ray) {
+            Some(hit) => {
+                let (u, v) = (hit.texture_coord().x, hit.texture_coord().y);
+                let cell = (u * 8.0).floor() as i64 + (v * 8.0).floor() as i64;
+                let checker = if cell.rem_euclid(2) == 0 { 0.0 } else { 0.5 };
+                Color::from_rgb(u.rem_euclid(1.0), v.rem_euclid(1.0), checker).to_rgba8()
+            }
+            None => Color::from_rgb(0.0, 0.0, 0.0).to_rgba8(),
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// the same few positions in every pixel
+    Fixed,
+    /// a jittered position in each cell of a grid, trading the aliasing of regular patterns
+    /// for noise
+    Stratified,
+}
+
+/// the reconstruction filter weighting the samples of a pixel, offsets are in pixels from its
+/// center
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter {
+    /// every sample counts the same
+    Box,
+    /// falls off linearly to 0 at `radius` along each axis
+    Tent { radius: f64 },
+    /// a normal distribution with the standard deviation `sigma`
+    Gaussian { sigma: f64 },
+}
+
+impl PixelFilter {
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        match *self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent { radius } => {
+                (1.0 - dx.abs() / radius).max(0.0) * (1.0 - dy.abs() / radius).max(0.0)
+            }
+            PixelFilter::Gaussian { sigma } => (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp(),
+        }
+    }
+}
+
+/// the order tiles are handed to the workers in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    /// row by row from the top left
+    Scanline,
+    /// from the center outwards, so the subject shows up first
+    Spiral,
+    /// along a hilbert curve, neighboring tiles are rendered close in time
+    Hilbert,
+}
+
+/// the column and row of every tile, in the order they are rendered
+fn tile_order(cols: u32, rows: u32, order: TileOrder) -> Vec<(u32, u32)> {
+    let mut tiles: Vec<(u32, u32)> = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (col, row)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center = |n: u32| (n as f64 - 1.0) / 2.0;
+            let polar = |&(col, row): &(u32, u32)| {
+                let (dx, dy) = (col as f64 - center(cols), row as f64 - center(rows));
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            tiles.sort_by(|a, b| polar(a).partial_cmp(&polar(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let side = cols.max(rows).next_power_of_two();
+            tiles.sort_by_key(|&(col, row)| hilbert_index(side, col, row));
+        }
+    }
+    tiles
+}
+
+/// the position of `x`, `y` along the hilbert curve filling a `side`² grid
+fn hilbert_index(side: u32, x: u32, y: u32) -> u64 {
+    let (mut x, mut y) = (x, y);
+    let mut index = 0u64;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // rotate the quadrant so the curve continues where the previous one ended
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            ::std::mem::swap(&mut x, &mut y);
+        }
+        x &= s - 1;
+        y &= s - 1;
+        s /= 2;
+    }
+    index
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderQuality {
+    Final,
+    /// a single sample per pixel without shadows or secondary rays, for interactive viewers
+    Preview,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub quality: RenderQuality,
+    pub aovs: bool,
+    pub depth_range: f64,
+    /// multiplier applied to the linear color of every sample before it is clamped
+    pub exposure: f32,
+    /// rays at this depth are no longer traced, 1 disables all secondary rays
+    pub max_depth: u32,
+    pub debug_view: Option<DebugView>,
+    /// the traversal cost shown as white in the bvh heatmap
+    pub heatmap_range: usize,
+    /// only render this part of the camera's image, the output has the size of the crop
+    pub crop: Option<Crop>,
+    /// how far secondary rays start off the surface, relative to the hit point's distance from
+    /// the origin
+    pub shadow_bias: f64,
+    /// how many sub-pixel samples a final quality pixel averages. at most 5 for the fixed
+    /// pattern, rounded to a square number for stratified sampling
+    pub samples: usize,
+    pub sampling: Sampling,
+    /// how the samples of a pixel are weighted by their distance from its center
+    pub filter: PixelFilter,
+    /// seeds the random sample positions, the same seed renders the same image
+    pub seed: u64,
+    /// worker threads rendering tiles, one per cpu if not set
+    pub threads: Option<usize>,
+    pub vignette: Option<Vignette>,
+    /// grades the finished image, after the vignette
+    pub lut: Option<Arc<Lut>>,
+    /// adds one bounce of indirect diffuse light, e.g. color bleeding from nearby walls
+    pub final_gather: Option<FinalGather>,
+    /// rays spread over the lens of a camera with depth of field, for every sub-pixel sample
+    pub lens_samples: usize,
+    /// shadow rays towards every soft light and points sampled on every emissive object,
+    /// for every shaded hit
+    pub light_samples: usize,
+    /// only this many lights with the largest estimated contribution at a hit cast shadow
+    /// rays, the others light it as if nothing was in the way. all lights do if not set
+    pub shadowed_lights: Option<usize>,
+    /// caps the reflection and refraction bounces of a whole frame, surfaces shade without
+    /// them once it is used up
+    pub ray_budget: Option<RayBudget>,
+    pub tile_order: TileOrder,
+    /// traces the prime rays of 2×2 pixel blocks as packets, walking each mesh's bvh once
+    /// per block. renders the same image, pinhole cameras only
+    pub packets: bool,
+    /// wall clock time a render may take. pixels not started by then are filled with the
+    /// background, so the image shows the tiles finished in time
+    pub time_limit: Option<Duration>,
+    /// renders this many times the camera's resolution in each direction and averages the
+    /// blocks of pixels back down, anti-aliasing that is independent of `samples`
+    pub resolution_scale: u32,
+    /// the most pixels a render may allocate, counting `resolution_scale`. larger cameras are
+    /// refused before anything is allocated
+    pub max_pixels: u64,
+    /// takes one sample per pixel first and all `samples` only where that finds an edge.
+    /// ignored by debug views and packets
+    pub edge_antialiasing: Option<EdgeDetection>,
+}
+
+/// a number of secondary bounces shared by everything rendered with it. clones draw from the
+/// same budget, so all worker threads of a frame run out together. every frame needs a new one,
+/// `render_views_with_options` gives each view a new one of the same size.
+#[derive(Debug, Clone)]
+pub struct RayBudget {
+    bounces: usize,
+    remaining: Arc<AtomicUsize>,
+}
+
+impl RayBudget {
+    pub fn new(bounces: usize) -> RayBudget {
+        RayBudget {
+            bounces,
+            remaining: Arc::new(AtomicUsize::new(bounces)),
+        }
+    }
+
+    /// a budget of its own with as many bounces as this one started with
+    fn renewed(&self) -> RayBudget {
+        RayBudget::new(self.bounces)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Relaxed)
+    }
+
+    /// takes a bounce for each of the rays from the budget, false and nothing taken if there
+    /// are not enough left
+    fn spend(&self, rays: usize) -> bool {
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                n.checked_sub(rays)
+            })
+            .is_ok()
+    }
+}
+
+/// a rectangle of the camera's image in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// the part of the crop that lies within the camera's image
+    fn clip(&self, camera: &Camera) -> Crop {
+        let x = min(self.x, camera.width);
+        let y = min(self.y, camera.height);
+        Crop {
+            x,
+            y,
+            width: min(self.width, camera.width - x),
+            height: min(self.height, camera.height - y),
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            quality: RenderQuality::Final,
+            aovs: false,
+            depth_range: 100.0,
+            exposure: 1.0,
+            max_depth: 32,
+            debug_view: None,
+            heatmap_range: 1000,
+            crop: None,
+            shadow_bias: SHADOW_BIAS,
+            samples: SUPER_SAMPLE_OFFSETS.len(),
+            sampling: Sampling::Fixed,
+            filter: PixelFilter::Box,
+            seed: 0,
+            threads: None,
+            vignette: None,
+            lut: None,
+            final_gather: None,
+            lens_samples: 16,
+            light_samples: 16,
+            shadowed_lights: None,
+            ray_budget: None,
+            tile_order: TileOrder::Scanline,
+            packets: false,
+            resolution_scale: 1,
+            time_limit: None,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            edge_antialiasing: None,
+        }
+    }
+}
+
+/// a single bounce of indirect diffuse light on top of the direct lighting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FinalGather {
+    /// rays cast into the hemisphere above every diffuse hit
+    pub samples: usize,
+    /// the fraction of the gathered light added to the surface
+    pub strength: f32,
+}
+
+impl Default for FinalGather {
+    fn default() -> FinalGather {
+        FinalGather {
+            samples: 16,
+            strength: 1.0,
+        }
+    }
+}
+
+/// when neighboring pixels count as an edge. they always do if they show different objects or
+/// only one of them hits anything
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeDetection {
+    /// difference in distance, relative to the nearer of the two hits
+    pub depth: f64,
+    /// 1 minus the cosine of the angle between the surface normals
+    pub normal: f64,
+    /// difference in any color channel
+    pub color: f32,
+}
+
+impl Default for EdgeDetection {
+    fn default() -> EdgeDetection {
+        EdgeDetection {
+            depth: 0.05,
+            normal: 0.1,
+            color: 0.1,
+        }
+    }
+}
+
+/// darkens the image toward its corners, applied to the finished image in linear light
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    /// how much of the light is taken away in the corners, 0 leaves the image untouched
+    pub strength: f32,
+    /// distance from the center where the darkening starts, 1 being the corners
+    pub radius: f32,
+}
+
+impl Vignette {
+    /// `region` is the part of the camera's image held by `image`, the vignette is always
+    /// centered on the full image.
+    fn apply(&self, image: &mut DynamicImage, camera: &Camera, region: Crop) {
+        let center_x = camera.width as f32 / 2.0;
+        let center_y = camera.height as f32 / 2.0;
+        let corner = (center_x * center_x + center_y * center_y).sqrt();
+        let radius = self.radius.clamp(0.0, 1.0);
+
+        for x in 0..region.width {
+            for y in 0..region.height {
+                let dx = (region.x + x) as f32 + 0.5 - center_x;
+                let dy = (region.y + y) as f32 + 0.5 - center_y;
+                let distance = (dx * dx + dy * dy).sqrt() / corner;
+                let t = ((distance - radius) / (1.0 - radius).max(1e-6)).clamp(0.0, 1.0);
+                let falloff = t * t * (3.0 - 2.0 * t);
+                let factor = 1.0 - self.strength.clamp(0.0, 1.0) * falloff;
+
+                // the light is dimmed, not the encoded bytes
+                let color = Color::from_rgba8(image.get_pixel(x, y)).decode_srgb();
+                let dimmed = Color::from_rgba(
+                    color.red * factor,
+                    color.green * factor,
+                    color.blue * factor,
+                    color.alpha,
+                );
+                image.put_pixel(x, y, dimmed.encode_srgb().to_rgba8());
+            }
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn preview() -> RenderOptions {
+        RenderOptions {
+            quality: RenderQuality::Preview,
+            ..RenderOptions::default()
+        }
+    }
+
+    fn max_depth(&self) -> u32 {
+        match self.quality {
+            RenderQuality::Final => self.max_depth,
+            RenderQuality::Preview => 1,
+        }
+    }
+
+    fn spend_secondary_rays(&self, rays: usize) -> bool {
+        self.ray_budget
+            .as_ref()
+            .is_none_or(|budget| budget.spend(rays))
+    }
+
+    fn shadows(&self) -> bool {
+        self.quality == RenderQuality::Final
+    }
+}
+
+/// why a render could not start
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    /// the image would have more than `RenderOptions::max_pixels` pixels
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_pixels: u64,
+    },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::TooLarge {
+                width,
+                height,
+                max_pixels,
+            } => write!(
+                f,
+                "a {}x{} image is larger than the limit of {} pixels",
+                width, height, max_pixels
+            ),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+/// whether the camera's image, at the resolution it is rendered in, fits the options' limit
+pub fn check_dimensions(camera: &Camera, options: &RenderOptions) -> Result<(), RenderError> {
+    let scale = options.resolution_scale.max(1) as u64;
+    let pixels = camera.width as u64 * camera.height as u64 * scale * scale;
+    if pixels > options.max_pixels {
+        return Err(RenderError::TooLarge {
+            width: camera.width,
+            height: camera.height,
+            max_pixels: options.max_pixels,
+        });
+    }
+    Ok(())
+}
+
+pub struct RenderOutput {
+    pub image: DynamicImage,
+    pub aovs: Option<Aovs>,
+    pub timing: RenderTiming,
+}
+
+/// how long rendering a tile took and whether it could be skipped, its position is in the
+/// camera's image
+#[derive(Debug, Clone)]
+pub struct TileTiming {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub duration: Duration,
+    /// no object could show up in the tile, it was filled with the background without
+    /// tracing any rays
+    pub skipped: bool,
+}
+
+/// where the time of a render went, to find the expensive regions of a scene
+#[derive(Debug, Clone, Default)]
+pub struct RenderTiming {
+    /// in the order the tiles finished
+    pub tiles: Vec<TileTiming>,
+    /// wall clock time of the whole render
+    pub total: Duration,
+}
+
+impl RenderTiming {
+    pub fn average(&self) -> Duration {
+        if self.tiles.is_empty() {
+            return Duration::default();
+        }
+        let sum: Duration = self.tiles.iter().map(|tile| tile.duration).sum();
+        sum / self.tiles.len() as u32
+    }
+
+    /// the `count` tiles that took longest, slowest first
+    pub fn slowest(&self, count: usize) -> Vec<&TileTiming> {
+        let mut tiles: Vec<&TileTiming> = self.tiles.iter().collect();
+        tiles.sort_by_key(|tile| ::std::cmp::Reverse(tile.duration));
+        tiles.truncate(count);
+        tiles
+    }
+}
+
+pub fn render(scene: Scene, camera: Camera) -> DynamicImage {
+    render_with_options(scene, camera, RenderOptions::default()).image
+}
+
+/// panics if the camera is larger than `RenderOptions::max_pixels`, see
+/// `try_render_with_options`
+pub fn render_with_options(scene: Scene, camera: Camera, options: RenderOptions) -> RenderOutput {
+    try_render_with_options(scene, camera, options).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// `render_with_options` that returns an error instead of allocating an image larger than
+/// `RenderOptions::max_pixels`
+pub fn try_render_with_options(
+    scene: Scene,
+    camera: Camera,
+    options: RenderOptions,
+) -> Result<RenderOutput, RenderError> {
+    check_dimensions(&camera, &options)?;
+    Ok(render_culled(Arc::new(scene), camera, options))
+}
+
+/// renders every camera's view of one scene, the scene and its meshes' bvhs are built once
+/// and shared by all views, each view culls it for its own camera. panics before rendering
+/// anything if a camera is larger than `RenderOptions::max_pixels`
+pub fn render_views(scene: Scene, cameras: Vec<Camera>) -> Vec<DynamicImage> {
+    render_views_with_options(scene, cameras, RenderOptions::default())
+        .into_iter()
+        .map(|output| output.image)
+        .collect()
+}
+
+/// panics if a camera is larger than `RenderOptions::max_pixels`, see
+/// `try_render_views_with_options`
+pub fn render_views_with_options(
+    scene: Scene,
+    cameras: Vec<Camera>,
+    options: RenderOptions,
+) -> Vec<RenderOutput> {
+    try_render_views_with_options(scene, cameras, options).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// `render_views_with_options` that returns an error before rendering anything if a camera
+/// is larger than `RenderOptions::max_pixels`. every view spends a budget of its own, as
+/// large as `options.ray_budget` was when it was created
+pub fn try_render_views_with_options(
+    scene: Scene,
+    cameras: Vec<Camera>,
+    options: RenderOptions,
+) -> Result<Vec<RenderOutput>, RenderError> {
+    for camera in &cameras {
+        check_dimensions(camera, &options)?;
+    }
+    let scene = Arc::new(scene);
+    Ok(cameras
+        .into_iter()
+        .map(|camera| {
+            let options = RenderOptions {
+                ray_budget: options.ray_budget.as_ref().map(RayBudget::renewed),
+                ..options.clone()
+            };
+            render_culled(scene.clone(), camera, options)
+        })
+        .collect())
+}
+
+/// what the workers of one render share
+struct RenderJob {
+    scene: Arc<Scene>,
+    camera: Camera,
+    options: RenderOptions,
+    culling: Culling,
+    deadline: Option<Instant>,
+}
+
+impl RenderJob {
+    /// culls the scene for the camera, the time limit starts now
+    fn new(scene: Arc<Scene>, camera: Camera, options: RenderOptions) -> RenderJob {
+        let culling = Culling::for_camera(&scene, &camera);
+        let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+        RenderJob {
+            scene,
+            camera,
+            options,
+            culling,
+            deadline,
+        }
+    }
+
+    fn pool(&self) -> ThreadPool {
+        ThreadPool::new(self.options.threads.unwrap_or_else(num_cpus::get).max(1))
+    }
+
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// whether prime rays are traced in packets, not for debug views or through a lens
+    fn packets(&self) -> bool {
+        self.options.packets && self.options.debug_view.is_none() && self.camera.lens.is_none()
+    }
+
+    /// how many samples every pixel takes
+    fn passes(&self) -> usize {
+        match self.options.debug_view {
+            Some(_) => 1,
+            None => sample_offsets(0, 0, &self.options).len(),
+        }
+    }
+
+    /// the rendered part of the image and its tiles, in the order they are rendered
+    fn tiles(&self) -> (Crop, Vec<Crop>) {
+        let region = self
+            .options
+            .crop
+            .unwrap_or(Crop {
+                x: 0,
+                y: 0,
+                width: self.camera.width,
+                height: self.camera.height,
+            })
+            .clip(&self.camera);
+        let (sw, sh) = (region.x + region.width, region.y + region.height);
+
+        let tile_size = 128;
+        let cols = (region.width as f32 / tile_size as f32).ceil() as u32;
+        let rows = (region.height as f32 / tile_size as f32).ceil() as u32;
+        let tiles = tile_order(cols, rows, self.options.tile_order)
+            .into_iter()
+            .map(|(col, row)| {
+                let x = region.x + tile_size * col;
+                let y = region.y + tile_size * row;
+                Crop {
+                    x,
+                    y,
+                    width: min(x + tile_size, sw) - x,
+                    height: min(y + tile_size, sh) - y,
+                }
+            })
+            .collect();
+        (region, tiles)
+    }
+
+    /// applies the vignette and the lut to the encoded image of the region
+    fn finish(&self, image: &mut DynamicImage, region: Crop) {
+        if let Some(vignette) = self.options.vignette {
+            vignette.apply(image, &self.camera, region);
+        }
+        if let Some(ref lut) = self.options.lut {
+            lut.apply(image);
+        }
+    }
+}
+
+/// a pixel of a tile being rendered
+enum TilePixel {
+    /// the samples taken so far
+    Sampled(Vec<Sample>),
+    /// a color no more samples change: the background, a debug color or the center sample
+    /// away from edges
+    Done(Rgba<u8>),
+}
+
+/// the pixels of one tile, sampled a few of their `sample_offsets` at a time. a render takes
+/// all of them at once, a progressive render one per pass
+struct TileSampler {
+    area: Crop,
+    /// no object can show up in the tile
+    empty: bool,
+    edges: Option<EdgeMask>,
+    /// row by row
+    pixels: Vec<TilePixel>,
+    aovs: Option<Aovs>,
+    duration: Duration,
+}
+
+impl TileSampler {
+    fn new(area: Crop, job: &RenderJob) -> TileSampler {
+        let start = Instant::now();
+        let options = &job.options;
+        // nothing to trace if no object can show up in the tile. the tile's frustum doesn't
+        // hold prime rays spread over a lens
+        let empty = options.debug_view.is_none()
+            && job.camera.lens.is_none()
+            && job.scene.is_empty_in(
+                &job.camera,
+                &job.culling,
+                area.x,
+                area.y,
+                area.width,
+                area.height,
+            );
+        let edges = match options.edge_antialiasing {
+            Some(detection) if !empty && !job.packets() && options.debug_view.is_none() => {
+                Some(EdgeMask::trace(
+                    area,
+                    &job.scene,
+                    &job.camera,
+                    options,
+                    &job.culling,
+                    detection,
+                ))
+            }
+            _ => None,
+        };
+        let mut tile = TileSampler {
+            area,
+            empty,
+            edges,
+            pixels: Vec::with_capacity((area.width * area.height) as usize),
+            aovs: if options.aovs {
+                Some(Aovs::new(area.width, area.height))
+            } else {
+                None
+            },
+            duration: Duration::default(),
+        };
+        for i in 0..(area.width * area.height) as usize {
+            let pixel = if empty {
+                let (x, y) = tile.position(i);
+                tile.record_aovs(job, i, None);
+                TilePixel::Done(background_sample(x, y, &job.scene, &job.camera, options))
+            } else {
+                TilePixel::Sampled(Vec::new())
+            };
+            tile.pixels.push(pixel);
+        }
+        tile.duration = start.elapsed();
+        tile
+    }
+
+    /// the position of the pixel in the camera's image
+    fn position(&self, i: usize) -> (u32, u32) {
+        let i = i as u32;
+        (
+            self.area.x + i % self.area.width,
+            self.area.y + i / self.area.width,
+        )
+    }
+
+    /// takes the samples `passes` of every pixel that is not done. pixels reached after the
+    /// time limit keep the samples they have, or show the background without any
+    fn sample(&mut self, job: &RenderJob, passes: Range<usize>) {
+        let start = Instant::now();
+        if self.empty {
+            return;
+        }
+        if job.packets() {
+            self.sample_packets(job, passes);
+        } else {
+            for i in 0..self.pixels.len() {
+                self.sample_pixel(job, i, passes.clone());
+            }
+        }
+        self.duration += start.elapsed();
+    }
+
+    fn sample_pixel(&mut self, job: &RenderJob, i: usize, passes: Range<usize>) {
+        if let TilePixel::Done(_) = self.pixels[i] {
+            return;
+        }
+        let (x, y) = self.position(i);
+        let first = passes.start == 0;
+        if job.out_of_time() {
+            self.finish_late(job, i, first);
+            return;
+        }
+
+        let options = &job.options;
+        let (scene, camera, culling) = (&*job.scene, &job.camera, &job.culling);
+        if let Some(view) = options.debug_view {
+            // debug views show no samples, the aovs follow the ray through the center
+            if first {
+                let ray = Ray::create_prime(x as f64, y as f64, scene, camera);
+                let mut center = FirstHit::default();
+                if let Some(hit) = scene.trace_culled(&ray, culling) {
+                    center.record_hit(0, &ray, &hit);
+                }
+                self.record_aovs(job, i, center.hit);
+            }
+            let color = debug_sample(x as f64, y as f64, scene, camera, options, view);
+            self.pixels[i] = TilePixel::Done(color);
+            return;
+        }
+        if let Some((color, hit)) = self.edges.as_ref().and_then(|edges| edges.center(x, y)) {
+            self.record_aovs(job, i, hit);
+            self.pixels[i] = TilePixel::Done(color);
+            return;
+        }
+
+        let black = Color::from_rgb(0.0, 0.0, 0.0);
+        let offsets = sample_offsets(x, y, options);
+        let mut new = Vec::with_capacity(passes.len());
+        for k in passes {
+            let (dx, dy) = match offsets.get(k) {
+                Some(&offset) => offset,
+                None => continue,
+            };
+            let (sx, sy) = (x as f64 + dx, y as f64 + dy);
+            let color = if k == 0 {
+                let mut first = FirstHit::default();
+                let color = sample_in_view(sx, sy, scene, camera, options, culling, &mut first);
+                self.record_aovs(job, i, first.hit);
+                color
+            } else {
+                sample_in_view(sx, sy, scene, camera, options, culling, &mut NoRecording)
+            };
+            new.push((color.unwrap_or(black), (dx, dy)));
+        }
+        if let TilePixel::Sampled(ref mut samples) = self.pixels[i] {
+            samples.extend(new);
+        }
+    }
+
+    /// `sample_pixel` for blocks of 2×2 pixels, their prime rays traced as one packet
+    fn sample_packets(&mut self, job: &RenderJob, passes: Range<usize>) {
+        let (width, height) = (self.area.width, self.area.height);
+        let first = passes.start == 0;
+        for by in (0..height).step_by(2) {
+            for bx in (0..width).step_by(2) {
+                let block: Vec<usize> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|&(dx, dy)| (bx + dx, by + dy))
+                    .filter(|&(x, y)| x < width && y < height)
+                    .map(|(x, y)| (y * width + x) as usize)
+                    .collect();
+                if job.out_of_time() {
+                    for &i in &block {
+                        self.finish_late(job, i, first);
+                    }
+                    continue;
+                }
+                let pixels: Vec<(u32, u32)> = block.iter().map(|&i| self.position(i)).collect();
+                let (samples, first_hits) = packet_samples(&pixels, passes.clone(), job);
+                for ((&i, new), hit) in block.iter().zip(samples).zip(first_hits) {
+                    if first {
+                        self.record_aovs(job, i, hit);
+                    }
+                    if let TilePixel::Sampled(ref mut samples) = self.pixels[i] {
+                        samples.extend(new);
+                    }
+                }
+            }
+        }
+    }
+
+    /// a pixel without samples at the time limit only shows the background
+    fn finish_late(&mut self, job: &RenderJob, i: usize, first: bool) {
+        if first {
+            self.record_aovs(job, i, None);
+        }
+        let (x, y) = self.position(i);
+        if let TilePixel::Sampled(ref samples) = self.pixels[i] {
+            if samples.is_empty() {
+                let color = background_sample(x, y, &job.scene, &job.camera, &job.options);
+                self.pixels[i] = TilePixel::Done(color);
+            }
+        }
+    }
+
+    /// records what the first sample of the pixel hit
+    fn record_aovs(&mut self, job: &RenderJob, i: usize, hit: Option<PrimeHit>) {
+        let (x, y) = self.position(i);
+        if let Some(ref mut aovs) = self.aovs {
+            aovs.record(
+                x - self.area.x,
+                y - self.area.y,
+                hit,
+                job.options.depth_range,
+            );
+        }
+    }
+
+    /// the pixels as they are now, samples averaged by `weighted_color`
+    fn image(&self, filter: PixelFilter) -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(self.area.width, self.area.height);
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let color = match *pixel {
+                TilePixel::Sampled(ref samples) => weighted_color(samples, filter),
+                TilePixel::Done(color) => color,
+            };
+            let i = i as u32;
+            image.put_pixel(i % self.area.width, i / self.area.width, color);
+        }
+        image
+    }
+}
+
+/// `render_with_options` for a shared scene, culled for the camera for this render only. all
+/// workers are done with the scene when this returns
+fn render_culled(scene: Arc<Scene>, camera: Camera, options: RenderOptions) -> RenderOutput {
+    if options.resolution_scale > 1 {
+        return render_downscaled(scene, camera, options);
+    }
+    let render_start = Instant::now();
+    let job = Arc::new(RenderJob::new(scene, camera, options));
+    let pool = job.pool();
+    let (region, tiles) = job.tiles();
+    let jobs = tiles.len();
+    let passes = job.passes();
+
+    let (tx, rx) = channel();
+    for area in tiles {
+        let job = job.clone();
+        let tx = tx.clone();
+        pool.execute(move || {
+            let mut tile = TileSampler::new(area, &job);
+            tile.sample(&job, 0..passes);
+            tx.send(tile).unwrap();
+        });
+    }
+
+    let mut counter = 0;
+    let initial = RenderOutput {
+        image: DynamicImage::new_rgba8(region.width, region.height),
+        aovs: if job.options.aovs {
+            Some(Aovs::new(region.width, region.height))
+        } else {
+            None
+        },
+        timing: RenderTiming::default(),
+    };
+    let mut output = rx
+        .iter()
+        .inspect(|_| {
+            counter += 1;
+            println!("{:?} of {:?} done", counter, jobs);
+        })
+        .take(jobs)
+        .fold(initial, |mut output, tile| {
+            let (x, y) = (tile.area.x - region.x, tile.area.y - region.y);
+            output
+                .image
+                .copy_from(&tile.image(job.options.filter), x, y);
+            if let (Some(aovs), Some(ref tile_aovs)) = (output.aovs.as_mut(), tile.aovs) {
+                aovs.copy_from(tile_aovs, x, y);
+            }
+            output.timing.tiles.push(TileTiming {
+                x: tile.area.x,
+                y: tile.area.y,
+                width: tile.area.width,
+                height: tile.area.height,
+                duration: tile.duration,
+                skipped: tile.empty,
+            });
+            output
+        });
+    pool.join();
+
+    job.finish(&mut output.image, region);
+    output.timing.total = render_start.elapsed();
+    output
+}
+
+/// the camera and options rendering `resolution_scale` times the size of the image
+fn scaled_up(camera: Camera, options: RenderOptions) -> (Camera, RenderOptions) {
+    let scale = options.resolution_scale;
+    let crop = options.crop.map(|crop| Crop {
+        x: crop.x * scale,
+        y: crop.y * scale,
+        width: crop.width * scale,
+        height: crop.height * scale,
+    });
+    let large = Camera {
+        width: camera.width * scale,
+        height: camera.height * scale,
+        ..camera
+    };
+    (
+        large,
+        RenderOptions {
+            crop,
+            resolution_scale: 1,
+            ..options
+        },
+    )
+}
+
+/// box filters every block of `scale`×`scale` pixels down to one, averaged as linear light,
+/// not as encoded bytes
+fn downscale(image: &DynamicImage, scale: u32) -> DynamicImage {
+    let (width, height) = (image.width() / scale, image.height() / scale);
+    let mut small = DynamicImage::new_rgba8(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = ColorSum::default();
+            for i in 0..scale * scale {
+                let pixel = image.get_pixel(x * scale + i % scale, y * scale + i / scale);
+                sum.add(Color::from_rgba8(pixel).decode_srgb(), 1.0);
+            }
+            let average = sum
+                .mean()
+                .map_or(Rgba([0, 0, 0, 0]), |color| color.encode_srgb().to_rgba8());
+            small.put_pixel(x, y, average);
+        }
+    }
+    small
+}
+
+/// renders at `resolution_scale` times the size and box filters the image back down. aovs
+/// can't be averaged, they keep the top left pixel of every block
+fn render_downscaled(scene: Arc<Scene>, camera: Camera, options: RenderOptions) -> RenderOutput {
+    let scale = options.resolution_scale;
+    let (large, options) = scaled_up(camera, options);
+    let output = render_culled(scene, large, options);
+
+    let image = downscale(&output.image, scale);
+    let (width, height) = image.dimensions();
+    let pick = |large: &DynamicImage| {
+        let mut small = large.clone().crop(0, 0, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                small.put_pixel(x, y, large.get_pixel(x * scale, y * scale));
+            }
+        }
+        small
+    };
+
+    RenderOutput {
+        image,
+        aovs: output.aovs.map(|aovs| Aovs {
+            depth: pick(&aovs.depth),
+            normal: pick(&aovs.normal),
+            object_id: pick(&aovs.object_id),
+        }),
+        timing: output.timing,
+    }
+}
+
+/// renders one sample per pixel at a time, calling `on_pass` with the average of the passes
+/// so far and their count. the image sharpens with every pass, after the last one it is the
+/// image `render_with_options` renders with the same options. no more passes start after the
+/// time limit, and aovs are not recorded. panics like `render_with_options` if the camera is
+/// larger than `RenderOptions::max_pixels`, see `try_render_progressive`
+pub fn render_progressive<F: FnMut(&DynamicImage, usize)>(
+    scene: Scene,
+    camera: Camera,
+    options: RenderOptions,
+    on_pass: F,
+) -> DynamicImage {
+    try_render_progressive(scene, camera, options, on_pass).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// `render_progressive` that returns an error before the first pass if the camera is larger
+/// than `RenderOptions::max_pixels`
+pub fn try_render_progressive<F: FnMut(&DynamicImage, usize)>(
+    scene: Scene,
+    camera: Camera,
+    options: RenderOptions,
+    mut on_pass: F,
+) -> Result<DynamicImage, RenderError> {
+    check_dimensions(&camera, &options)?;
+    let scale = options.resolution_scale;
+    let (camera, options) = scaled_up(camera, options);
+    let options = RenderOptions {
+        aovs: false,
+        ..options
+    };
+    let job = Arc::new(RenderJob::new(Arc::new(scene), camera, options));
+    let pool = job.pool();
+    let (region, areas) = job.tiles();
+
+    let mut tiles: Vec<Option<TileSampler>> = areas.iter().map(|_| None).collect();
+    let mut image = DynamicImage::new_rgba8(region.width, region.height);
+    let mut shown = image.clone();
+    for pass in 0..job.passes() {
+        if pass > 0 && job.out_of_time() {
+            break;
+        }
+        let (tx, rx) = channel();
+        for (index, &area) in areas.iter().enumerate() {
+            let tile = tiles[index].take();
+            let job = job.clone();
+            let tx = tx.clone();
+            pool.execute(move || {
+                let mut tile = tile.unwrap_or_else(|| TileSampler::new(area, &job));
+                tile.sample(&job, pass..pass + 1);
+                tx.send((index, tile)).unwrap();
+            });
+        }
+        drop(tx);
+
+        for (index, tile) in rx.iter() {
+            let (x, y) = (tile.area.x - region.x, tile.area.y - region.y);
+            image.copy_from(&tile.image(job.options.filter), x, y);
+            tiles[index] = Some(tile);
+        }
+        shown = image.clone();
+        job.finish(&mut shown, region);
+        if scale > 1 {
+            shown = downscale(&shown, scale);
+        }
+        on_pass(&shown, pass + 1);
+    }
+
+    Ok(shown)
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+    use cgmath::{Deg, One, Quaternion, Rotation3};
+    use color_space::srgb_to_linear;
+    use environment::EnvironmentMap;
+    use image::{DynamicImage, GenericImage, Rgba};
+    use light::{AmbientLight, DirectionalLight, Light, LightSample, ALL_LAYERS};
+    use objects::{
+        Coloration, Instance, Material, Mesh, ObjectBuilder, Plane, Quad, ScalarMap, Sphere,
+        TextureFilter, Visibility,
+    };
+    use raycast::{Ray, RayType};
+    use render::{
+        cast_ray, check_dimensions, hilbert_index, render, render_progressive, render_views,
+        render_views_with_options, render_with_options, sample, sample_offsets, super_sample,
+        tile_order, trace_pixel, try_render_progressive, try_render_views_with_options,
+        try_render_with_options, weighted_color, Crop, DebugView, EdgeDetection, EdgeMask,
+        FinalGather, PixelFilter, RayBudget, RenderError, RenderOptions, Sample, Sampling,
+        TileOrder, Vignette, DEFAULT_MAX_PIXELS, GLOSSY_SAMPLES,
+    };
+    use scene::{Camera, Culling, Lens, Scene, SceneBuilder};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use types::{Color, Direction, Point, Scale};
+    use wavefront_obj::obj;
+
+    /// a mirror floor reflecting a lit white wall
+    fn mirror_scene(floor: Material) -> Scene {
+        SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(floor)
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish()
+    }
+
+    fn floor_ray() -> Ray {
+        Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, -1.0, -1.0).normalize(),
+            RayType::Prime,
+        )
+    }
+
+    fn center_depth(distance: f64) -> u8 {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -distance))
+                    .into(),
+            )
+            .finish();
+        let camera = Camera {
+            width: 9,
+            height: 9,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            aovs: true,
+            depth_range: 20.0,
+            ..RenderOptions::default()
+        };
+
+        let output = render_with_options(scene, camera, options);
+        output.aovs.unwrap().depth.get_pixel(4, 4).data[0]
+    }
+
+    #[test]
+    fn test_edge_antialiasing_only_supersamples_edges() {
+        let scene = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 0.0, -4.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                        .at_position(Point::new(0.0, 0.0, -10.0))
+                        .into(),
+                )
+                .add_light(AmbientLight {
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                })
+                .finish()
+        };
+        let camera = || Camera {
+            width: 32,
+            height: 32,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = || RenderOptions {
+            edge_antialiasing: Some(EdgeDetection::default()),
+            ..RenderOptions::default()
+        };
+        let whole = Crop {
+            x: 0,
+            y: 0,
+            width: 32,
+            height: 32,
+        };
+        let (mask_scene, mask_camera) = (scene(), camera());
+        let mask = EdgeMask::trace(
+            whole,
+            &mask_scene,
+            &mask_camera,
+            &options(),
+            &Culling::none(),
+            EdgeDetection::default(),
+        );
+        let on_sphere = |x: u32| {
+            let ray = Ray::create_prime(x as f64, 16.0, &mask_scene, &mask_camera);
+            mask_scene.trace(&ray).unwrap().distance() < 5.0
+        };
+        let silhouette = (16..32).find(|&x| !on_sphere(x)).unwrap();
+        let image = render_with_options(scene(), camera(), options()).image;
+
+        assert!(mask.center(0, 0).is_some());
+        assert!(mask.center(16, 16).is_some());
+        assert!(mask.center(silhouette - 1, 16).is_none());
+        assert!(mask.center(silhouette, 16).is_none());
+        assert_eq!(image.get_pixel(16, 16), mask.center(16, 16).unwrap().0);
+        assert_eq!(
+            image.get_pixel(silhouette, 16),
+            super_sample(
+                silhouette as f64,
+                16.0,
+                &mask_scene,
+                &mask_camera,
+                &options()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sphere_scaled_along_one_axis_renders_as_an_ellipse() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .scale_axes(Scale::new(2.0, 1.0, 1.0))
+                    .unwrap()
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .into(),
+            )
+            .add_light(AmbientLight {
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            })
+            .finish();
+        let camera = Camera {
+            width: 64,
+            height: 64,
+            fov: 30.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            samples: 1,
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+        let covered = |x: u32, y: u32| image.get_pixel(x, y).data[0] > 0;
+        let width = (0..64).filter(|&x| covered(x, 32)).count();
+        let height = (0..64).filter(|&y| covered(32, y)).count();
+
+        assert!(height > 8, "{}", height);
+        assert!(
+            (width as f64 / height as f64 - 2.0).abs() < 0.2,
+            "{} × {}",
+            width,
+            height
+        );
+    }
+
+    #[test]
+    fn test_depth_aov_is_larger_for_farther_sphere() {
+        let near = center_depth(4.0);
+        let far = center_depth(12.0);
+
+        assert!(far > near, "expected {} > {}", far, near);
+    }
+
+    #[test]
+    fn test_aovs_show_what_the_lens_rays_hit() {
+        // the pinhole ray through the center hits the small sphere, the ray from the edge
+        // of the lens passes beside it to the wall
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(0.05))
+                    .at_position(Point::new(0.0, 0.0, -2.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                    .at_position(Point::new(0.0, 0.0, -20.0))
+                    .into(),
+            )
+            .add_light(AmbientLight {
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            })
+            .finish();
+        let lens = Lens {
+            aperture: 1.0,
+            focal_distance: 10.0,
+            chromatic_aberration: 0.0,
+        };
+        let camera = Camera {
+            width: 9,
+            height: 9,
+            fov: 60.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: Some(lens),
+        };
+        let pinhole = Ray::create_prime(4.0, 4.0, &scene, &camera);
+        assert_eq!(scene.trace(&pinhole).unwrap().object_id(), 0);
+        let options = RenderOptions {
+            aovs: true,
+            samples: 1,
+            lens_samples: 1,
+            ..RenderOptions::default()
+        };
+
+        let aovs = render_with_options(scene, camera, options).aovs.unwrap();
+
+        assert_eq!(aovs.object_id.get_pixel(4, 4), Rgba([0, 0, 2, 255]));
+    }
+
+    #[test]
+    fn test_metallic_reflection_is_tinted_by_base_color() {
+        let gold = Color::from_rgb(1.0, 0.8, 0.3);
+        let metal = cast_ray(
+            &mirror_scene(Material::metallic_color(gold, 0.0, 1.0)),
+            &floor_ray(),
+            0,
+            &RenderOptions::default(),
+        );
+        let mirror = cast_ray(
+            &mirror_scene(Material::reflective_color(gold, 0.0, 1.0)),
+            &floor_ray(),
+            0,
+            &RenderOptions::default(),
+        );
+
+        assert!(mirror.red > 0.0);
+        assert_approx_eq!(mirror.red, mirror.blue);
+        assert_approx_eq!(metal.red, mirror.red);
+        assert_approx_eq!(metal.blue, mirror.blue * 0.3);
+    }
+
+    #[test]
+    fn test_reflection_tint_colors_a_mirror() {
+        let white = Color::from_rgb(1.0, 1.0, 1.0);
+        let red = Color::from_rgb(1.0, 0.2, 0.2);
+        let tinted = cast_ray(
+            &mirror_scene(Material {
+                reflection_tint: Some(red),
+                ..Material::reflective_color(white, 0.0, 1.0)
+            }),
+            &floor_ray(),
+            0,
+            &RenderOptions::default(),
+        );
+        let mirror = cast_ray(
+            &mirror_scene(Material::reflective_color(white, 0.0, 1.0)),
+            &floor_ray(),
+            0,
+            &RenderOptions::default(),
+        );
+
+        assert!(mirror.red > 0.0);
+        assert_approx_eq!(tinted.red, mirror.red);
+        assert_approx_eq!(tinted.green, mirror.green * 0.2);
+        assert_approx_eq!(tinted.blue, mirror.blue * 0.2);
+    }
+
+    #[test]
+    fn test_colored_glass_casts_a_tinted_shadow() {
+        let glass = |absorption| {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                        .at_position(Point::new(0.0, -1.0, 0.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 2.0, -5.0))
+                        .with_material(Material::refractive_color(
+                            Color::from_rgb(1.0, 1.0, 1.0),
+                            0.0,
+                            1.5,
+                            0.9,
+                            absorption,
+                        ))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, -1.0, 0.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        let floor = |scene: &Scene, x: f64| {
+            let ray = Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(x, -1.0, -5.0).normalize(),
+                RayType::Prime,
+            );
+            cast_ray(scene, &ray, 0, &RenderOptions::default())
+        };
+        let scene = glass(Color::from_rgb(0.0, 2.0, 2.0));
+
+        let lit = floor(&scene, 3.0);
+        let shadow = floor(&scene, 0.0);
+
+        assert!(lit.red > 0.0);
+        // both surfaces let 0.9 through, green and blue are absorbed across the diameter
+        assert_approx_eq!(shadow.red, lit.red * 0.81, 1e-4);
+        assert_approx_eq!(shadow.green, lit.green * 0.81 * (-4.0f32).exp(), 1e-4);
+        assert!(shadow.red > shadow.green * 10.0);
+        let clear = floor(&glass(Color::from_rgb(0.0, 0.0, 0.0)), 0.0);
+        assert_approx_eq!(clear.green, lit.green * 0.81, 1e-4);
+    }
+
+    #[test]
+    fn test_glass_mesh_tints_the_shadows_of_lights_and_emitters() {
+        let floor = || {
+            ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                .at_position(Point::new(0.0, -1.0, 0.0))
+                .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                .into()
+        };
+        // a glass cube with sides of 2 between the floor and the lights
+        let glass_cube = || {
+            let set = obj::parse(String::from(include_str!("../cube.obj"))).unwrap();
+            ObjectBuilder::create_for(Mesh::create(set.objects.into_iter().next().unwrap()))
+                .scale(2.0)
+                .unwrap()
+                .at_position(Point::new(0.0, 2.0, -5.0))
+                .with_material(Material::refractive_color(
+                    Color::from_rgb(1.0, 1.0, 1.0),
+                    0.0,
+                    1.5,
+                    0.9,
+                    Color::from_rgb(0.0, 2.0, 2.0),
+                ))
+                .into()
+        };
+        let sun = |glass: bool| {
+            let builder = SceneBuilder::new()
+                .add_object(floor())
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, -1.0, 0.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                });
+            if glass {
+                builder.add_object(glass_cube()).finish()
+            } else {
+                builder.finish()
+            }
+        };
+        let lamp = |glass: bool| {
+            let builder = SceneBuilder::new().add_object(floor()).add_object(
+                ObjectBuilder::create_for(Quad::create(0.2, 0.2))
+                    .at_position(Point::new(0.0, 4.0, -5.0))
+                    .with_material(Material::emissive(Color::from_rgb(1.0, 1.0, 1.0), 5.0))
+                    .into(),
+            );
+            if glass {
+                builder.add_object(glass_cube()).finish()
+            } else {
+                builder.finish()
+            }
+        };
+        // straight below the cube, the shadow rays run through the diagonals the top and
+        // bottom faces are split at
+        let below = |scene: &Scene| {
+            let ray = Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(0.0, -1.0, -5.0).normalize(),
+                RayType::Prime,
+            );
+            cast_ray(scene, &ray, 0, &RenderOptions::default())
+        };
+
+        for &(lit, shadow) in &[
+            (below(&sun(false)), below(&sun(true))),
+            (below(&lamp(false)), below(&lamp(true))),
+        ] {
+            assert!(lit.red > 0.0);
+            // both faces let 0.9 through, green and blue are absorbed across the cube
+            assert_approx_eq!(shadow.red / lit.red, 0.81, 1e-3);
+            assert_approx_eq!(shadow.green / lit.green, 0.81 * (-4.0f32).exp(), 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_exhausted_ray_budget_stops_reflections() {
+        let scene = mirror_scene(Material::reflective_color(
+            Color::from_rgb(1.0, 1.0, 1.0),
+            0.0,
+            1.0,
+        ));
+        let budget = RayBudget::new(1);
+        let options = RenderOptions {
+            ray_budget: Some(budget.clone()),
+            ..RenderOptions::default()
+        };
+
+        let reflected = cast_ray(&scene, &floor_ray(), 0, &options);
+        let exhausted = cast_ray(&scene, &floor_ray(), 0, &options);
+
+        assert!(reflected.red > 0.0);
+        assert_eq!(budget.remaining(), 0);
+        assert_approx_eq!(exhausted.red, 0.0);
+    }
+
+    #[test]
+    fn test_glossy_reflection_spends_a_bounce_per_ray() {
+        let mut material = Material::reflective_color(Color::from_rgb(1.0, 1.0, 1.0), 0.0, 1.0);
+        material.roughness = Some(ScalarMap::Value(0.2));
+        let scene = mirror_scene(material);
+        let budget = RayBudget::new(GLOSSY_SAMPLES + 1);
+        let options = RenderOptions {
+            ray_budget: Some(budget.clone()),
+            ..RenderOptions::default()
+        };
+
+        let glossy = cast_ray(&scene, &floor_ray(), 0, &options);
+        let exhausted = cast_ray(&scene, &floor_ray(), 0, &options);
+
+        assert!(glossy.red > 0.0);
+        assert_eq!(budget.remaining(), 1);
+        assert_approx_eq!(exhausted.red, 0.0);
+    }
+
+    fn transmitted_through_sphere(radius: f64) -> Color {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(radius))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .with_material(Material::refractive_color(
+                        Color::from_rgb(1.0, 1.0, 1.0),
+                        0.0,
+                        1.0,
+                        1.0,
+                        Color::from_rgb(0.0, 0.0, 0.5),
+                    ))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(1.0, 0.0, -1.0).normalize(),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        cast_ray(&scene, &ray, 0, &RenderOptions::default())
+    }
+
+    #[test]
+    fn test_thicker_medium_absorbs_more() {
+        let thin = transmitted_through_sphere(0.5);
+        let thick = transmitted_through_sphere(2.0);
+
+        assert!(thin.red > 0.0);
+        assert_approx_eq!(thin.red, thick.red);
+        assert_approx_eq!(thin.blue, thin.red * (-0.5f32).exp());
+        assert_approx_eq!(thick.blue, thick.red * (-2.0f32).exp());
+    }
+
+    #[test]
+    fn test_trace_pixel_records_mirror_bounce() {
+        let scene = mirror_scene(Material::reflective_color(
+            Color::from_rgb(1.0, 1.0, 1.0),
+            0.0,
+            1.0,
+        ));
+        let camera = Camera {
+            width: 9,
+            height: 9,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+
+        let debug = trace_pixel(4, 8, &scene, &camera, &RenderOptions::default());
+
+        assert_eq!(debug.hits.len(), 2);
+        assert_eq!(debug.hits[0].object_id, 0);
+        assert_eq!(debug.hits[0].ray_type, RayType::Prime);
+        assert_eq!(debug.hits[1].object_id, 1);
+        assert_eq!(debug.hits[1].depth, 1);
+        assert_eq!(debug.hits[1].ray_type, RayType::Reflection);
+        assert_approx_eq!(debug.color.red, debug.hits[0].color.red);
+    }
+
+    /// looks at the floor right below a sphere that blocks a light shining straight down
+    fn color_below_occluder(casts_shadows: bool, softness: f64) -> Color {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 1.0, -5.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, -1.0, 0.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows,
+                softness,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(3.0, 0.0, -5.0),
+            Direction::new(-3.0, -1.0, 0.0).normalize(),
+            RayType::Prime,
+        );
+
+        cast_ray(&scene, &ray, 0, &RenderOptions::default())
+    }
+
+    #[test]
+    fn test_ambient_light_fills_shadows() {
+        let shadowed = |ambient: Option<AmbientLight>| {
+            let mut builder = SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                        .at_position(Point::new(0.0, -1.0, 0.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 0.5, 1.0), 1.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 1.0, -5.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, -1.0, 0.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                });
+            if let Some(ambient) = ambient {
+                builder = builder.add_light(ambient);
+            }
+            let ray = Ray::create(
+                Point::new(3.0, 0.0, -5.0),
+                Direction::new(-3.0, -1.0, 0.0).normalize(),
+                RayType::Prime,
+            );
+            cast_ray(&builder.finish(), &ray, 0, &RenderOptions::default())
+        };
+
+        let dark = shadowed(None);
+        let filled = shadowed(Some(AmbientLight {
+            color: Color::from_rgb(1.0, 1.0, 1.0),
+            intensity: 0.2,
+        }));
+
+        assert_approx_eq!(dark.red, 0.0);
+        assert_approx_eq!(filled.red, 0.2);
+        assert_approx_eq!(filled.green, 0.1);
+    }
+
+    #[test]
+    fn test_shadow_catcher_is_transparent_except_in_shadow() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::shadow_catcher())
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 1.0, -5.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, -1.0, 0.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let towards = |target: Point| {
+            let origin = Point::new(3.0, 0.0, -5.0);
+            Ray::create(origin, (target - origin).normalize(), RayType::Prime)
+        };
+
+        let lit = cast_ray(
+            &scene,
+            &towards(Point::new(6.0, -1.0, -5.0)),
+            0,
+            &RenderOptions::default(),
+        );
+        let shadowed = cast_ray(
+            &scene,
+            &towards(Point::new(0.0, -1.0, -5.0)),
+            0,
+            &RenderOptions::default(),
+        );
+
+        assert_eq!(lit.to_rgba8(), Rgba([0, 0, 0, 0]));
+        assert_approx_eq!(shadowed.alpha, 1.0);
+        assert_approx_eq!(shadowed.red, 0.0);
+    }
+
+    #[test]
+    fn test_roughness_map_blurs_part_of_a_mirror() {
+        // smooth where z < 0, rough where z > 0
+        let mut map = DynamicImage::new_luma8(1, 2);
+        map.put_pixel(0, 1, Rgba([255, 255, 255, 255]));
+        let floor = Material {
+            roughness: Some(ScalarMap::Map(Coloration::Texture(
+                Arc::new(map),
+                TextureFilter::Nearest,
+            ))),
+            ..Material::reflective_color(Color::from_rgb(1.0, 1.0, 1.0), 0.0, 1.0)
+        };
+        // a glowing ceiling over x < 0, the mirror is looked at just beside its edge
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Quad::create(20.0, 20.0))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(floor)
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Quad::create(50.0, 50.0))
+                    .at_position(Point::new(-25.0, 5.0, 0.0))
+                    .with_material(Material::emissive(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .finish();
+        let reflected = |z: f64| {
+            let ray = Ray::create(
+                Point::new(0.1, 0.0, z),
+                Direction::new(0.0, -1.0, 0.0),
+                RayType::Prime,
+            );
+            cast_ray(&scene, &ray, 0, &RenderOptions::default())
+        };
+
+        let sharp = reflected(-5.0);
+        let blurred = reflected(5.0);
+
+        assert_approx_eq!(sharp.red, 0.0);
+        assert!(blurred.red > 0.1 && blurred.red < 0.9);
+    }
+
+    /// a light defined outside the crate's own, falling off with the squared distance
+    struct PointLight {
+        position: Point,
+        intensity: f32,
+    }
+
+    impl Light for PointLight {
+        fn sample(&self, point: &Point) -> LightSample {
+            let to_light = self.position - point;
+            let distance = to_light.magnitude();
+            let radiance = self.intensity / (distance * distance) as f32;
+            LightSample::Directed {
+                direction: to_light / distance,
+                distance,
+                radiance: Color::from_rgb(radiance, radiance, radiance),
+            }
+        }
+
+        fn casts_shadows(&self) -> bool {
+            true
+        }
+    }
+
+    /// the floor at `x` next to a point light hanging below a sphere
+    fn lit_by_point_light(x: f64) -> Color {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 1.5, -5.0))
+                    .into(),
+            )
+            .add_light(PointLight {
+                position: Point::new(0.0, -0.5, -5.0),
+                intensity: 1.0,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(x, 0.0, -4.0),
+            Direction::new(0.0, -1.0, -1.0).normalize(),
+            RayType::Prime,
+        );
+
+        cast_ray(&scene, &ray, 0, &RenderOptions::default())
+    }
+
+    #[test]
+    fn test_custom_light_shines_on_the_scene() {
+        let below = lit_by_point_light(0.0);
+        let far = lit_by_point_light(2.0);
+
+        // the sphere lies behind the light and casts no shadow
+        assert!(below.red > 0.5, "{:?}", below);
+        assert!(far.red > 0.0);
+        assert!(below.red > far.red * 10.0);
+    }
+
+    /// a sphere lit from above by a soft light, seen with its soft shadow on the floor
+    fn soft_shadow_render(light_samples: usize) -> DynamicImage {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.5, -5.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, -1.0, 0.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.3,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let camera = Camera {
+            width: 64,
+            height: 48,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            light_samples,
+            ..RenderOptions::default()
+        };
+
+        render_with_options(scene, camera, options).image
+    }
+
+    #[test]
+    fn test_more_light_samples_smooth_the_penumbra_only() {
+        let reference = soft_shadow_render(256);
+        let error = |image: &DynamicImage| {
+            image
+                .raw_pixels()
+                .iter()
+                .zip(reference.raw_pixels())
+                .map(|(a, b)| (*a as i32 - b as i32).abs())
+                .sum::<i32>()
+        };
+        let few = soft_shadow_render(4);
+        let many = soft_shadow_render(32);
+
+        assert!(
+            error(&many) * 2 < error(&few),
+            "{} {}",
+            error(&many),
+            error(&few)
+        );
+        // the anti-aliased silhouette of the lit top of the sphere doesn't depend on it, up to
+        // the rounding of the darkest pixels that srgb encoding magnifies
+        for y in 0..20 {
+            for x in 0..64 {
+                let (a, b) = (few.get_pixel(x, y), many.get_pixel(x, y));
+                for c in 0..4 {
+                    assert!(
+                        (a.data[c] as i32 - b.data[c] as i32).abs() <= 1,
+                        "{:?} {:?}",
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+        assert_ne!(few.get_pixel(32, 17), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_shadowing_more_lights_converges_to_all_of_them() {
+        // eight lights of rising intensity around the sphere above the floor point seen
+        let mut builder = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 1.0, -5.0))
+                    .into(),
+            );
+        for k in 0..8 {
+            let angle = k as f64 * ::std::f64::consts::PI / 4.0;
+            builder = builder.add_light(DirectionalLight {
+                direction: Direction::new(0.4 * angle.cos(), -1.0, 0.4 * angle.sin()),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 0.1 * (k + 1) as f32,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            });
+        }
+        let scene = builder.finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, -2.0),
+            Direction::new(0.0, -1.0, -3.0).normalize(),
+            RayType::Prime,
+        );
+        let shade = |shadowed_lights| {
+            let options = RenderOptions {
+                shadowed_lights,
+                ..RenderOptions::default()
+            };
+            cast_ray(&scene, &ray, 0, &options).red
+        };
+
+        let all = shade(None);
+        let errors: Vec<f32> = [1, 2, 4, 8]
+            .iter()
+            .map(|&k| (shade(Some(k)) - all).abs())
+            .collect();
+
+        assert!(errors[0] > 0.1, "{:?}", errors);
+        for pair in errors.windows(2) {
+            assert!(pair[1] < pair[0], "{:?}", errors);
+        }
+        assert_eq!(errors[3], 0.0);
+    }
+
+    #[test]
+    fn test_only_shadow_casters_are_ranked_for_shadow_rays() {
+        let light = |direction: Direction, intensity: f32, casts_shadows: bool| DirectionalLight {
+            direction,
+            color: Color::from_rgb(1.0, 1.0, 1.0),
+            intensity,
+            casts_shadows,
+            softness: 0.0,
+            layers: ALL_LAYERS,
+        };
+        // the floor point seen lies right below the sphere. the strongest light casts no
+        // shadows, the sphere blocks the stronger of the two casters and misses the other
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 1.0, -5.0))
+                    .into(),
+            )
+            .add_light(light(Direction::new(0.0, -1.0, 0.0), 4.0, false))
+            .add_light(light(Direction::new(0.0, -1.0, 0.0), 1.0, true))
+            .add_light(light(Direction::new(1.0, -1.0, 0.0), 0.5, true))
+            .add_light(AmbientLight {
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 8.0,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, -2.0),
+            Direction::new(0.0, -1.0, -3.0).normalize(),
+            RayType::Prime,
+        );
+        let shade = |shadowed_lights| {
+            let options = RenderOptions {
+                shadowed_lights,
+                ..RenderOptions::default()
+            };
+            cast_ray(&scene, &ray, 0, &options).red
+        };
+
+        assert_eq!(shade(Some(1)), shade(None));
+        assert!(shade(Some(0)) > shade(None));
+    }
+
+    #[test]
+    fn test_light_without_shadows_lights_through_occluder() {
+        let shadowed = color_below_occluder(true, 0.0);
+        let unshadowed = color_below_occluder(false, 0.0);
+
+        assert_approx_eq!(shadowed.red, 0.0);
+        assert!(unshadowed.red > 0.0);
+    }
+
+    #[test]
+    fn test_soft_light_is_partially_visible_at_shadow_edge() {
+        let hard = color_below_occluder(true, 0.0);
+        let soft = color_below_occluder(true, 1.2);
+        let unshadowed = color_below_occluder(false, 0.0);
+
+        assert_approx_eq!(hard.red, 0.0);
+        assert!(soft.red > 0.0 && soft.red < unshadowed.red);
+    }
+
+    #[test]
+    fn test_exposure_scales_linear_color() {
+        // a white wall lit head-on, shading to a linear value of 0.25
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 0.25 * ::std::f32::consts::PI,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let camera = Camera {
+            width: 1,
+            height: 1,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let exposed = |exposure| {
+            let options = RenderOptions {
+                exposure,
+                ..RenderOptions::default()
+            };
+            sample(0.0, 0.0, &scene, &camera, &options)
+                .unwrap()
+                .to_rgba8()
+                .data[0]
+        };
+
+        assert_eq!(exposed(1.0), 64);
+        assert_eq!(exposed(2.0), 128);
+        assert_eq!(exposed(0.5), 32);
+    }
+
+    #[test]
+    fn test_max_depth_limits_secondary_rays() {
+        let scene = mirror_scene(Material::reflective_color(
+            Color::from_rgb(1.0, 1.0, 1.0),
+            0.0,
+            1.0,
+        ));
+        let with_depth = |max_depth| {
+            let options = RenderOptions {
+                max_depth,
+                ..RenderOptions::default()
+            };
+            cast_ray(&scene, &floor_ray(), 0, &options)
+        };
+
+        assert_approx_eq!(with_depth(1).red, 0.0);
+        assert!(with_depth(2).red > 0.0);
+    }
+
+    #[test]
+    fn test_preview_takes_one_sample_without_secondary_rays() {
+        let scene = mirror_scene(Material::reflective_color(
+            Color::from_rgb(1.0, 1.0, 1.0),
+            0.0,
+            1.0,
+        ));
+        let camera = Camera {
+            width: 9,
+            height: 9,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let preview = RenderOptions::preview();
+
+        let debug = trace_pixel(4, 8, &scene, &camera, &preview);
+
+        assert_eq!(sample_offsets(0, 0, &preview).len(), 1);
+        assert_eq!(sample_offsets(0, 0, &RenderOptions::default()).len(), 5);
+        assert_eq!(debug.hits.len(), 1);
+        assert_eq!(debug.hits[0].ray_type, RayType::Prime);
+    }
+
+    #[test]
+    fn test_packets_render_the_same_pixels_as_single_rays() {
+        let teapot = || {
+            let object = obj::parse(String::from(include_str!("../teapot.obj")))
+                .unwrap()
+                .objects
+                .into_iter()
+                .find(|o| !o.vertices.is_empty())
+                .unwrap();
+            let mesh = Arc::new(Mesh::create(object));
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Instance::of(&mesh))
+                        .rotation(Quaternion::from_angle_y(Deg(30.0)))
+                        .at_position(Point::new(0.0, -2.0, -6.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Instance::of(&mesh))
+                        .scale(0.5)
+                        .unwrap()
+                        .at_position(Point::new(-2.5, 1.0, -5.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(2.5, 1.0, -5.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(-1.0, -1.0, -1.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 2.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        // odd sizes leave partial blocks at the tile border
+        let camera = || Camera {
+            width: 41,
+            height: 31,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = |packets| RenderOptions {
+            samples: 4,
+            sampling: Sampling::Stratified,
+            packets,
+            ..RenderOptions::default()
+        };
+
+        let scene = teapot();
+        let rays: Vec<Ray> = (0..camera().width)
+            .map(|x| Ray::create_prime(x as f64, 15.0, &scene, &camera()))
+            .collect();
+        for (ray, hit) in rays.iter().zip(scene.trace_packet(&rays, &Culling::none())) {
+            assert_eq!(hit, scene.trace(ray));
+        }
+
+        let single = render_with_options(teapot(), camera(), options(false)).image;
+        let packets = render_with_options(teapot(), camera(), options(true)).image;
+        assert_eq!(single.raw_pixels(), packets.raw_pixels());
+    }
+
+    #[test]
+    fn test_uv_view_varies_smoothly_around_the_sphere() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -3.0))
+                    .into(),
+            )
+            .finish();
+        let camera = Camera {
+            width: 60,
+            height: 60,
+            fov: 60.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            debug_view: Some(DebugView::Uv),
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+        let equator: Vec<i32> = (0..60)
+            .map(|x| image.get_pixel(x, 30))
+            .filter(|pixel| pixel.data[1] > 0)
+            .map(|pixel| pixel.data[0] as i32)
+            .collect();
+
+        assert!(equator.len() > 20);
+        assert!((equator[0] - equator[equator.len() - 1]).abs() > 60);
+        for pair in equator.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 20, "{:?}", equator);
+        }
+    }
+
+    #[test]
+    fn test_bvh_heatmap_is_hotter_over_the_teapot() {
+        let teapot = obj::parse(String::from(include_str!("../teapot.obj"))).unwrap();
+        let object = teapot
+            .objects
+            .into_iter()
+            .find(|o| !o.vertices.is_empty())
+            .unwrap();
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Mesh::create(object))
+                    .rotation(Quaternion::from_angle_y(Deg(30.0)))
+                    .at_position(Point::new(0.0, -2.0, -6.0))
+                    .into(),
+            )
+            .finish();
+        let camera = Camera {
+            width: 20,
+            height: 20,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            debug_view: Some(DebugView::BvhHeatmap),
+            heatmap_range: 200,
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+        let teapot_body = image.get_pixel(10, 13).data;
+        let empty_sky = image.get_pixel(1, 1).data;
+
+        // a missed ray only tests the root bounding box
+        assert!(empty_sky[0] < 5);
+        assert!(teapot_body[0] > empty_sky[0]);
+    }
+
+    #[test]
+    fn test_crop_matches_region_of_full_render() {
+        let scene = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.5, 0.0, -3.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(-1.0, -1.0, -1.0).normalize(),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        let camera = || Camera {
+            width: 32,
+            height: 32,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            crop: Some(Crop {
+                x: 12,
+                y: 8,
+                width: 10,
+                height: 10,
+            }),
+            ..RenderOptions::default()
+        };
+
+        let full = render_with_options(scene(), camera(), RenderOptions::default()).image;
+        let cropped = render_with_options(scene(), camera(), options).image;
+
+        assert_eq!(cropped.dimensions(), (10, 10));
+        for x in 0..10 {
+            for y in 0..10 {
+                assert_eq!(cropped.get_pixel(x, y), full.get_pixel(12 + x, 8 + y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_surface_facing_away_from_light_is_unlit() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -3.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, 1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: false,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let color = cast_ray(&scene, &ray, 0, &RenderOptions::default());
+
+        assert_eq!(color.red, 0.0);
+        assert_eq!(color.green, 0.0);
+        assert_eq!(color.blue, 0.0);
+    }
+
+    #[test]
+    fn test_far_away_sphere_does_not_shadow_itself() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1000.0))
+                    .at_position(Point::new(0.0, 0.0, -1e6))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+
+        for i in 0..100 {
+            let x = (i % 10) as f64 * 1e-4 - 5e-4;
+            let y = (i / 10) as f64 * 1e-4 - 5e-4;
+            let ray = Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(x, y, -1.0).normalize(),
+                RayType::Prime,
+            );
+            let color = cast_ray(&scene, &ray, 0, &RenderOptions::default());
+
+            assert!(color.red > 0.0, "ray {} is shadowed", i);
+        }
+    }
+
+    #[test]
+    fn test_timing_report_has_one_entry_per_tile() {
+        let camera = Camera {
+            width: 300,
+            height: 140,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let scene = mirror_scene(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 0.5));
+        let options = RenderOptions {
+            samples: 1,
+            ..RenderOptions::default()
+        };
+
+        let timing = render_with_options(scene, camera, options).timing;
+        let covered: u32 = timing
+            .tiles
+            .iter()
+            .map(|tile| tile.width * tile.height)
+            .sum();
+        let slowest = timing.slowest(2);
+
+        // 3 columns and 2 rows of 128 pixel tiles
+        assert_eq!(timing.tiles.len(), 6);
+        assert_eq!(covered, 300 * 140);
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest[0].duration >= slowest[1].duration);
+        assert!(timing.average() <= slowest[0].duration);
+        assert!(timing.total >= slowest[0].duration);
+    }
+
+    #[test]
+    fn test_views_of_one_scene_match_single_renders() {
+        let scene = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 0.0, -5.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(-1.0, -1.0, -1.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 2.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        // a stereo pair, the right eye looks a little to the left
+        let eye = |x: f64, angle: f64| Camera {
+            width: 40,
+            height: 30,
+            fov: 60.0,
+            position: Point::new(x, 0.0, 0.0),
+            rotation: Quaternion::from_angle_y(Deg(angle)),
+            lens: None,
+        };
+
+        let views = render_views(scene(), vec![eye(-0.5, -5.0), eye(0.5, 5.0)]);
+
+        assert_eq!(views.len(), 2);
+        assert_ne!(views[0].raw_pixels(), views[1].raw_pixels());
+        assert_eq!(
+            views[0].raw_pixels(),
+            render(scene(), eye(-0.5, -5.0)).raw_pixels()
+        );
+        assert_eq!(
+            views[1].raw_pixels(),
+            render(scene(), eye(0.5, 5.0)).raw_pixels()
+        );
+    }
+
+    #[test]
+    fn test_every_view_gets_a_ray_budget_of_its_own() {
+        let scene = || {
+            mirror_scene(Material::reflective_color(
+                Color::from_rgb(1.0, 1.0, 1.0),
+                0.0,
+                1.0,
+            ))
+        };
+        let camera = || Camera {
+            width: 16,
+            height: 16,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_x(Deg(-45.0)),
+            lens: None,
+        };
+        // one thread, so the budget runs out at the same pixel every time
+        let options = || RenderOptions {
+            ray_budget: Some(RayBudget::new(100)),
+            threads: Some(1),
+            ..RenderOptions::default()
+        };
+
+        let single = render_with_options(scene(), camera(), options()).image;
+        let views = render_views_with_options(scene(), vec![camera(), camera()], options());
+
+        assert_ne!(single.raw_pixels(), render(scene(), camera()).raw_pixels());
+        for view in &views {
+            assert_eq!(view.image.raw_pixels(), single.raw_pixels());
+        }
+    }
+
+    #[test]
+    fn test_views_facing_away_from_each_other_see_their_own_objects() {
+        let sphere = |z: f64| {
+            ObjectBuilder::create_for(Sphere::create(1.0))
+                .at_position(Point::new(0.0, 0.0, z))
+                .into()
+        };
+        let scene = SceneBuilder::new()
+            .add_object(sphere(-5.0))
+            .add_object(sphere(5.0))
+            .add_light(AmbientLight {
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            })
+            .finish();
+        let facing = |angle: f64| Camera {
+            width: 20,
+            height: 20,
+            fov: 60.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_y(Deg(angle)),
+            lens: None,
+        };
+
+        // each camera culls the sphere the other one looks at
+        let views = render_views(scene, vec![facing(0.0), facing(180.0)]);
+
+        for view in &views {
+            assert_eq!(view.get_pixel(10, 10)[3], 255);
+        }
+    }
+
+    #[test]
+    fn test_single_thread_renders_the_same_image() {
+        let render_with_threads = |threads| {
+            // more than one tile, so several workers have something to do
+            let camera = Camera {
+                width: 160,
+                height: 140,
+                fov: 90.0,
+                position: Point::new(0.0, 0.0, 0.0),
+                rotation: Quaternion::one(),
+                lens: None,
+            };
+            let options = RenderOptions {
+                threads: Some(threads),
+                ..RenderOptions::default()
+            };
+            render_with_options(
+                mirror_scene(Material::reflective_color(
+                    Color::from_rgb(0.2, 0.3, 0.4),
+                    0.5,
+                    0.3,
+                )),
+                camera,
+                options,
+            )
+            .image
+        };
+
+        let single = render_with_threads(1);
+        let multi = render_with_threads(4);
+
+        assert_eq!(single.raw_pixels(), multi.raw_pixels());
+    }
+
+    #[test]
+    fn test_progressive_passes_end_in_the_full_render() {
+        let camera = || Camera {
+            width: 160,
+            height: 140,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        // the edge of the sphere differs between the sample positions
+        let scene = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(2.0))
+                        .at_position(Point::new(0.0, 0.0, -5.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, 0.0, -1.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        let options = || RenderOptions {
+            samples: 4,
+            sampling: Sampling::Stratified,
+            threads: Some(2),
+            ..RenderOptions::default()
+        };
+
+        let mut passes = Vec::new();
+        let progressive = render_progressive(scene(), camera(), options(), |image, pass| {
+            passes.push((pass, image.raw_pixels()))
+        });
+        let direct = render_with_options(scene(), camera(), options()).image;
+
+        assert_eq!(
+            passes.iter().map(|pass| pass.0).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_ne!(passes[0].1, direct.raw_pixels());
+        assert_eq!(passes[3].1, direct.raw_pixels());
+        assert_eq!(progressive.raw_pixels(), direct.raw_pixels());
+    }
+
+    #[test]
+    fn test_progressive_passes_take_the_options_of_the_full_render() {
+        let camera = || Camera {
+            width: 80,
+            height: 60,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let scene = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(2.0))
+                        .at_position(Point::new(0.0, 0.0, -5.0))
+                        .into(),
+                )
+                .add_light(AmbientLight {
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                })
+                .finish()
+        };
+        let base = || RenderOptions {
+            samples: 4,
+            sampling: Sampling::Stratified,
+            // no sample is close enough to the center to get any weight
+            filter: PixelFilter::Tent { radius: 0.01 },
+            threads: Some(2),
+            ..RenderOptions::default()
+        };
+        let variants = vec![
+            RenderOptions {
+                packets: true,
+                resolution_scale: 2,
+                ..base()
+            },
+            RenderOptions {
+                edge_antialiasing: Some(EdgeDetection::default()),
+                ..base()
+            },
+        ];
+
+        for options in variants {
+            let direct = render_with_options(scene(), camera(), options.clone()).image;
+            let progressive = render_progressive(scene(), camera(), options, |_, _| ());
+
+            assert_eq!(progressive.dimensions(), (80, 60));
+            assert_eq!(progressive.raw_pixels(), direct.raw_pixels());
+            assert_eq!(progressive.get_pixel(40, 30)[3], 255);
+        }
+    }
+
+    #[test]
+    fn test_resolution_scale_smooths_edges() {
+        let render_at = |resolution_scale| {
+            let scene = SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 0.0, -3.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                        .into(),
+                )
+                .add_light(AmbientLight {
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                })
+                .finish();
+            let camera = Camera {
+                width: 30,
+                height: 20,
+                fov: 60.0,
+                position: Point::new(0.0, 0.0, 0.0),
+                rotation: Quaternion::one(),
+                lens: None,
+            };
+            let options = RenderOptions {
+                samples: 1,
+                resolution_scale,
+                ..RenderOptions::default()
+            };
+            render_with_options(scene, camera, options).image
+        };
+        // pixels neither fully covered by the sphere nor empty
+        let partial = |image: &DynamicImage| {
+            image
+                .pixels()
+                .filter(|&(_, _, pixel)| pixel.data[0] > 0 && pixel.data[0] < 255)
+                .count()
+        };
+
+        let plain = render_at(1);
+        let scaled = render_at(2);
+
+        assert_eq!(scaled.dimensions(), plain.dimensions());
+        assert_eq!(partial(&plain), 0);
+        assert!(partial(&scaled) > 10);
+    }
+
+    #[test]
+    fn test_vignette_darkens_the_corners() {
+        // a lit wall facing the camera is evenly bright
+        let wall = || {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                        .at_position(Point::new(0.0, 0.0, -10.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, 0.0, -1.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: false,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        let camera = || Camera {
+            width: 21,
+            height: 21,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            vignette: Some(Vignette {
+                strength: 0.8,
+                radius: 0.3,
+            }),
+            ..RenderOptions::default()
+        };
+
+        let plain = render_with_options(wall(), camera(), RenderOptions::default()).image;
+        let image = render_with_options(wall(), camera(), options).image;
+
+        assert_eq!(plain.get_pixel(0, 0), plain.get_pixel(10, 10));
+        assert_eq!(image.get_pixel(10, 10), plain.get_pixel(10, 10));
+        for &(x, y) in &[(0, 0), (20, 0), (0, 20), (20, 20)] {
+            assert!(image.get_pixel(x, y).data[0] < image.get_pixel(10, 10).data[0]);
+        }
+    }
+
+    #[test]
+    fn test_object_hidden_from_camera_still_casts_shadow() {
+        let scene = |visibility| {
+            SceneBuilder::new()
+                .add_object(
+                    ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                        .at_position(Point::new(0.0, -1.0, 0.0))
+                        .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                        .into(),
+                )
+                .add_object(
+                    ObjectBuilder::create_for(Sphere::create(1.0))
+                        .at_position(Point::new(0.0, 1.0, -5.0))
+                        .with_visibility(visibility)
+                        .into(),
+                )
+                .add_light(DirectionalLight {
+                    direction: Direction::new(0.0, -1.0, 0.0),
+                    color: Color::from_rgb(1.0, 1.0, 1.0),
+                    intensity: 1.0,
+                    casts_shadows: true,
+                    softness: 0.0,
+                    layers: ALL_LAYERS,
+                })
+                .finish()
+        };
+        let hidden = scene(Visibility {
+            camera: false,
+            ..Visibility::default()
+        });
+        let no_shadow = scene(Visibility {
+            camera: false,
+            shadows: false,
+            ..Visibility::default()
+        });
+        let ray = Ray::create(
+            Point::new(0.0, 5.0, -5.0),
+            Direction::new(0.0, -1.0, 0.0),
+            RayType::Prime,
+        );
+
+        assert_eq!(
+            scene(Visibility::default())
+                .trace(&ray)
+                .unwrap()
+                .object_id(),
+            1
+        );
+        assert_eq!(hidden.trace(&ray).unwrap().object_id(), 0);
+        assert_approx_eq!(
+            cast_ray(&hidden, &ray, 0, &RenderOptions::default()).red,
+            0.0
+        );
+        assert!(cast_ray(&no_shadow, &ray, 0, &RenderOptions::default()).red > 0.0);
+    }
+
+    /// the variance of the red channel over an image of a checkerboard far too fine for the
+    /// pixels to resolve, ideally every pixel is the same gray
+    fn checkerboard_variance(samples: usize, sampling: Sampling) -> f64 {
+        let mut checker = DynamicImage::new_rgb8(2, 2);
+        checker.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+        checker.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, 0.0, -1.0)))
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .scale(0.013)
+                    .unwrap()
+                    .with_material(Material::diffuse_texture(checker, 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: ::std::f32::consts::PI,
+                casts_shadows: false,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let camera = Camera {
+            width: 32,
+            height: 32,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            samples,
+            sampling,
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+        let values: Vec<f64> = image.pixels().map(|(_, _, p)| p.data[0] as f64).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn test_stratified_sampling_reduces_checkerboard_aliasing() {
+        let fixed = checkerboard_variance(5, Sampling::Fixed);
+        let stratified = checkerboard_variance(16, Sampling::Stratified);
+
+        assert!(stratified < fixed, "expected {} < {}", stratified, fixed);
+    }
+
+    #[test]
+    fn test_float_accumulation_is_closer_to_the_mean_than_bytes() {
+        // dim samples that each round to 0 or 1 as bytes
+        let shades: Vec<f32> = (0..100).map(|i| (i % 7) as f32 * 0.3 / 255.0).collect();
+        let mean = shades.iter().sum::<f32>() / 100.0;
+        let samples: Vec<Sample> = shades
+            .iter()
+            .map(|&shade| (Color::from_rgb(shade, shade, shade), (0.0, 0.0)))
+            .collect();
+
+        // the old accumulation: rounded to bytes first, then averaged as integers
+        let byte_sum: usize = samples
+            .iter()
+            .map(|&(color, _)| color.to_rgba8().data[0] as usize)
+            .sum();
+        let bytes = (byte_sum / samples.len()) as f32;
+        let floats = weighted_color(&samples, PixelFilter::Box);
+        let byte_error = (bytes / 255.0 - mean).abs();
+        let float_error = (srgb_to_linear(floats.data[0] as f32 / 255.0) - mean).abs();
+
+        assert!(float_error < byte_error, "{} < {}", float_error, byte_error);
+    }
+
+    #[test]
+    fn test_gaussian_filter_weights_the_center_sample_most() {
+        let gaussian = PixelFilter::Gaussian { sigma: 0.25 };
+        let tent = PixelFilter::Tent { radius: 0.5 };
+        // a white sample in the center of the fixed pattern, black ones towards the corners
+        let samples: Vec<Sample> = sample_offsets(0, 0, &RenderOptions::default())
+            .into_iter()
+            .map(|offset| {
+                let shade = if offset == (0.0, 0.0) { 1.0 } else { 0.0 };
+                (Color::from_rgb(shade, shade, shade), offset)
+            })
+            .collect();
+
+        assert!(gaussian.weight(0.0, 0.0) > gaussian.weight(0.25, 0.25));
+        assert!(gaussian.weight(0.25, 0.25) > gaussian.weight(0.5, 0.5));
+        assert_eq!(tent.weight(0.5, 0.0), 0.0);
+        // a fifth of the light, srgb encoded
+        assert_eq!(weighted_color(&samples, PixelFilter::Box).data[0], 124);
+        assert!(weighted_color(&samples, gaussian).data[0] > 124);
+        assert!(weighted_color(&samples, tent).data[0] > 124);
+    }
+
+    #[test]
+    fn test_stratified_offsets_cover_every_cell() {
+        let options = RenderOptions {
+            samples: 9,
+            sampling: Sampling::Stratified,
+            ..RenderOptions::default()
+        };
+
+        let offsets = sample_offsets(3, 5, &options);
+
+        assert_eq!(offsets, sample_offsets(3, 5, &options));
+        assert_eq!(offsets.len(), 9);
+        for (i, &(dx, dy)) in offsets.iter().enumerate() {
+            let cell_x = ((dx + 0.5) * 3.0).floor() as usize;
+            let cell_y = ((dy + 0.5) * 3.0).floor() as usize;
+            assert_eq!((cell_x, cell_y), (i % 3, i / 3));
+        }
+    }
+
+    #[test]
+    fn test_object_unlinked_from_light_stays_dark() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(-1.5, 0.0, -5.0))
+                    .light_layers(0b01)
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(1.5, 0.0, -5.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: 0b10,
+            })
+            .finish();
+        let toward = |x: f64| {
+            Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(x, 0.0, -5.0).normalize(),
+                RayType::Prime,
+            )
+        };
+
+        let excluded = cast_ray(&scene, &toward(-1.5), 0, &RenderOptions::default());
+        let neighbor = cast_ray(&scene, &toward(1.5), 0, &RenderOptions::default());
+
+        assert_approx_eq!(excluded.red, 0.0);
+        assert!(neighbor.red > 0.0);
+    }
+
+    #[test]
+    fn test_final_gather_bleeds_wall_color_onto_floor() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(-1.0, 0.0, 0.0)))
+                    .at_position(Point::new(-1.0, 0.0, 0.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 0.0, 0.0), 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(-1.0, -1.0, 0.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+                casts_shadows: true,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(-0.7, -1.0, -5.0).normalize(),
+            RayType::Prime,
+        );
+        let gathered = RenderOptions {
+            final_gather: Some(FinalGather::default()),
+            ..RenderOptions::default()
+        };
+
+        let direct = cast_ray(&scene, &ray, 0, &RenderOptions::default());
+        let bled = cast_ray(&scene, &ray, 0, &gathered);
+
+        assert_approx_eq!(direct.red, direct.green);
+        assert!(bled.red > direct.red);
+        assert!(bled.red > bled.green + 0.01);
+        assert_approx_eq!(bled.green, direct.green);
+    }
+
+    #[test]
+    fn test_emissive_quad_lights_the_floor_below() {
+        let floor = || {
+            ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                .at_position(Point::new(0.0, -1.0, 0.0))
+                .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                .into()
+        };
+        let lamp = SceneBuilder::new()
+            .add_object(floor())
+            .add_object(
+                ObjectBuilder::create_for(Quad::create(1.0, 1.0))
+                    .at_position(Point::new(0.0, 1.0, -5.0))
+                    .with_material(Material::emissive(Color::from_rgb(1.0, 0.9, 0.8), 5.0))
+                    .into(),
+            )
+            .finish();
+        let dark = SceneBuilder::new().add_object(floor()).finish();
+        let toward = |target: Point| {
+            Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                (target - Point::new(0.0, 0.0, 0.0)).normalize(),
+                RayType::Prime,
+            )
+        };
+
+        let below = cast_ray(
+            &lamp,
+            &toward(Point::new(0.0, -1.0, -5.0)),
+            0,
+            &RenderOptions::default(),
+        );
+        let far = cast_ray(
+            &lamp,
+            &toward(Point::new(0.0, -1.0, -15.0)),
+            0,
+            &RenderOptions::default(),
+        );
+        let unlit = cast_ray(
+            &dark,
+            &toward(Point::new(0.0, -1.0, -5.0)),
+            0,
+            &RenderOptions::default(),
+        );
+        let glow = cast_ray(
+            &lamp,
+            &toward(Point::new(0.0, 1.0, -5.0)),
+            0,
+            &RenderOptions::default(),
+        );
+
+        let back = cast_ray(
+            &lamp,
+            &Ray::create(
+                Point::new(0.0, 3.0, -5.0),
+                Direction::new(0.0, -1.0, 0.0),
+                RayType::Prime,
+            ),
+            0,
+            &RenderOptions::default(),
+        );
+
+        assert!(lamp.lights.is_empty());
+        assert_approx_eq!(unlit.red, 0.0);
+        assert!(below.red > 0.1);
+        assert!(below.red > below.blue);
+        assert!(far.red < below.red);
+        // the lamp glows downward only
+        assert_approx_eq!(glow.red, 5.0);
+        assert_approx_eq!(back.red, 0.0);
+    }
+
+    #[test]
+    fn test_chromatic_aberration_fringes_out_of_focus_edges() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(2.0))
+                    .at_position(Point::new(0.0, 0.0, -12.0))
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 1.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(0.0, 0.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 3.0,
+                casts_shadows: false,
+                softness: 0.0,
+                layers: ALL_LAYERS,
+            })
+            .finish();
+        let camera = |chromatic_aberration: f64| Camera {
+            width: 48,
+            height: 48,
+            fov: 30.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: Some(Lens {
+                aperture: 0.3,
+                focal_distance: 4.0,
+                chromatic_aberration,
+            }),
+        };
+        let fringe = |camera: &Camera| {
+            (0..camera.width)
+                .map(|x| {
+                    let pixel = sample(x as f64, 23.5, &scene, camera, &RenderOptions::default())
+                        .unwrap()
+                        .to_rgba8();
+                    (pixel.data[0] as i32 - pixel.data[2] as i32).abs()
+                })
+                .max()
+                .unwrap()
+        };
+
+        assert_eq!(fringe(&camera(0.0)), 0);
+        assert!(fringe(&camera(0.2)) > 10);
+    }
+
+    #[test]
+    fn test_every_tile_order_visits_each_tile_once() {
+        for &(cols, rows) in &[(5, 3), (4, 4), (1, 7)] {
+            let scanline = tile_order(cols, rows, TileOrder::Scanline);
+            for &order in &[TileOrder::Spiral, TileOrder::Hilbert] {
+                let mut tiles = tile_order(cols, rows, order);
+                tiles.sort_by_key(|&(col, row)| (row, col));
+
+                assert_eq!(tiles, scanline, "{:?} {}x{}", order, cols, rows);
+            }
+        }
+
+        assert_eq!(tile_order(5, 3, TileOrder::Spiral)[0], (2, 1));
+        // consecutive tiles of a hilbert curve are neighbors
+        let hilbert = tile_order(4, 4, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let distance = (pair[0].0 as i32 - pair[1].0 as i32).abs()
+                + (pair[0].1 as i32 - pair[1].1 as i32).abs();
+            assert_eq!(distance, 1);
+        }
+        assert_eq!(hilbert_index(4, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_tiny_parallel_triangles_both_render() {
+        // a triangle in front of the left half of a larger one, both a hundred millionth of a
+        // unit across and a billionth apart
+        let triangle = |vertices: &str, z: f64| {
+            let source = format!(
+                "o plate\n{}f 1 2 3\n",
+                vertices.replace("Z", &z.to_string())
+            );
+            Mesh::create(obj::parse(source).unwrap().objects.remove(0))
+        };
+        let front = triangle("v -4e-8 -4e-8 Z\nv 0 -4e-8 Z\nv 0 4e-8 Z\n", -1e-8);
+        let back = triangle("v -8e-8 -8e-8 Z\nv 8e-8 -8e-8 Z\nv 0 8e-8 Z\n", -1.1e-8);
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(front)
+                    .with_material(Material::diffuse_color(Color::from_rgb(1.0, 0.0, 0.0), 1.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(back)
+                    .with_material(Material::diffuse_color(Color::from_rgb(0.0, 0.0, 1.0), 1.0))
+                    .into(),
+            )
+            .add_light(AmbientLight {
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            })
+            .finish();
+        let camera = Camera {
+            width: 16,
+            height: 16,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            samples: 1,
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+
+        assert_eq!(image.get_pixel(4, 10), Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(10, 10), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_huge_camera_is_refused_before_allocating() {
+        let camera = |width, height| Camera {
+            width,
+            height,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+
+        let result = try_render_with_options(
+            SceneBuilder::new().finish(),
+            camera(50_000, 50_000),
+            RenderOptions::default(),
+        );
+        let scaled = RenderOptions {
+            resolution_scale: 4,
+            max_pixels: 1000,
+            ..RenderOptions::default()
+        };
+
+        assert_eq!(
+            result.err(),
+            Some(RenderError::TooLarge {
+                width: 50_000,
+                height: 50_000,
+                max_pixels: DEFAULT_MAX_PIXELS,
+            })
+        );
+        assert!(check_dimensions(&camera(8, 8), &scaled).is_err());
+        assert!(check_dimensions(&camera(7, 7), &scaled).is_ok());
+        // no view is rendered if one of them is too large
+        assert!(try_render_views_with_options(
+            SceneBuilder::new().finish(),
+            vec![camera(7, 7), camera(8, 8)],
+            scaled.clone(),
+        )
+        .is_err());
+        let mut passes = 0;
+        let progressive = try_render_progressive(
+            SceneBuilder::new().finish(),
+            camera(8, 8),
+            scaled.clone(),
+            |_, _| passes += 1,
+        );
+        assert!(progressive.is_err());
+        assert_eq!(passes, 0);
+    }
+
+    #[test]
+    fn test_time_limit_returns_the_background_when_out_of_time() {
+        let object = obj::parse(String::from(include_str!("../teapot.obj")))
+            .unwrap()
+            .objects
+            .into_iter()
+            .find(|o| !o.vertices.is_empty())
+            .unwrap();
+        let mut sky = DynamicImage::new_rgb8(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                sky.put_pixel(x, y, Rgba([64, 128, 255, 255]));
+            }
+        }
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Mesh::create(object))
+                    .at_position(Point::new(0.0, -1.0, -5.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(-1.0, -1.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 2.0,
+                casts_shadows: true,
+                softness: 0.5,
+                layers: ALL_LAYERS,
+            })
+            .with_environment(EnvironmentMap::new(sky))
+            .finish();
+        let camera = Camera {
+            width: 512,
+            height: 256,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            samples: 256,
+            time_limit: Some(Duration::from_nanos(1)),
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(scene, camera, options).image;
+
+        assert_eq!(image.dimensions(), (512, 256));
+        for y in 0..256 {
+            for x in 0..512 {
+                assert_eq!(image.get_pixel(x, y), Rgba([64, 128, 255, 255]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sky_only_tile_is_filled_with_the_background() {
+        let object = obj::parse(String::from(include_str!("../teapot.obj")))
+            .unwrap()
+            .objects
+            .into_iter()
+            .find(|o| !o.vertices.is_empty())
+            .unwrap();
+        let mut sky = DynamicImage::new_rgb8(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                sky.put_pixel(x, y, Rgba([64, 128, 255, 255]));
+            }
+        }
+        // the teapot only shows up in the left one of two tiles
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Mesh::create(object))
+                    .at_position(Point::new(-4.0, -1.0, -8.0))
+                    .into(),
+            )
+            .add_light(DirectionalLight {
+                direction: Direction::new(-1.0, -1.0, -1.0),
+                color: Color::from_rgb(1.0, 1.0, 1.0),
+                intensity: 2.0,
+                casts_shadows: true,
+                softness: 0.5,
+                layers: ALL_LAYERS,
+            })
+            .with_environment(EnvironmentMap::new(sky))
+            .finish();
+        let camera = Camera {
+            width: 256,
+            height: 128,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let options = RenderOptions {
+            samples: 4,
+            ..RenderOptions::default()
+        };
+
+        let render = render_with_options(scene, camera, options);
+        let tile = |x| {
+            render
+                .timing
+                .tiles
+                .iter()
+                .find(|tile| tile.x == x)
+                .unwrap()
+                .skipped
+        };
+
+        assert!((0..128)
+            .flat_map(|x| (0..128).map(move |y| (x, y)))
+            .any(|(x, y)| render.image.get_pixel(x, y) != Rgba([64, 128, 255, 255])));
+        for y in 0..128 {
+            for x in 128..256 {
+                assert_eq!(render.image.get_pixel(x, y), Rgba([64, 128, 255, 255]));
+            }
+        }
+        assert!(tile(128));
+        assert!(!tile(0));
+    }
 }
@@ -1,16 +1,117 @@
-use cgmath::InnerSpace;
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Quaternion, Rotation};
+use environment::EnvironmentMap;
 use light::Light;
-use objects::Object;
-use raycast::{IntersectionResult, Ray};
-use types::Direction;
+use objects::{BoundingBox, Material, MaterialLibrary, Object};
+use raycast::{IntersectionResult, Ray, RayType};
+use std::sync::Arc;
+use types::{Color, Direction, Point, HIT_EPSILON};
 
 pub struct Camera {
     pub width: u32,
     pub height: u32,
+    /// the vertical field of view in degrees
     pub fov: f64,
+    pub position: Point,
+    /// the camera looks along -z when not rotated
+    pub rotation: Quaternion<f64>,
+    /// a pinhole camera with everything in focus if not set
+    pub lens: Option<Lens>,
+}
+
+/// a thin lens, blurring everything that is not at the focal distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+    /// radius of the aperture, larger ones blur more
+    pub aperture: f64,
+    /// distance from the camera to the plane in focus
+    pub focal_distance: f64,
+    /// how far apart the focal distances of red and blue are, relative to the focal distance.
+    /// red focuses behind and blue in front of green, fringing out-of-focus edges
+    pub chromatic_aberration: f64,
 }
 
 impl Camera {
+    /// the ray through the sensor position `x`, `y` leaving the lens at `lens_point`, a point on
+    /// the unit disc. it passes through the point the pinhole ray sees at `focal_distance`.
+    pub fn lens_ray(
+        &self,
+        x: f64,
+        y: f64,
+        lens_point: (f64, f64),
+        lens: &Lens,
+        focal_distance: f64,
+    ) -> Ray {
+        let direction = self.to_sensor_direction(x, y);
+        let focus = Point::new(0.0, 0.0, 0.0) + direction * (focal_distance / -direction.z);
+        let origin = Point::new(
+            lens_point.0 * lens.aperture,
+            lens_point.1 * lens.aperture,
+            0.0,
+        );
+
+        Ray::create(
+            self.position + self.rotation.rotate_vector(origin.to_vec()),
+            self.rotation.rotate_vector((focus - origin).normalize()),
+            RayType::Prime,
+        )
+    }
+
+    /// the direction the camera is looking in
+    pub fn forward(&self) -> Direction {
+        self.rotation.rotate_vector(Direction::new(0.0, 0.0, -1.0))
+    }
+
+    /// moves the camera back along its view direction until everything in the bounds is in
+    /// view, looking at their center
+    pub fn frame(self, bounds: &BoundingBox) -> Camera {
+        let center = bounds.center();
+        let radius = center.distance(bounds.max);
+        let vertical = (self.fov.to_radians() / 2.0).tan();
+        let horizontal = vertical * self.width as f64 / self.height as f64;
+        let half_angle = vertical.min(horizontal).atan();
+        let distance = radius / half_angle.sin();
+
+        Camera {
+            position: center - self.forward() * distance,
+            ..self
+        }
+    }
+
+    /// whether any part of the box could be seen by a prime ray. the box is outside the view
+    /// if all of its corners lie beyond one of the planes bounding it
+    pub fn sees(&self, bounds: &BoundingBox) -> bool {
+        self.sees_in(bounds, 0, 0, self.width, self.height)
+    }
+
+    /// `sees` for the prime rays of a rectangle of pixels only
+    pub fn sees_in(&self, bounds: &BoundingBox, x: u32, y: u32, width: u32, height: u32) -> bool {
+        let vertical = (self.fov.to_radians() / 2.0).tan();
+        let horizontal = vertical * self.width as f64 / self.height as f64;
+        // where the pixel edges lie on the sensor, one unit in front of the camera
+        let sensor_x = |x: u32| (x as f64 / self.width as f64 * 2.0 - 1.0) * horizontal;
+        let sensor_y = |y: u32| (1.0 - y as f64 / self.height as f64 * 2.0) * vertical;
+        let (left, right) = (sensor_x(x), sensor_x(x + width));
+        let (top, bottom) = (sensor_y(y), sensor_y(y + height));
+        // outward normals in camera space, the planes all pass through the camera
+        let planes = [
+            Direction::new(1.0, 0.0, right),
+            Direction::new(-1.0, 0.0, -left),
+            Direction::new(0.0, 1.0, top),
+            Direction::new(0.0, -1.0, -bottom),
+            Direction::new(0.0, 0.0, 1.0),
+        ];
+        let to_camera = self.rotation.invert();
+        let corners: Vec<Direction> = bounds
+            .corners()
+            .into_iter()
+            .map(|corner| to_camera.rotate_vector(corner - self.position))
+            .collect();
+
+        !planes
+            .iter()
+            .any(|plane| corners.iter().all(|corner| plane.dot(*corner) > 0.0))
+    }
+
     pub fn to_sensor_direction(&self, x: f64, y: f64) -> Direction {
         let fov_adjustment = (self.fov.to_radians() / 2.0).tan();
         let aspect_ratio = self.width as f64 / self.height as f64;
@@ -27,24 +128,198 @@ impl Camera {
     }
 }
 
+/// the objects a camera can see. prime rays of a render only test those, shadow, reflection
+/// and refraction rays still hit everything. computed for every render and handed along
+/// with it, the scene itself is left as it is
+#[derive(Debug, Clone, Default)]
+pub struct Culling {
+    /// by index into `Scene::objects`, every object is in view if not set
+    in_view: Option<Vec<bool>>,
+}
+
+impl Culling {
+    /// keeps every object
+    pub fn none() -> Culling {
+        Culling { in_view: None }
+    }
+
+    /// leaves out the objects outside the camera's view. a lens moves the origin of prime
+    /// rays off the camera position, so nothing is culled for one
+    pub fn for_camera(scene: &Scene, camera: &Camera) -> Culling {
+        if camera.lens.is_some() {
+            return Culling::none();
+        }
+        Culling {
+            in_view: Some(
+                scene
+                    .objects
+                    .iter()
+                    .map(|object| object.bounds().is_none_or(|b| camera.sees(&b)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// whether the object at `index` of the scene's objects may be seen by prime rays
+    pub fn in_view(&self, index: usize) -> bool {
+        self.in_view
+            .as_ref()
+            .is_none_or(|in_view| in_view.get(index).cloned().unwrap_or(true))
+    }
+}
+
 pub struct Scene {
     pub objects: Vec<Object>,
-    pub lights: Vec<Light>,
+    pub lights: Vec<Box<dyn Light + Send + Sync>>,
+    /// seen by rays that miss every object, black if not set
+    pub environment: Option<EnvironmentMap>,
+    /// hits closer to a ray's origin are ignored, see `HIT_EPSILON`
+    pub hit_epsilon: f64,
 }
 
 impl Scene {
+    /// the color of a ray that hits nothing
+    pub fn background(&self, ray: &Ray) -> Color {
+        match self.environment {
+            Some(ref environment) => environment.sample(&ray.direction),
+            None => Color::from_rgb(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// the distance a hit needs from the ray's origin, growing with the origin's distance from
+    /// the world origin because its precision shrinks
+    fn min_distance(&self, ray: &Ray) -> f64 {
+        self.hit_epsilon * ray.origin.to_vec().magnitude().max(1.0)
+    }
+
     pub fn trace(&self, ray: &Ray) -> Option<IntersectionResult> {
+        self.trace_culled(ray, &Culling::none())
+    }
+
+    /// `trace` that skips the objects out of view if the ray is a prime ray
+    pub fn trace_culled(&self, ray: &Ray, culling: &Culling) -> Option<IntersectionResult> {
+        let min_distance = self.min_distance(ray);
         self.objects
             .iter()
-            .filter_map(|object| object.intersect(ray))
-            .filter(|intersection| intersection.distance() > 1e-13)
+            .enumerate()
+            .filter(|&(index, object)| {
+                (ray.ray_type != RayType::Prime || culling.in_view(index))
+                    && object.is_visible_to(ray)
+                    && object.may_be_hit_by(ray)
+            })
+            .filter_map(|(_, object)| object.intersect(ray))
+            .filter(|intersection| intersection.distance() > min_distance)
             .min()
     }
+
+    /// `trace_culled` for a bundle of rays, each object is intersected with all of them at once
+    pub fn trace_packet(&self, rays: &[Ray], culling: &Culling) -> Vec<Option<IntersectionResult>> {
+        let mut nearest: Vec<Option<IntersectionResult>> = rays.iter().map(|_| None).collect();
+        let mut active: Vec<usize> = Vec::with_capacity(rays.len());
+        let mut packet: Vec<&Ray> = Vec::with_capacity(rays.len());
+        for (index, object) in self.objects.iter().enumerate() {
+            // only the rays that may hit the object are traced, like in `trace_culled`
+            active.clear();
+            active.extend((0..rays.len()).filter(|&i| {
+                let ray = &rays[i];
+                (ray.ray_type != RayType::Prime || culling.in_view(index))
+                    && object.is_visible_to(ray)
+                    && object.may_be_hit_by(ray)
+            }));
+            if active.is_empty() {
+                continue;
+            }
+            packet.clear();
+            packet.extend(active.iter().map(|&i| &rays[i]));
+
+            let hits = object.intersect_packet(&packet);
+            for (&i, hit) in active.iter().zip(hits) {
+                let hit = match hit {
+                    Some(hit) if hit.distance() > self.min_distance(&rays[i]) => hit,
+                    _ => continue,
+                };
+                if nearest[i].as_ref().is_none_or(|best| hit < *best) {
+                    nearest[i] = Some(hit);
+                }
+            }
+        }
+        nearest
+    }
+
+    /// every hit along the ray, entries and exits of all objects, nearest first
+    pub fn trace_all(&self, ray: &Ray) -> Vec<IntersectionResult> {
+        let min_distance = self.min_distance(ray);
+        let mut hits: Vec<IntersectionResult> = self
+            .objects
+            .iter()
+            .filter(|object| object.is_visible_to(ray) && object.may_be_hit_by(ray))
+            .flat_map(|object| object.intersect_all(ray))
+            .filter(|intersection| intersection.distance() > min_distance)
+            .collect();
+        hits.sort();
+        hits
+    }
+
+    /// whether anything visible to the ray lies closer than `max_distance`. stops at the first
+    /// object found, which is all shadow rays need to know.
+    pub fn occludes(&self, ray: &Ray, max_distance: f64) -> bool {
+        self.objects
+            .iter()
+            .filter(|object| object.is_visible_to(ray) && object.may_be_hit_by(ray))
+            .filter_map(|object| object.hit_distance(ray))
+            .any(|distance| distance > self.min_distance(ray) && distance < max_distance)
+    }
+
+    pub fn traversal_cost(&self, ray: &Ray) -> usize {
+        self.objects
+            .iter()
+            .map(|object| object.traversal_cost(ray))
+            .sum()
+    }
+
+    /// whether nothing the camera sees lies within the rectangle of pixels. objects without
+    /// bounds, like planes, could be anywhere
+    pub fn is_empty_in(
+        &self,
+        camera: &Camera,
+        culling: &Culling,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> bool {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|&(index, object)| object.is_visible_to_camera() && culling.in_view(index))
+            .all(|(_, object)| {
+                object
+                    .bounds()
+                    .is_some_and(|bounds| !camera.sees_in(&bounds, x, y, width, height))
+            })
+    }
+
+    /// a box around every bounded object, unbounded ones like planes are left out
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.objects
+            .iter()
+            .filter_map(|object| object.bounds())
+            .fold(None, |all, bounds| match all {
+                None => Some(bounds),
+                Some(all) => Some(all.union(&bounds)),
+            })
+    }
 }
 
+#[derive(Default)]
 pub struct SceneBuilder {
     objects: Vec<Object>,
-    lights: Vec<Light>,
+    lights: Vec<Box<dyn Light + Send + Sync>>,
+    environment: Option<EnvironmentMap>,
+    materials: MaterialLibrary,
+    /// what objects built without a material show
+    default_material: Arc<Material>,
+    hit_epsilon: f64,
 }
 
 impl SceneBuilder {
@@ -52,23 +327,408 @@ impl SceneBuilder {
         SceneBuilder {
             objects: Vec::new(),
             lights: Vec::new(),
+            environment: None,
+            materials: MaterialLibrary::new(),
+            default_material: Arc::new(Material::default()),
+            hit_epsilon: HIT_EPSILON,
         }
     }
 
     pub fn add_object(mut self, obj: Object) -> SceneBuilder {
-        self.objects.push(obj);
+        let id = self.objects.len();
+        self.objects.push(obj.with_id(id));
         self
     }
 
-    pub fn add_light(mut self, light: Light) -> SceneBuilder {
-        self.lights.push(light);
+    pub fn add_light<L: Light + Send + Sync + 'static>(mut self, light: L) -> SceneBuilder {
+        self.lights.push(Box::new(light));
+        self
+    }
+
+    /// registers a material for `ObjectBuilder::with_material_named`
+    pub fn add_material<S: Into<String>>(mut self, name: S, material: Material) -> SceneBuilder {
+        self.materials.add(name, material);
+        self
+    }
+
+    pub fn materials(&self) -> &MaterialLibrary {
+        &self.materials
+    }
+
+    /// the material of every object built without one, `Material::default` if not set.
+    /// applies to objects added before and after
+    pub fn with_default_material(mut self, material: Material) -> SceneBuilder {
+        self.default_material = Arc::new(material);
+        self
+    }
+
+    pub fn with_environment(mut self, environment: EnvironmentMap) -> SceneBuilder {
+        self.environment = Some(environment);
+        self
+    }
+
+    pub fn with_hit_epsilon(mut self, hit_epsilon: f64) -> SceneBuilder {
+        self.hit_epsilon = hit_epsilon;
         self
     }
 
     pub fn finish(self) -> Scene {
+        let default_material = self.default_material;
         Scene {
-            objects: self.objects,
+            objects: self
+                .objects
+                .into_iter()
+                .map(|object| object.with_default_material(&default_material))
+                .collect(),
             lights: self.lights,
+            environment: self.environment,
+            hit_epsilon: self.hit_epsilon,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Deg, EuclideanSpace, InnerSpace, One, Quaternion, Rotation, Rotation3};
+    use objects::{BoundingBox, Cone, ObjectBuilder, Plane, Sphere, Structure, Visibility};
+    use raycast::{Intersection, Ray, RayType};
+    use render::RenderOptions;
+    use scene::{Camera, Culling, SceneBuilder};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use types::{Direction, Point, HIT_EPSILON, SHADOW_BIAS};
+
+    /// a sphere that counts how often it is intersected
+    struct CountingSphere {
+        sphere: Sphere,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Structure for CountingSphere {
+        fn get_intersection(&self, ray: &Ray) -> Option<Intersection> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.sphere.get_intersection(ray)
+        }
+
+        fn bounds(&self) -> Option<BoundingBox> {
+            self.sphere.bounds()
+        }
+    }
+
+    #[test]
+    fn test_trace_returns_id_of_nearest_object() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -10.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .into(),
+            )
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let hit = scene.trace(&ray).unwrap();
+
+        assert_eq!(hit.object_id(), 1);
+        assert_approx_eq!(hit.distance(), 4.0);
+    }
+
+    #[test]
+    fn test_trace_returns_the_object_space_hit() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(1.0, 0.0, -10.0))
+                    .rotation(Quaternion::from_angle_y(Deg(40.0)))
+                    .scale(3.0)
+                    .unwrap()
+                    .into(),
+            )
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.2, -1.0).normalize(),
+            RayType::Prime,
+        );
+
+        let hit = scene.trace(&ray).unwrap();
+        let local = hit.object_point();
+        let rotated = Quaternion::from_angle_y(Deg(40.0)).rotate_point(*local);
+
+        assert_approx_eq!(local.to_vec().magnitude(), 1.0);
+        assert_approx_eq!(rotated.x * 3.0 + 1.0, hit.hit_point().x);
+        assert_approx_eq!(rotated.y * 3.0, hit.hit_point().y);
+        assert_approx_eq!(rotated.z * 3.0 - 10.0, hit.hit_point().z);
+    }
+
+    #[test]
+    fn test_bounding_spheres_skip_objects_far_from_the_ray() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut builder = SceneBuilder::new();
+        for i in 0..100 {
+            let (x, y) = ((i % 10) as f64 * 4.0 - 18.0, (i / 10) as f64 * 4.0 - 18.0);
+            builder = builder.add_object(
+                ObjectBuilder::create_for(CountingSphere {
+                    sphere: Sphere::create(1.0),
+                    calls: calls.clone(),
+                })
+                .at_position(Point::new(x, y, -30.0 - (i % 7) as f64))
+                .into(),
+            );
+        }
+        let scene = builder.finish();
+        let rays: Vec<Ray> = (0..400)
+            .map(|i| {
+                let (x, y) = ((i % 20) as f64 - 9.5, (i / 20) as f64 - 9.5);
+                Ray::create(
+                    Point::new(0.0, 0.0, 0.0),
+                    Direction::new(x, y, -15.0).normalize(),
+                    RayType::Prime,
+                )
+            })
+            .collect();
+
+        let traced: Vec<Option<(usize, f64)>> = rays
+            .iter()
+            .map(|ray| {
+                scene
+                    .trace(ray)
+                    .map(|hit| (hit.object_id(), hit.distance()))
+            })
+            .collect();
+        let tested = calls.swap(0, Ordering::SeqCst);
+        let exhaustive: Vec<Option<(usize, f64)>> = rays
+            .iter()
+            .map(|ray| {
+                scene
+                    .objects
+                    .iter()
+                    .filter_map(|object| object.intersect(ray))
+                    .min()
+                    .map(|hit| (hit.object_id(), hit.distance()))
+            })
+            .collect();
+
+        assert!(traced.iter().filter(|hit| hit.is_some()).count() > 10);
+        assert_eq!(traced, exhaustive);
+        assert_eq!(calls.load(Ordering::SeqCst), 100 * 400);
+        assert!(tested < 100 * 400 / 10, "{} intersections", tested);
+    }
+
+    #[test]
+    fn test_occludes_agrees_with_trace() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -2.0, 0.0))
+                    .into(),
+            )
+            .finish();
+
+        for i in 0..100 {
+            let angle = i as f64 * 0.0628;
+            let ray = Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(angle.cos(), angle.sin() * 0.5, -1.0).normalize(),
+                RayType::Shadow,
+            );
+
+            assert_eq!(
+                scene.occludes(&ray, f64::INFINITY),
+                scene.trace(&ray).is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_occludes_ignores_objects_beyond_max_distance() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .into(),
+            )
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Shadow,
+        );
+
+        assert!(scene.occludes(&ray, 4.5));
+        assert!(!scene.occludes(&ray, 3.5));
+    }
+
+    #[test]
+    fn test_trace_all_returns_hits_in_distance_order() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(2.0))
+                    .at_position(Point::new(0.0, 0.0, -7.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(2.0))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .into(),
+            )
+            .finish();
+        let ray = Ray::create(
+            Point::new(0.0, 0.0, 0.0),
+            Direction::new(0.0, 0.0, -1.0),
+            RayType::Prime,
+        );
+
+        let hits = scene.trace_all(&ray);
+        let distances: Vec<f64> = hits.iter().map(|hit| hit.distance()).collect();
+        let ids: Vec<usize> = hits.iter().map(|hit| hit.object_id()).collect();
+
+        assert_eq!(ids, vec![1, 0, 1, 0]);
+        for (distance, expected) in distances.iter().zip(&[3.0, 5.0, 7.0, 9.0]) {
+            assert_approx_eq!(distance, expected);
+        }
+        assert_eq!(hits[0], scene.trace(&ray).unwrap());
+    }
+
+    #[test]
+    fn test_biased_secondary_rays_leave_the_surface() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, 0.0, 0.0))
+                    .into(),
+            )
+            .finish();
+        let from = |height: f64| {
+            Ray::create(
+                Point::new(0.0, height, 0.0),
+                Direction::new(0.0, -1.0, 0.0),
+                RayType::Shadow,
+            )
+        };
+
+        // the default bias lies beyond the distance filter, so the hit the ray starts at
+        // is skipped while the surface is still found from just above it
+        assert_eq!(RenderOptions::default().shadow_bias, SHADOW_BIAS);
+        assert!(scene.trace(&from(HIT_EPSILON / 2.0)).is_none());
+        assert!(!scene.occludes(&from(HIT_EPSILON / 2.0), f64::INFINITY));
+        assert!(scene.trace(&from(SHADOW_BIAS)).is_some());
+        assert!(scene.occludes(&from(SHADOW_BIAS), f64::INFINITY));
+    }
+
+    #[test]
+    fn test_objects_behind_the_camera_are_culled_from_prime_rays() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, -5.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(0.0, 0.0, 5.0))
+                    .into(),
+            )
+            .finish();
+        let camera = Camera {
+            width: 64,
+            height: 48,
+            fov: 60.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        };
+        let backwards = |ray_type: RayType| {
+            Ray::create(
+                Point::new(0.0, 0.0, 0.0),
+                Direction::new(0.0, 0.0, 1.0),
+                ray_type,
+            )
+        };
+
+        assert!(camera.sees(&scene.objects[0].bounds().unwrap()));
+        assert!(!camera.sees(&scene.objects[1].bounds().unwrap()));
+        assert!(scene.trace(&backwards(RayType::Prime)).is_some());
+
+        let culling = Culling::for_camera(&scene, &camera);
+
+        assert!(scene
+            .trace_culled(&backwards(RayType::Prime), &culling)
+            .is_none());
+        assert!(scene.occludes(&backwards(RayType::Shadow), f64::INFINITY));
+        let ahead = Ray::create_prime(31.5, 23.5, &scene, &camera);
+        assert_eq!(scene.trace_culled(&ahead, &culling).unwrap().object_id(), 0);
+        // the scene itself is not changed, other rays still see everything
+        assert!(scene.trace(&backwards(RayType::Prime)).is_some());
+    }
+
+    #[test]
+    fn test_framed_camera_sees_the_whole_scene() {
+        let scene = SceneBuilder::new()
+            .add_object(
+                ObjectBuilder::create_for(Sphere::create(1.0))
+                    .at_position(Point::new(4.0, 1.0, -3.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Cone::create(1.0, 3.0))
+                    .at_position(Point::new(-2.0, -1.0, 2.0))
+                    .into(),
+            )
+            .add_object(
+                ObjectBuilder::create_for(Plane::create(Direction::new(0.0, -1.0, 0.0)))
+                    .at_position(Point::new(0.0, -1.0, 0.0))
+                    .with_visibility(Visibility {
+                        camera: false,
+                        ..Visibility::default()
+                    })
+                    .into(),
+            )
+            .finish();
+        let bounds = scene.bounds().unwrap();
+        let camera = Camera {
+            width: 64,
+            height: 48,
+            fov: 60.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_y(Deg(30.0)),
+            lens: None,
+        }
+        .frame(&bounds);
+
+        assert_approx_eq!(bounds.min.x, -3.0);
+        assert_approx_eq!(bounds.max.y, 2.0);
+        assert_approx_eq!(bounds.max.z, 3.0);
+
+        let center = Ray::create_prime(31.5, 23.5, &scene, &camera);
+        let to_center = (bounds.center() - camera.position).normalize();
+        assert_approx_eq!(center.direction.dot(to_center), 1.0);
+
+        // nothing is cut off at the image border
+        for x in 0..camera.width {
+            for &y in &[0, camera.height - 1] {
+                let ray = Ray::create_prime(x as f64, y as f64, &scene, &camera);
+                assert!(scene.trace(&ray).is_none());
+            }
+        }
+        for y in 0..camera.height {
+            for &x in &[0, camera.width - 1] {
+                let ray = Ray::create_prime(x as f64, y as f64, &scene, &camera);
+                assert!(scene.trace(&ray).is_none());
+            }
         }
     }
 }
@@ -0,0 +1,756 @@
+//! scene description files in a small subset of ron: structs with named fields, tuples,
+//! lists, maps with string keys, numbers, strings, booleans and `//` comments.
+//!
+//! ```text
+//! Scene(
+//!     camera: Camera(position: (0, 1, 5), rotation: (-10, 0, 0), fov: 60),
+//!     default_material: Diffuse(color: (0.8, 0.8, 0.8), albedo: 0.3),
+//!     materials: {"floor": Diffuse(color: (0.2, 0.3, 0.4), albedo: 0.2)},
+//!     lights: [Directional(direction: (0.25, -1, -1), color: (1, 1, 1), intensity: 20)],
+//!     objects: [
+//!         Object(shape: Plane(normal: (0, -1, 0)), material: "floor"),
+//!         Object(shape: Mesh(path: "teapot.obj", smooth: true), position: (0, 0, -6)),
+//!     ],
+//! )
+//! ```
+
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3};
+use light::{AmbientLight, DirectionalLight, ALL_LAYERS};
+use objects::{
+    Cone, Material, MaterialLibrary, Mesh, MeshOptions, Object, ObjectBuilder, Plane, Quad, Sphere,
+    Structure, Torus,
+};
+use scene::{Camera, Scene, SceneBuilder};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use types::{Color, Direction, Point, Scale};
+use wavefront_obj::obj;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    /// `(a, b, c)`
+    Tuple(Vec<Value>),
+    /// `[a, b, c]`
+    List(Vec<Value>),
+    /// `{"a": b}`
+    Map(Vec<(String, Value)>),
+    /// `Name(field: value, ..)`, or just `Name` without any fields
+    Struct(String, Vec<(String, Value)>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// skips whitespace and comments
+    fn skip(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.next();
+            } else if c == '/' && self.chars.get(self.position + 1) == Some(&'/') {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.next();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// true and skips `c` if it comes next
+    fn accept(&mut self, c: char) -> bool {
+        self.skip();
+        if self.peek() == Some(c) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.accept(c) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", c))
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|&c| c.is_alphanumeric() || c == '_') {
+            word.push(c);
+            self.next();
+        }
+        word
+    }
+
+    /// values separated by commas up to `end`, a trailing comma is allowed
+    fn sequence<T, F: FnMut(&mut Parser) -> Result<T, String>>(
+        &mut self,
+        end: char,
+        mut item: F,
+    ) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        while !self.accept(end) {
+            items.push(item(self)?);
+            if !self.accept(',') {
+                self.expect(end)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip();
+        match self.peek() {
+            Some('(') => {
+                self.next();
+                Ok(Value::Tuple(self.sequence(')', Parser::value)?))
+            }
+            Some('[') => {
+                self.next();
+                Ok(Value::List(self.sequence(']', Parser::value)?))
+            }
+            Some('{') => {
+                self.next();
+                let entries = self.sequence('}', |parser| {
+                    let key = match parser.value()? {
+                        Value::Text(key) => key,
+                        _ => return parser.error("map keys have to be strings"),
+                    };
+                    parser.expect(':')?;
+                    Ok((key, parser.value()?))
+                })?;
+                Ok(Value::Map(entries))
+            }
+            Some('"') => self.text(),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => {
+                let name = self.word();
+                match name.as_str() {
+                    "true" => return Ok(Value::Bool(true)),
+                    "false" => return Ok(Value::Bool(false)),
+                    _ => {}
+                }
+                let fields = if self.accept('(') {
+                    self.sequence(')', |parser| {
+                        parser.skip();
+                        let field = parser.word();
+                        if field.is_empty() {
+                            return parser.error("expected a field name");
+                        }
+                        parser.expect(':')?;
+                        Ok((field, parser.value()?))
+                    })?
+                } else {
+                    Vec::new()
+                };
+                Ok(Value::Struct(name, fields))
+            }
+            Some(c) => self.error(&format!("unexpected '{}'", c)),
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|&c| c.is_ascii_digit() || "+-.eE".contains(c))
+        {
+            text.push(c);
+            self.next();
+        }
+        match text.parse() {
+            Ok(number) => Ok(Value::Number(number)),
+            Err(_) => self.error(&format!("invalid number {}", text)),
+        }
+    }
+
+    fn text(&mut self) -> Result<Value, String> {
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(Value::Text(text)),
+                Some('\\') => match self.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(c) => text.push(c),
+                    None => return self.error("unterminated string"),
+                },
+                Some(c) => text.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        line: 1,
+    };
+    let value = parser.value()?;
+    parser.skip();
+    if parser.peek().is_some() {
+        return parser.error("expected the end of the file");
+    }
+    Ok(value)
+}
+
+/// the fields of a struct value. fields it doesn't know are an error, to catch typos
+struct Fields<'a> {
+    name: &'a str,
+    fields: &'a [(String, Value)],
+}
+
+impl<'a> Fields<'a> {
+    fn of(value: &'a Value, known: &[&str]) -> Result<Fields<'a>, String> {
+        let (name, fields) = match *value {
+            Value::Struct(ref name, ref fields) => (name.as_str(), fields.as_slice()),
+            _ => return Err(format!("expected a struct, found {:?}", value)),
+        };
+        for (index, (field, _)) in fields.iter().enumerate() {
+            if !known.contains(&field.as_str()) {
+                return Err(format!("{} has no field {}", name, field));
+            }
+            if fields[..index].iter().any(|(other, _)| other == field) {
+                return Err(format!("{} sets {} twice", name, field));
+            }
+        }
+        Ok(Fields { name, fields })
+    }
+
+    fn get(&self, field: &str) -> Option<&'a Value> {
+        self.fields
+            .iter()
+            .find(|&(name, _)| name == field)
+            .map(|(_, value)| value)
+    }
+
+    fn required(&self, field: &str) -> Result<&'a Value, String> {
+        self.get(field)
+            .ok_or_else(|| format!("{} needs a {}", self.name, field))
+    }
+
+    /// converts the field with `convert`, `default` if it isn't set
+    fn or<T, F: Fn(&'a Value) -> Result<T, String>>(
+        &self,
+        field: &str,
+        default: T,
+        convert: F,
+    ) -> Result<T, String> {
+        match self.get(field) {
+            Some(value) => self.convert(field, value, convert),
+            None => Ok(default),
+        }
+    }
+
+    fn with<T, F: Fn(&'a Value) -> Result<T, String>>(
+        &self,
+        field: &str,
+        convert: F,
+    ) -> Result<T, String> {
+        self.convert(field, self.required(field)?, convert)
+    }
+
+    fn convert<T, F: Fn(&'a Value) -> Result<T, String>>(
+        &self,
+        field: &str,
+        value: &'a Value,
+        convert: F,
+    ) -> Result<T, String> {
+        convert(value).map_err(|err| format!("{}.{}: {}", self.name, field, err))
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match *value {
+        Value::Number(number) => Ok(number),
+        _ => Err(format!("expected a number, found {:?}", value)),
+    }
+}
+
+fn float(value: &Value) -> Result<f32, String> {
+    number(value).map(|number| number as f32)
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    match *value {
+        Value::Bool(flag) => Ok(flag),
+        _ => Err(format!("expected true or false, found {:?}", value)),
+    }
+}
+
+fn text(value: &Value) -> Result<&str, String> {
+    match *value {
+        Value::Text(ref text) => Ok(text),
+        _ => Err(format!("expected a string, found {:?}", value)),
+    }
+}
+
+fn list(value: &Value) -> Result<&[Value], String> {
+    match *value {
+        Value::List(ref items) => Ok(items),
+        _ => Err(format!("expected a list, found {:?}", value)),
+    }
+}
+
+fn triple(value: &Value) -> Result<(f64, f64, f64), String> {
+    match *value {
+        Value::Tuple(ref items) if items.len() == 3 => {
+            Ok((number(&items[0])?, number(&items[1])?, number(&items[2])?))
+        }
+        _ => Err(format!("expected three numbers, found {:?}", value)),
+    }
+}
+
+fn point(value: &Value) -> Result<Point, String> {
+    triple(value).map(|(x, y, z)| Point::new(x, y, z))
+}
+
+fn direction(value: &Value) -> Result<Direction, String> {
+    let (x, y, z) = triple(value)?;
+    let direction = Direction::new(x, y, z);
+    if direction.magnitude2() == 0.0 {
+        return Err(String::from("a direction can't be zero"));
+    }
+    Ok(direction.normalize())
+}
+
+fn color(value: &Value) -> Result<Color, String> {
+    triple(value).map(|(r, g, b)| Color::from_rgb(r as f32, g as f32, b as f32))
+}
+
+/// one factor for every axis, or three
+fn scale(value: &Value) -> Result<Scale, String> {
+    match *value {
+        Value::Number(factor) => Ok(Scale::new(factor, factor, factor)),
+        _ => triple(value).map(|(x, y, z)| Scale::new(x, y, z)),
+    }
+}
+
+/// degrees around x, then y, then z, like `ObjectBuilder::rotation_euler`
+fn rotation(value: &Value) -> Result<Quaternion<f64>, String> {
+    let (x, y, z) = triple(value)?;
+    Ok(Quaternion::from_angle_z(Deg(z))
+        * Quaternion::from_angle_y(Deg(y))
+        * Quaternion::from_angle_x(Deg(x)))
+}
+
+fn material(value: &Value, base: &Path) -> Result<Material, String> {
+    let name = match *value {
+        Value::Struct(ref name, _) => name.as_str(),
+        _ => return Err(format!("expected a material, found {:?}", value)),
+    };
+    let known: &[&str] = match name {
+        "Diffuse" => &["color", "albedo"],
+        "Reflective" | "Metallic" => &["color", "albedo", "reflectivity"],
+        "Refractive" => &["color", "albedo", "index", "transparency", "absorption"],
+        "Emissive" => &["color", "intensity"],
+        "ShadowCatcher" => &[],
+        "Texture" => &["path", "albedo"],
+        _ => return Err(format!("unknown material {}", name)),
+    };
+    let fields = Fields::of(value, known)?;
+    let albedo = || fields.or("albedo", 0.1, float);
+    Ok(match name {
+        "Diffuse" => Material::diffuse_color(fields.with("color", color)?, albedo()?),
+        "Reflective" => Material::reflective_color(
+            fields.with("color", color)?,
+            albedo()?,
+            fields.with("reflectivity", float)?,
+        ),
+        "Metallic" => Material::metallic_color(
+            fields.with("color", color)?,
+            albedo()?,
+            fields.with("reflectivity", float)?,
+        ),
+        "Refractive" => Material::refractive_color(
+            fields.or("color", Color::from_rgb(1.0, 1.0, 1.0), color)?,
+            albedo()?,
+            fields.with("index", float)?,
+            fields.or("transparency", 1.0, float)?,
+            fields.or("absorption", Color::from_rgb(0.0, 0.0, 0.0), color)?,
+        ),
+        "Emissive" => Material::emissive(
+            fields.with("color", color)?,
+            fields.with("intensity", float)?,
+        ),
+        "ShadowCatcher" => Material::shadow_catcher(),
+        _ => {
+            let path = base.join(fields.with("path", text)?);
+            Material::textured_from_path(&path, albedo()?)
+                .map_err(|err| format!("could not read {}: {}", path.display(), err))?
+        }
+    })
+}
+
+/// the first object of an obj file, or the object or group called `object`
+fn mesh(fields: &Fields, base: &Path) -> Result<Mesh, String> {
+    let path = base.join(fields.with("path", text)?);
+    let mut source = String::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    let set = obj::parse(source).map_err(|err| format!("{}: {:?}", path.display(), err))?;
+    let options = MeshOptions {
+        smooth_normals: fields.or("smooth", false, boolean)?,
+        ..MeshOptions::default()
+    };
+
+    match fields.get("object") {
+        Some(name) => {
+            let name = fields.convert("object", name, text)?;
+            Mesh::from_obj_named(&set, name, &options)
+                .ok_or_else(|| format!("{} has no object {:?}", path.display(), name))
+        }
+        None => Mesh::from_obj_all(&set, &options)
+            .into_iter()
+            .next()
+            .map(|(_, mesh)| mesh)
+            .ok_or_else(|| format!("{} has no faces", path.display())),
+    }
+}
+
+/// the builder of `object` with its position, rotation, scale and material
+fn place<E: Structure + Send + Sync + 'static>(
+    shape: E,
+    fields: &Fields,
+    materials: &MaterialLibrary,
+    base: &Path,
+) -> Result<Object, String> {
+    let mut builder = ObjectBuilder::create_for(shape)
+        .at_position(fields.or("position", Point::new(0.0, 0.0, 0.0), point)?)
+        .scale_axes(fields.or("scale", Scale::new(1.0, 1.0, 1.0), scale)?)
+        .map_err(|err| format!("{}.scale: {}", fields.name, err))?;
+    if let Some(value) = fields.get("rotation") {
+        builder = builder.rotation(fields.convert("rotation", value, rotation)?);
+    }
+    builder = match fields.get("material") {
+        Some(Value::Text(name)) => builder.with_material_named(materials, name)?,
+        Some(value) => builder.with_material(material(value, base)?),
+        None => builder,
+    };
+    Ok(builder.into())
+}
+
+fn object(value: &Value, materials: &MaterialLibrary, base: &Path) -> Result<Object, String> {
+    let fields = Fields::of(
+        value,
+        &["shape", "position", "rotation", "scale", "material"],
+    )?;
+    let shape = fields.required("shape")?;
+    let name = match *shape {
+        Value::Struct(ref name, _) => name.as_str(),
+        _ => return Err(format!("expected a shape, found {:?}", shape)),
+    };
+    let known: &[&str] = match name {
+        "Sphere" => &["radius"],
+        "Plane" => &["normal"],
+        "Quad" => &["width", "depth"],
+        "Cone" => &["radius", "height"],
+        "Torus" => &["major_radius", "minor_radius"],
+        "Mesh" => &["path", "object", "smooth"],
+        _ => return Err(format!("unknown shape {}", name)),
+    };
+    let shape = Fields::of(shape, known)?;
+    match name {
+        "Sphere" => place(
+            Sphere::create(shape.with("radius", number)?),
+            &fields,
+            materials,
+            base,
+        ),
+        "Plane" => place(
+            Plane::create(shape.with("normal", direction)?),
+            &fields,
+            materials,
+            base,
+        ),
+        "Quad" => place(
+            Quad::create(shape.with("width", number)?, shape.with("depth", number)?),
+            &fields,
+            materials,
+            base,
+        ),
+        "Cone" => place(
+            Cone::create(shape.with("radius", number)?, shape.with("height", number)?),
+            &fields,
+            materials,
+            base,
+        ),
+        "Torus" => place(
+            Torus::create(
+                shape.with("major_radius", number)?,
+                shape.with("minor_radius", number)?,
+            ),
+            &fields,
+            materials,
+            base,
+        ),
+        _ => place(mesh(&shape, base)?, &fields, materials, base),
+    }
+}
+
+fn add_light(builder: SceneBuilder, value: &Value) -> Result<SceneBuilder, String> {
+    let name = match *value {
+        Value::Struct(ref name, _) => name.as_str(),
+        _ => return Err(format!("expected a light, found {:?}", value)),
+    };
+    match name {
+        "Directional" => {
+            let fields = Fields::of(
+                value,
+                &["direction", "color", "intensity", "shadows", "softness"],
+            )?;
+            Ok(builder.add_light(DirectionalLight {
+                direction: fields.with("direction", direction)?,
+                color: fields.or("color", Color::from_rgb(1.0, 1.0, 1.0), color)?,
+                intensity: fields.or("intensity", 1.0, float)?,
+                casts_shadows: fields.or("shadows", true, boolean)?,
+                softness: fields.or("softness", 0.0, number)?,
+                layers: ALL_LAYERS,
+            }))
+        }
+        "Ambient" => {
+            let fields = Fields::of(value, &["color", "intensity"])?;
+            Ok(builder.add_light(AmbientLight {
+                color: fields.or("color", Color::from_rgb(1.0, 1.0, 1.0), color)?,
+                intensity: fields.or("intensity", 1.0, float)?,
+            }))
+        }
+        _ => Err(format!("unknown light {}", name)),
+    }
+}
+
+/// the scene described by `source` and the camera it is seen through. `camera` gives the
+/// image size and whatever the file's own camera leaves out. paths of models and textures
+/// are relative to `base`
+pub fn parse_scene(source: &str, base: &Path, camera: Camera) -> Result<(Scene, Camera), String> {
+    let root = parse(source)?;
+    if let Value::Struct(ref name, _) = root {
+        if name != "Scene" {
+            return Err(format!("expected a Scene, found {}", name));
+        }
+    }
+    let fields = Fields::of(
+        &root,
+        &[
+            "camera",
+            "default_material",
+            "materials",
+            "lights",
+            "objects",
+        ],
+    )?;
+    let mut builder = SceneBuilder::new();
+
+    if let Some(value) = fields.get("default_material") {
+        builder = builder.with_default_material(
+            material(value, base).map_err(|err| format!("default_material: {}", err))?,
+        );
+    }
+    match fields.get("materials") {
+        Some(Value::Map(entries)) => {
+            for (name, value) in entries {
+                let material = material(value, base).map_err(|err| format!("{}: {}", name, err))?;
+                builder = builder.add_material(name.as_str(), material);
+            }
+        }
+        Some(value) => return Err(format!("expected a map of materials, found {:?}", value)),
+        None => {}
+    }
+    for light in fields.or("lights", &[][..], list)? {
+        builder = add_light(builder, light)?;
+    }
+    for value in fields.or("objects", &[][..], list)? {
+        let object = object(value, builder.materials(), base)?;
+        builder = builder.add_object(object);
+    }
+
+    let mut camera = camera;
+    if let Some(value) = fields.get("camera") {
+        let placement = Fields::of(value, &["position", "rotation", "fov"])?;
+        camera.position = placement.or("position", camera.position, point)?;
+        camera.rotation = placement.or("rotation", camera.rotation, rotation)?;
+        camera.fov = placement.or("fov", camera.fov, number)?;
+    }
+
+    Ok((builder.finish(), camera))
+}
+
+/// reads a scene description file, see `parse_scene`. paths in it are relative to the file
+pub fn read_scene<P: AsRef<Path>>(path: P, camera: Camera) -> Result<(Scene, Camera), String> {
+    let path = path.as_ref();
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&source, base, camera).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{One, Quaternion};
+    use scene::Camera;
+    use scene_file::{parse, parse_scene, Value};
+    use std::path::Path;
+    use types::Point;
+
+    fn camera() -> Camera {
+        Camera {
+            width: 40,
+            height: 30,
+            fov: 90.0,
+            position: Point::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            lens: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let value =
+            parse("Object(a: (1, -2.5e1), b: [\"x\\\"y\", true], c: {\"k\": Unit},)").unwrap();
+
+        assert_eq!(
+            value,
+            Value::Struct(
+                String::from("Object"),
+                vec![
+                    (
+                        String::from("a"),
+                        Value::Tuple(vec![Value::Number(1.0), Value::Number(-25.0)])
+                    ),
+                    (
+                        String::from("b"),
+                        Value::List(vec![Value::Text(String::from("x\"y")), Value::Bool(true)])
+                    ),
+                    (
+                        String::from("c"),
+                        Value::Map(vec![(
+                            String::from("k"),
+                            Value::Struct(String::from("Unit"), vec![])
+                        )])
+                    ),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_bundled_scene_builds() {
+        let (scene, camera) = parse_scene(
+            include_str!("../scene.ron"),
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            camera(),
+        )
+        .unwrap();
+
+        assert_eq!(scene.objects.len(), 3);
+        assert_eq!(scene.lights.len(), 2);
+        assert_eq!(camera.width, 40);
+        assert_approx_eq!(camera.fov, 90.0);
+    }
+
+    #[test]
+    fn test_the_file_places_the_camera() {
+        let source = "Scene(camera: Camera(position: (0, 1, 5), rotation: (0, 90, 0), fov: 60))";
+        let (scene, camera) = parse_scene(source, Path::new("."), camera()).unwrap();
+
+        assert!(scene.objects.is_empty());
+        assert_eq!(camera.height, 30);
+        assert_approx_eq!(camera.position.y, 1.0);
+        assert_approx_eq!(camera.fov, 60.0);
+        // turned left, the camera looks along -x
+        assert_approx_eq!(camera.forward().x, -1.0);
+    }
+
+    #[test]
+    fn test_shapes_and_materials_are_read() {
+        let source = "Scene(
+            materials: {\"glass\": Refractive(albedo: 0.2, index: 1.5)},
+            objects: [
+                Object(shape: Sphere(radius: 2), material: \"glass\", scale: (1, 2, 1)),
+                Object(shape: Torus(major_radius: 1, minor_radius: 0.25), rotation: (90, 0, 0)),
+                Object(shape: Cone(radius: 1, height: 2), material: ShadowCatcher),
+                Object(shape: Quad(width: 2, depth: 3), material: Emissive(color: (1, 1, 1), intensity: 4)),
+                Object(shape: Sphere(radius: 1), position: (0, 0, -5)),
+            ],
+            default_material: Emissive(color: (1, 0, 0), intensity: 1),
+            lights: [Ambient(intensity: 0.1), Directional(direction: (0, -1, 0), shadows: false)],
+        )";
+        let (scene, _) = parse_scene(source, Path::new("."), camera()).unwrap();
+
+        assert_eq!(scene.objects.len(), 5);
+        assert_eq!(scene.lights.len(), 2);
+        assert!(!scene.lights[1].casts_shadows());
+        assert_approx_eq!(scene.objects[0].position().scale.y, 2.0);
+        let sphere = scene.objects[0].bounds().unwrap();
+        assert_approx_eq!(sphere.max.y, 4.0);
+        assert!(scene.objects[3].is_emissive());
+        assert!(!scene.objects[0].is_emissive());
+        assert!(scene.objects[1].is_emissive());
+        assert_approx_eq!(scene.objects[4].emission().red, 1.0);
+    }
+
+    #[test]
+    fn test_errors_name_the_problem() {
+        let error = |source: &str| parse_scene(source, Path::new("."), camera()).err().unwrap();
+
+        assert!(
+            error("Scene(\n  objects: [\n    Object(shape: Sphere(radius: 1)").contains("line 3")
+        );
+        assert!(
+            error("Scene(objects: [Object(shape: Sphere(radius: \"big\"))])")
+                .contains("Sphere.radius")
+        );
+        assert!(
+            error("Scene(objects: [Object(shape: Sphere(radius: 1), colour: 1)])")
+                .contains("no field colour")
+        );
+        assert!(error("Scene(objects: [Object(shape: Cube(size: 1))])").contains("Cube"));
+        assert!(
+            error("Scene(objects: [Object(shape: Sphere(radius: 1), scale: 0)])")
+                .contains("Object.scale")
+        );
+        assert!(
+            error("Scene(objects: [Object(shape: Sphere(radius: 1), material: \"x\")])")
+                .contains("no material named")
+        );
+        assert!(
+            error("Scene(objects: [Object(shape: Mesh(path: \"missing.obj\"))])")
+                .contains("could not read")
+        );
+    }
+}
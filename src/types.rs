@@ -1,49 +1,144 @@
-use cgmath::{Point3, Vector3};
-use image::Rgba;
+use cgmath::{InnerSpace, Point3, Vector3};
+use color_space::{color_linear_to_srgb, color_srgb_to_linear, parse_hex, srgb_to_linear};
+use image::{Luma, Pixel, Rgba};
 use std::ops::{Add, Mul};
 
+/// a linear rgb color with premultiplied alpha, all arithmetic works on the four channels alike
 #[derive(Debug, Copy, Clone)]
 pub struct Color {
     pub red: f32,
     pub green: f32,
     pub blue: f32,
+    pub alpha: f32,
 }
 
 impl Color {
     pub fn from_rgb(r: f32, g: f32, b: f32) -> Color {
+        Color::from_rgba(r, g, b, 1.0)
+    }
+
+    /// `r`, `g` and `b` are expected to be premultiplied by `a` already
+    pub fn from_rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
         Color {
             red: r,
             green: g,
             blue: b,
+            alpha: a,
         }
     }
 
-    pub fn from_rgba(source: Rgba<u8>) -> Color {
+    pub fn from_rgba8(source: Rgba<u8>) -> Color {
+        let alpha = source.data[3] as f32 / 255.0;
         Color {
-            red: source.data[0] as f32 / 255.0,
-            green: source.data[1] as f32 / 255.0,
-            blue: source.data[2] as f32 / 255.0,
+            red: source.data[0] as f32 / 255.0 * alpha,
+            green: source.data[1] as f32 / 255.0 * alpha,
+            blue: source.data[2] as f32 / 255.0 * alpha,
+            alpha,
         }
     }
 
+    /// a gray of the same value in every channel
+    pub fn from_luma8(source: Luma<u8>) -> Color {
+        let value = source.data[0] as f32 / 255.0;
+        Color::from_rgb(value, value, value)
+    }
+
+    /// reads any 8 bit pixel by its channel layout. gray pixels, with or without alpha, are
+    /// spread over the three color channels, everything else is reordered to rgba first
+    pub fn from_pixel<P: Pixel<Subpixel = u8>>(source: &P) -> Color {
+        let channels = source.channels();
+        match P::color_model() {
+            "Y" => Color::from_luma8(Luma([channels[0]])),
+            "YA" => Color::from_rgba8(Rgba([channels[0], channels[0], channels[0], channels[1]])),
+            _ => Color::from_rgba8(source.to_rgba()),
+        }
+    }
+
+    /// the color of a black body glowing at `kelvin`, e.g. 2700 for a light bulb or 6500 for
+    /// daylight. uses tanner helland's fit of the planckian locus in srgb, decoded to linear,
+    /// which holds from 1000 to 40000 kelvin
+    pub fn from_kelvin(kelvin: f32) -> Color {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_73 * (t - 60.0).powf(-0.133_204_76)
+        };
+        let green = if t <= 66.0 {
+            99.470_8 * t.ln() - 161.119_57
+        } else {
+            288.122_17 * (t - 60.0).powf(-0.075_514_85)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * (t - 10.0).ln() - 305.044_8
+        };
+        let linear = |srgb: f32| srgb_to_linear((srgb / 255.0).clamp(0.0, 1.0));
+
+        Color::from_rgb(linear(red), linear(green), linear(blue))
+    }
+
+    /// the linear color of one whose channels are srgb encoded, as 8 bit images store them.
+    /// alpha stays linear
+    pub fn decode_srgb(&self) -> Color {
+        color_srgb_to_linear(*self)
+    }
+
+    /// the srgb encoded color of a linear one, as 8 bit images are shown. alpha stays linear
+    pub fn encode_srgb(&self) -> Color {
+        color_linear_to_srgb(*self)
+    }
+
+    /// a color picked as a `#rrggbb` or `#rrggbbaa` hex code, decoded to linear
+    pub fn from_hex(hex: &str) -> Result<Color, String> {
+        parse_hex(hex)
+    }
+
+    /// converts back to straight alpha, as images store it
     pub fn to_rgba8(&self) -> Rgba<u8> {
+        let alpha = self.alpha.clamp(0.0, 1.0);
+        let straight = |channel: f32| {
+            if alpha > 0.0 {
+                (channel / alpha * 255.0).round() as u8
+            } else {
+                0
+            }
+        };
         Rgba {
             data: [
-                (self.red * 255.0).round() as u8,
-                (self.green * 255.0).round() as u8,
-                (self.blue * 255.0).round() as u8,
-                0,
+                straight(self.red),
+                straight(self.green),
+                straight(self.blue),
+                (alpha * 255.0).round() as u8,
             ],
         }
     }
 
+    /// limits every channel to [0, 1]. nan and infinite channels, left by degenerate geometry,
+    /// become 0 so a single bad ray can't turn into an arbitrary pixel value.
     pub fn clamp(&self) -> Color {
+        let unit = |channel: f32| {
+            if channel.is_finite() {
+                channel.clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
         Color {
-            red: self.red.min(1.0).max(0.0),
-            blue: self.blue.min(1.0).max(0.0),
-            green: self.green.min(1.0).max(0.0),
+            red: unit(self.red),
+            blue: unit(self.blue),
+            green: unit(self.green),
+            alpha: unit(self.alpha),
         }
     }
+
+    /// composites this color on top of `background`
+    pub fn over(self, background: Color) -> Color {
+        self + background * (1.0 - self.alpha.clamp(0.0, 1.0))
+    }
 }
 
 impl Mul for Color {
@@ -54,6 +149,7 @@ impl Mul for Color {
             red: self.red * other.red,
             blue: self.blue * other.blue,
             green: self.green * other.green,
+            alpha: self.alpha * other.alpha,
         }
     }
 }
@@ -66,6 +162,7 @@ impl Mul<f32> for Color {
             red: self.red * other,
             blue: self.blue * other,
             green: self.green * other,
+            alpha: self.alpha * other,
         }
     }
 }
@@ -78,11 +175,203 @@ impl Add for Color {
             red: self.red + other.red,
             blue: self.blue + other.blue,
             green: self.green + other.green,
+            alpha: self.alpha + other.alpha,
+        }
+    }
+}
+
+/// a weighted running sum of colors, kept in f64 so the samples of a pixel are averaged
+/// before anything is rounded to 8 bits
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ColorSum {
+    red: f64,
+    green: f64,
+    blue: f64,
+    alpha: f64,
+    weight: f64,
+}
+
+impl ColorSum {
+    pub fn add(&mut self, color: Color, weight: f64) {
+        self.red += color.red as f64 * weight;
+        self.green += color.green as f64 * weight;
+        self.blue += color.blue as f64 * weight;
+        self.alpha += color.alpha as f64 * weight;
+        self.weight += weight;
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// the weighted average, `None` while nothing with a positive weight was added
+    pub fn mean(&self) -> Option<Color> {
+        if self.weight <= 0.0 {
+            return None;
         }
+        Some(Color {
+            red: (self.red / self.weight) as f32,
+            green: (self.green / self.weight) as f32,
+            blue: (self.blue / self.weight) as f32,
+            alpha: (self.alpha / self.weight) as f32,
+        })
     }
 }
 
+/// hits closer than this to a ray's origin are ignored, they are the surface the ray just
+/// left. relative to the origin's distance from the world origin, like `SHADOW_BIAS`, and
+/// it has to stay below it, or secondary rays get lost in thin geometry.
+pub const HIT_EPSILON: f64 = 1e-13;
+/// the default distance secondary rays start off a surface, relative to the hit point's
+/// distance from the origin. larger values fight shadow acne on big scenes.
+pub const SHADOW_BIAS: f64 = 1e-9;
+/// a ray whose direction has a cosine below this with a surface's normal runs parallel to it
+pub const PARALLEL_EPSILON: f64 = 1e-12;
+/// triangles with a smaller möller-trumbore determinant are seen edge-on and missed. relative
+/// to the product of the triangle's edge lengths, so tiny triangles are not lost
+pub const DETERMINANT_EPSILON: f64 = 1e-13;
+
 pub type Point = Point3<f64>;
 
-pub type Scale = f64;
+/// factors along the object's own x, y and z axes
+pub type Scale = Vector3<f64>;
 pub type Direction = Vector3<f64>;
+
+/// builds two unit vectors that together with `normal` form an orthonormal basis.
+///
+/// uses the branchless construction from duff et al., "building an orthonormal basis,
+/// revisited", which is stable for every unit normal including the poles.
+pub fn orthonormal_basis(normal: &Direction) -> (Direction, Direction) {
+    let n = normal.normalize();
+    let sign = 1.0f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    let tangent = Direction::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let bitangent = Direction::new(b, sign + n.y * n.y * a, -n.y);
+
+    (tangent, bitangent)
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::InnerSpace;
+    use color_space::srgb_to_linear;
+    use image::{Luma, LumaA, Rgb, Rgba};
+    use types::{orthonormal_basis, Color, Direction};
+
+    #[test]
+    fn test_srgb_texel_decodes_to_linear() {
+        let texel = Color::from_rgba8(Rgba([188, 188, 188, 255]));
+        let translucent = Color::from_rgba8(Rgba([188, 0, 255, 128])).decode_srgb();
+
+        assert_approx_eq!(texel.red, 0.737, 1e-3);
+        assert_approx_eq!(texel.decode_srgb().red, 0.503, 1e-3);
+        assert_approx_eq!(translucent.red / translucent.alpha, 0.503, 1e-3);
+        assert_approx_eq!(translucent.blue / translucent.alpha, 1.0);
+        assert_approx_eq!(srgb_to_linear(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_kelvin_runs_from_orange_to_white() {
+        let daylight = Color::from_kelvin(6500.0);
+        let candle = Color::from_kelvin(2000.0);
+
+        assert!(daylight.red > 0.95 && daylight.green > 0.95 && daylight.blue > 0.9);
+        assert_approx_eq!(candle.red, 1.0);
+        assert!(candle.green < 0.4 * candle.red);
+        assert!(candle.blue < 0.1 * candle.red);
+    }
+
+    #[test]
+    fn test_orthonormal_basis_is_orthonormal() {
+        let normals = vec![
+            Direction::new(0.0, 0.0, 1.0),
+            Direction::new(0.0, 0.0, -1.0),
+            Direction::new(0.0, 1.0, 0.0),
+            Direction::new(1.0, 0.0, 0.0),
+            Direction::new(1.0, 2.0, -3.0).normalize(),
+            Direction::new(-0.2, 0.1, -0.97).normalize(),
+        ];
+
+        for normal in normals {
+            let (tangent, bitangent) = orthonormal_basis(&normal);
+
+            assert_approx_eq!(tangent.magnitude(), 1.0);
+            assert_approx_eq!(bitangent.magnitude(), 1.0);
+            assert_approx_eq!(tangent.dot(bitangent), 0.0);
+            assert_approx_eq!(tangent.dot(normal), 0.0);
+            assert_approx_eq!(bitangent.dot(normal), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_half_transparent_color_over_opaque_blends() {
+        let red = Color::from_rgba(0.5, 0.0, 0.0, 0.5);
+        let blue = Color::from_rgb(0.0, 0.0, 1.0);
+
+        let blended = red.over(blue);
+
+        assert_approx_eq!(blended.red, 0.5);
+        assert_approx_eq!(blended.green, 0.0);
+        assert_approx_eq!(blended.blue, 0.5);
+        assert_approx_eq!(blended.alpha, 1.0);
+    }
+
+    #[test]
+    fn test_alpha_takes_part_in_arithmetic() {
+        let color = Color::from_rgba(0.2, 0.4, 0.6, 0.5);
+
+        let scaled = color * 0.5;
+        let filtered = color * Color::from_rgba(1.0, 1.0, 1.0, 0.5);
+        let sum = color + color;
+
+        assert_approx_eq!(scaled.alpha, 0.25);
+        assert_approx_eq!(filtered.alpha, 0.25);
+        assert_approx_eq!(sum.alpha, 1.0);
+    }
+
+    #[test]
+    fn test_rgba8_round_trip_keeps_straight_alpha() {
+        let pixel = Rgba([200, 100, 50, 128]);
+
+        let color = Color::from_rgba8(pixel);
+
+        assert_approx_eq!(color.alpha, 128.0 / 255.0);
+        assert_approx_eq!(color.red, 200.0 / 255.0 * color.alpha);
+        assert_eq!(color.to_rgba8(), pixel);
+        assert_eq!(
+            Color::from_rgb(1.0, 0.5, 0.0).to_rgba8(),
+            Rgba([255, 128, 0, 255])
+        );
+    }
+
+    #[test]
+    fn test_gray_pixels_spread_over_every_channel() {
+        let gray = Color::from_pixel(&Luma([77]));
+        let translucent = Color::from_pixel(&LumaA([77, 51]));
+
+        assert_approx_eq!(gray.red, 77.0 / 255.0);
+        assert_eq!(gray.red, gray.green);
+        assert_eq!(gray.red, gray.blue);
+        assert_eq!(gray.alpha, 1.0);
+        assert_eq!(translucent.to_rgba8(), Rgba([77, 77, 77, 51]));
+        assert_eq!(
+            Color::from_pixel(&Rgb([200, 100, 50])).to_rgba8(),
+            Rgba([200, 100, 50, 255])
+        );
+    }
+
+    #[test]
+    fn test_clamp_replaces_non_finite_channels() {
+        let broken = Color::from_rgb(f32::NAN, f32::INFINITY, 0.5);
+
+        let pixel = broken.clamp().to_rgba8();
+
+        assert_eq!(pixel, Rgba([0, 0, 128, 255]));
+        assert_eq!(
+            Color::from_rgba(0.5, 0.5, 0.5, f32::NAN).clamp().to_rgba8(),
+            Rgba([0, 0, 0, 0])
+        );
+    }
+}